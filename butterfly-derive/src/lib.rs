@@ -0,0 +1,292 @@
+//! Derive macros for [butterfly](https://docs.rs/butterfly-fuzz)
+//!
+//! Don't use this crate directly, enable the `derive` feature of butterfly instead
+//! and use `butterfly::PacketMutations`.
+
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use proc_macro_crate::{crate_name, FoundCrate};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, spanned::Spanned, Attribute, Data, DeriveInput, Error, Fields, Ident, Member, Path, Type};
+
+/// Generates the impls of `HasHavocMutation`, `HasSpliceMutation`, `HasCrossoverInsertMutation`,
+/// `HasCrossoverReplaceMutation`, `HasSplitMutation`, `HasMergeMutation` and `HasPacketKind` for a packet type.
+///
+/// Every mutation is delegated to one randomly selected field of the packet.
+/// The fields must implement the respective traits themselves, like
+/// `BytesInput`, `Option<BytesInput>` or other packet types that use this derive.
+/// Mutations that get a second packet are only applied if both packets
+/// are the same enum variant, else they are skipped.
+///
+/// # Attributes
+/// - `#[butterfly(immutable)]` on a field excludes it from all mutations.
+///   The field doesn't have to implement any of the traits.
+/// - `#[butterfly(skip)]` on an enum variant excludes the whole variant from all mutations.
+///
+/// Variants without any mutable fields are never mutated.
+///
+/// The packet kind of an enum is the index of its variant, so reordering the variants
+/// invalidates the statistics that a `PotencyMutationScheduler` stored in the state.
+/// Structs always have kind 0.
+///
+/// # Example
+/// ```
+/// #[derive(PacketMutations)]
+/// enum FTPCommand {
+///     USER(BytesInput),
+///     PASS(BytesInput),
+///     PORT(#[butterfly(immutable)] u16, BytesInput),
+///     LIST(Option<BytesInput>),
+///     #[butterfly(skip)]
+///     QUIT,
+/// }
+/// ```
+#[proc_macro_derive(PacketMutations, attributes(butterfly))]
+pub fn derive_packet_mutations(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// A struct or an enum variant whose fields can be mutated
+struct Variant {
+    path: Path,
+    skip: bool,
+    fields: Vec<MutableField>,
+}
+
+struct MutableField {
+    member: Member,
+    ty: Type,
+}
+
+/// Parse all `#[butterfly(...)]` attributes and check that only `allowed` is used
+fn has_flag(attrs: &[Attribute], allowed: &str) -> Result<bool, Error> {
+    let mut found = false;
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("butterfly")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(allowed) {
+                found = true;
+                Ok(())
+            } else {
+                Err(meta.error(format!("unsupported butterfly attribute, expected `{}`", allowed)))
+            }
+        })?;
+    }
+
+    Ok(found)
+}
+
+fn parse_fields(fields: &Fields) -> Result<Vec<MutableField>, Error> {
+    let mut ret = Vec::new();
+
+    for (index, field) in fields.iter().enumerate() {
+        if has_flag(&field.attrs, "immutable")? {
+            continue;
+        }
+
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(index.into()),
+        };
+
+        ret.push(MutableField {
+            member,
+            ty: field.ty.clone(),
+        });
+    }
+
+    Ok(ret)
+}
+
+fn parse_variants(input: &DeriveInput) -> Result<Vec<Variant>, Error> {
+    match &input.data {
+        Data::Struct(data) => Ok(vec![Variant {
+            path: parse_quote!(Self),
+            skip: false,
+            fields: parse_fields(&data.fields)?,
+        }]),
+        Data::Enum(data) => {
+            let mut ret = Vec::new();
+
+            for variant in &data.variants {
+                let ident = &variant.ident;
+
+                ret.push(Variant {
+                    path: parse_quote!(Self::#ident),
+                    skip: has_flag(&variant.attrs, "skip")?,
+                    fields: parse_fields(&variant.fields)?,
+                });
+            }
+
+            Ok(ret)
+        },
+        Data::Union(_) => Err(Error::new(input.span(), "PacketMutations cannot be derived for unions")),
+    }
+}
+
+/// Path to the butterfly crate as seen from the crate that uses the derive
+fn butterfly_path() -> Result<TokenStream2, Error> {
+    match crate_name("butterfly-fuzz") {
+        Ok(FoundCrate::Itself) => Ok(quote!(crate)),
+        Ok(FoundCrate::Name(name)) => {
+            let ident = Ident::new(&name, Span::call_site());
+            Ok(quote!(::#ident))
+        },
+        Err(_) => Err(Error::new(Span::call_site(), "PacketMutations requires butterfly-fuzz as a dependency")),
+    }
+}
+
+/// Pattern that binds all mutable fields of `variant` to `{prefix}_{i}`
+fn pattern(variant: &Variant, prefix: &str) -> (TokenStream2, Vec<Ident>) {
+    let path = &variant.path;
+    let members: Vec<&Member> = variant.fields.iter().map(|field| &field.member).collect();
+    let bindings: Vec<Ident> = (0..members.len()).map(|i| format_ident!("{}_{}", prefix, i)).collect();
+    (quote!(#path { #(#members: #bindings,)* .. }), bindings)
+}
+
+/// Execute one of `calls` at random
+fn select(calls: Vec<TokenStream2>, butterfly: &TokenStream2) -> TokenStream2 {
+    if calls.len() == 1 {
+        return calls.into_iter().next().unwrap();
+    }
+
+    let len = calls.len();
+    let indices = 0..len;
+
+    quote! {
+        match #butterfly::__private::libafl_bolts::rands::Rand::below(#butterfly::__private::libafl::state::HasRand::rand_mut(state), ::core::num::NonZero::new(#len).unwrap()) {
+            #(#indices => #calls,)*
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Body of `mutate_havoc()`
+fn havoc_body(variants: &[Variant], butterfly: &TokenStream2) -> TokenStream2 {
+    let arms = variants.iter().filter(|variant| !variant.skip && !variant.fields.is_empty()).map(|variant| {
+        let (pattern, bindings) = pattern(variant, "__self");
+        let calls = bindings.iter().map(|binding| quote!(#butterfly::HasHavocMutation::<__MT, __S>::mutate_havoc(#binding, state, mutations, mutation))).collect();
+        let call = select(calls, butterfly);
+        quote!(#pattern => #call,)
+    });
+
+    quote! {
+        match self {
+            #(#arms)*
+            #[allow(unreachable_patterns)]
+            _ => Ok(#butterfly::__private::libafl::mutators::MutationResult::Skipped),
+        }
+    }
+}
+
+/// Body of a mutation that gets a second packet `other`
+fn binary_body(variants: &[Variant], butterfly: &TokenStream2, trait_name: &Ident, method: &Ident) -> TokenStream2 {
+    let arms = variants.iter().filter(|variant| !variant.skip && !variant.fields.is_empty()).map(|variant| {
+        let (self_pattern, self_bindings) = pattern(variant, "__self");
+        let (other_pattern, other_bindings) = pattern(variant, "__other");
+        let calls = self_bindings.iter().zip(&other_bindings).map(|(this, other)| quote!(#butterfly::#trait_name::<__S>::#method(#this, state, #other))).collect();
+        let call = select(calls, butterfly);
+        quote!((#self_pattern, #other_pattern) => #call,)
+    });
+
+    quote! {
+        match (self, other) {
+            #(#arms)*
+            #[allow(unreachable_patterns)]
+            _ => Ok(#butterfly::__private::libafl::mutators::MutationResult::Skipped),
+        }
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let variants = parse_variants(&input)?;
+    let butterfly = butterfly_path()?;
+    let libafl = quote!(#butterfly::__private::libafl);
+    let ident = &input.ident;
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let field_types: Vec<&Type> = variants.iter().filter(|variant| !variant.skip).flat_map(|variant| variant.fields.iter().map(|field| &field.ty)).collect();
+
+    let mut havoc_generics = input.generics.clone();
+    havoc_generics.params.push(parse_quote!(__MT));
+    havoc_generics.params.push(parse_quote!(__S));
+    {
+        let where_clause = havoc_generics.make_where_clause();
+        where_clause.predicates.push(parse_quote!(__MT: #libafl::mutators::MutatorsTuple<#libafl::inputs::BytesInput, __S>));
+        where_clause.predicates.push(parse_quote!(__S: #libafl::state::HasRand + #libafl::state::HasMaxSize));
+
+        for ty in &field_types {
+            where_clause.predicates.push(parse_quote!(#ty: #butterfly::HasHavocMutation<__MT, __S>));
+        }
+    }
+    let (havoc_impl_generics, _, havoc_where_clause) = havoc_generics.split_for_impl();
+    let havoc = havoc_body(&variants, &butterfly);
+
+    let mut ret = quote! {
+        #[automatically_derived]
+        impl #havoc_impl_generics #butterfly::HasHavocMutation<__MT, __S> for #ident #ty_generics #havoc_where_clause {
+            fn mutate_havoc(&mut self, state: &mut __S, mutations: &mut __MT, mutation: #libafl::mutators::MutationId) -> ::core::result::Result<#libafl::mutators::MutationResult, #libafl::Error> {
+                #havoc
+            }
+        }
+    };
+
+    for (trait_name, method, other_ty) in [
+        ("HasSpliceMutation", "mutate_splice", quote!(&Self)),
+        ("HasCrossoverInsertMutation", "mutate_crossover_insert", quote!(&Self)),
+        ("HasCrossoverReplaceMutation", "mutate_crossover_replace", quote!(&Self)),
+        ("HasSplitMutation", "mutate_split", quote!(&mut Self)),
+        ("HasMergeMutation", "mutate_merge", quote!(&Self)),
+    ] {
+        let trait_name = Ident::new(trait_name, Span::call_site());
+        let method = Ident::new(method, Span::call_site());
+
+        let mut generics = input.generics.clone();
+        generics.params.push(parse_quote!(__S));
+        {
+            let where_clause = generics.make_where_clause();
+            where_clause.predicates.push(parse_quote!(__S: #libafl::state::HasRand + #libafl::state::HasMaxSize));
+
+            for ty in &field_types {
+                where_clause.predicates.push(parse_quote!(#ty: #butterfly::#trait_name<__S>));
+            }
+        }
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+        let body = binary_body(&variants, &butterfly, &trait_name, &method);
+
+        ret.extend(quote! {
+            #[automatically_derived]
+            impl #impl_generics #butterfly::#trait_name<__S> for #ident #ty_generics #where_clause {
+                fn #method(&mut self, state: &mut __S, other: #other_ty) -> ::core::result::Result<#libafl::mutators::MutationResult, #libafl::Error> {
+                    #body
+                }
+            }
+        });
+    }
+
+    let kind_arms = variants.iter().enumerate().map(|(kind, variant)| {
+        let path = &variant.path;
+        let kind = kind as u32;
+        quote!(#path { .. } => #kind,)
+    });
+    let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+
+    ret.extend(quote! {
+        #[automatically_derived]
+        impl #impl_generics #butterfly::HasPacketKind for #ident #ty_generics #where_clause {
+            fn packet_kind(&self) -> u32 {
+                match *self {
+                    #(#kind_arms)*
+                }
+            }
+        }
+    });
+
+    Ok(ret)
+}
@@ -0,0 +1,189 @@
+use butterfly::{supported_havoc_mutations, HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasMergeMutation, HasPacketKind, HasSpliceMutation, HasSplitMutation, PacketMutations};
+use libafl::{
+    inputs::BytesInput,
+    mutators::{MutationId, MutationResult},
+    state::{HasMaxSize, HasRand},
+};
+use libafl_bolts::rands::StdRand;
+
+struct TestState {
+    rand: StdRand,
+    max_size: usize,
+}
+impl TestState {
+    fn new() -> Self {
+        Self {
+            rand: StdRand::with_seed(0),
+            max_size: 0,
+        }
+    }
+}
+impl HasRand for TestState {
+    type Rand = StdRand;
+
+    fn rand(&self) -> &StdRand {
+        &self.rand
+    }
+
+    fn rand_mut(&mut self) -> &mut StdRand {
+        &mut self.rand
+    }
+}
+impl HasMaxSize for TestState {
+    fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+    }
+}
+
+#[derive(PacketMutations, Clone, Debug, PartialEq)]
+enum Packet {
+    Data(BytesInput),
+    Port(#[butterfly(immutable)] u16, BytesInput),
+    Login {
+        user: BytesInput,
+        pass: Option<BytesInput>,
+    },
+    #[butterfly(skip)]
+    Raw(BytesInput),
+    Quit,
+}
+
+#[derive(PacketMutations, Clone, Debug, PartialEq)]
+struct Nested {
+    #[butterfly(immutable)]
+    id: u32,
+    packet: Packet,
+}
+
+#[derive(PacketMutations)]
+struct Generic<T> {
+    inner: T,
+}
+
+fn bytes(data: &[u8]) -> BytesInput {
+    BytesInput::new(data.to_vec())
+}
+
+#[test]
+fn test_havoc() {
+    let mut state = TestState::new();
+    let mut mutations = supported_havoc_mutations();
+    // BitFlipMutator
+    let mutation = MutationId::from(0usize);
+
+    let mut packet = Packet::Port(21, bytes(b"AAAA"));
+    assert_eq!(packet.mutate_havoc(&mut state, &mut mutations, mutation).unwrap(), MutationResult::Mutated);
+    match &packet {
+        Packet::Port(port, data) => {
+            assert_eq!(*port, 21);
+            assert_ne!(data, &bytes(b"AAAA"));
+        },
+        _ => unreachable!(),
+    }
+
+    for mut packet in [Packet::Raw(bytes(b"AAAA")), Packet::Quit] {
+        let original = packet.clone();
+        assert_eq!(packet.mutate_havoc(&mut state, &mut mutations, mutation).unwrap(), MutationResult::Skipped);
+        assert_eq!(packet, original);
+    }
+
+    let mut nested = Nested {
+        id: 1,
+        packet: Packet::Data(bytes(b"AAAA")),
+    };
+    assert_eq!(nested.mutate_havoc(&mut state, &mut mutations, mutation).unwrap(), MutationResult::Mutated);
+    assert_eq!(nested.id, 1);
+
+    let mut generic = Generic {
+        inner: bytes(b"AAAA"),
+    };
+    assert_eq!(generic.mutate_havoc(&mut state, &mut mutations, mutation).unwrap(), MutationResult::Mutated);
+}
+
+#[test]
+fn test_multiple_fields() {
+    let mut state = TestState::new();
+    let mut mutations = supported_havoc_mutations();
+    let mutation = MutationId::from(0usize);
+    let original = Packet::Login {
+        user: bytes(b"AAAA"),
+        pass: Some(bytes(b"BBBB")),
+    };
+    let mut mutated_user = false;
+    let mut mutated_pass = false;
+
+    for _ in 0..64 {
+        let mut packet = original.clone();
+        packet.mutate_havoc(&mut state, &mut mutations, mutation).unwrap();
+
+        if let Packet::Login {
+            user,
+            pass,
+        } = packet
+        {
+            mutated_user |= user != bytes(b"AAAA");
+            mutated_pass |= pass != Some(bytes(b"BBBB"));
+        }
+    }
+
+    assert!(mutated_user && mutated_pass);
+}
+
+#[test]
+fn test_same_variant_only() {
+    let mut state = TestState::new();
+    let other = Packet::Data(bytes(b"BBBB"));
+
+    let mut packet = Packet::Port(21, bytes(b"AAAA"));
+    assert_eq!(packet.mutate_splice(&mut state, &other).unwrap(), MutationResult::Skipped);
+    assert_eq!(packet.mutate_crossover_insert(&mut state, &other).unwrap(), MutationResult::Skipped);
+    assert_eq!(packet.mutate_crossover_replace(&mut state, &other).unwrap(), MutationResult::Skipped);
+
+    let mut packet = Packet::Raw(bytes(b"AAAA"));
+    assert_eq!(packet.mutate_crossover_insert(&mut state, &Packet::Raw(bytes(b"BBBB"))).unwrap(), MutationResult::Skipped);
+
+    let mut packet = Packet::Data(bytes(b"AAAA"));
+    assert_eq!(packet.mutate_crossover_insert(&mut state, &other).unwrap(), MutationResult::Mutated);
+    match &packet {
+        Packet::Data(data) => assert!(data.as_ref().windows(1).any(|window| window == b"B")),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_split_merge() {
+    let mut state = TestState::new();
+
+    let mut packet = Packet::Port(21, bytes(b"AAAA"));
+    let mut other = packet.clone();
+    assert_eq!(packet.mutate_split(&mut state, &mut other).unwrap(), MutationResult::Mutated);
+    match (&packet, &other) {
+        (Packet::Port(21, first), Packet::Port(21, second)) => assert_eq!([first.as_ref() as &[u8], second.as_ref()].concat(), b"AAAA"),
+        _ => unreachable!(),
+    }
+
+    assert_eq!(packet.mutate_merge(&mut state, &other).unwrap(), MutationResult::Mutated);
+    assert_eq!(packet, Packet::Port(21, bytes(b"AAAA")));
+
+    let mut packet = Packet::Data(bytes(b"AAAA"));
+    assert_eq!(packet.mutate_merge(&mut state, &Packet::Raw(bytes(b"BBBB"))).unwrap(), MutationResult::Skipped);
+}
+
+#[test]
+fn test_packet_kind() {
+    assert_eq!(Packet::Data(bytes(b"A")).packet_kind(), 0);
+    assert_eq!(Packet::Port(21, bytes(b"A")).packet_kind(), 1);
+    assert_eq!(Packet::Quit.packet_kind(), 4);
+    assert_eq!(
+        Nested {
+            id: 1,
+            packet: Packet::Quit,
+        }
+        .packet_kind(),
+        0
+    );
+}
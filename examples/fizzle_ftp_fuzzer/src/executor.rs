@@ -1,11 +1,11 @@
 use std::fmt::{Debug, Formatter};
 
-use butterfly::StateObserver;
+use butterfly::{ResponseObserver, StateObserver};
 use libafl::{executors::{Executor, ExitKind, ForkserverExecutor, HasObservers}, inputs::TargetBytesConverter, observers::ObserversTuple, state::HasExecutions};
 use libafl_bolts::{shmem::ShMem, Error};
 use libafl_bolts::tuples::{MatchName, RefIndexable};
 
-use crate::{observer::{PacketResponseMapObserver, PKT_RSP_MAP_NAME}, proto::{PacketProtocol, Packets, ProtoParser}};
+use crate::proto::{PacketProtocol, Packets, ProtoParser};
 
 pub struct FizzleExecutor<OT, PKT, S, SHM, TC>
 where
@@ -76,15 +76,13 @@ where
         /*
         // TODO: record responses as clusters from individual requests (for protocols that employ multiple responses)
         let observers = self.observers();
-        let response_observer: &PacketResponseMapObserver<'_> = observers.match_name(PKT_RSP_MAP_NAME).unwrap();
-        let responses = response_observer.responses();
+        let response_observer: &ResponseObserver<SHM> = observers.match_name("fizzle_responses").unwrap();
+        let states: Vec<(usize, u32)> = response_observer.responses().filter_map(|(packet, response)| Some((packet, PKT::parse_response(&mut self.proto_parser, response)?))).collect();
         drop(observers);
-        for response in responses {
-            if let Some(rsp) = PKT::parse_response(&mut self.proto_parser, response) {
-                let mut observers = self.observers_mut();
-                let state_observer: &mut StateObserver<u32> = observers.match_name_mut("ButterflyState").unwrap();
-                state_observer.record(&rsp);
-            }
+        let mut observers = self.observers_mut();
+        let state_observer: &mut StateObserver<u32> = observers.match_name_mut("ButterflyState").unwrap();
+        for (packet, state) in states {
+            state_observer.record_at(packet, &state);
         }
 
         for packet in input.packets() {
@@ -82,14 +82,14 @@ impl PacketProtocol for FtpProtocol {
         }
     }
 
-    fn from_pcap(mut capture: pcap::Capture<pcap::Offline>) -> Option<Vec<Self>> {
+    fn from_pcap(mut capture: butterfly::Capture) -> Option<Vec<Self>> {
         // Packets extracted from pcap
         let mut packets = Vec::<FtpProtocol>::new();
         // Port numbers of the command connection: (client port, server port)
         let mut command_connection = None;
         
-        while let Ok(packet) = capture.next_packet() {
-            let packet = etherparse::PacketHeaders::from_ethernet_slice(&packet.data).unwrap();
+        while let Some(packet) = capture.next_packet() {
+            let packet = etherparse::PacketHeaders::from_ethernet_slice(packet.data()).unwrap();
             
             if let Some(etherparse::TransportHeader::Tcp(tcp)) = &packet.transport {
                 let packet_ports = (tcp.source_port, tcp.destination_port);
@@ -1,5 +1,4 @@
 mod executor;
-mod observer;
 mod proto;
 mod ftp;
 
@@ -18,16 +17,14 @@ use butterfly::{
     HasCrossoverReplaceMutation, PacketCrossoverReplaceMutator,
     HasSpliceMutation, PacketSpliceMutator,
     HasHavocMutation, PacketHavocMutator, supported_havoc_mutations,
-    HasPcapRepresentation, load_pcaps, GraphvizMonitor,
+    HasPcapRepresentation, load_pcaps, GraphvizMonitor, Capture, ResponseObserver,
 };
-use observer::PacketResponseMapObserver;
 use proto::{OpaqueParser, OpaqueProtocol, Packets};
 use serde::{Serialize, Deserialize};
 use std::{env, marker::PhantomData, time::Duration};
 use std::fmt::{Debug, Formatter};
 use std::net::{TcpStream, SocketAddrV4, Ipv4Addr};
 use std::io::{Read, Write};
-use pcap::{Capture, Offline};
 use etherparse;
 
 /*
@@ -173,14 +170,14 @@ impl Input for FTPInput {
 
 // Add pcap support to FTPInput
 impl HasPcapRepresentation<FTPInput> for FTPInput {
-    fn from_pcap(mut capture: Capture<Offline>) -> Result<FTPInput, Error> {
+    fn from_pcap(mut capture: Capture) -> Result<FTPInput, Error> {
         // Packets extracted from pcap
         let mut packets = Vec::<FTPCommand>::new();
         // Port numbers of the command connection: (client port, server port)
         let mut command_connection = None;
         
-        while let Ok(packet) = capture.next_packet() {
-            let packet = etherparse::PacketHeaders::from_ethernet_slice(&packet.data).unwrap();
+        while let Some(packet) = capture.next_packet() {
+            let packet = etherparse::PacketHeaders::from_ethernet_slice(packet.data()).unwrap();
             
             if let Some(etherparse::TransportHeader::Tcp(tcp)) = &packet.transport {
                 let packet_ports = (tcp.source_port, tcp.destination_port);
@@ -572,7 +569,7 @@ where
 
 fn main() {
     const MAP_SIZE: usize = 65536;
-    // const FIZZLE_RSPBUF_SIZE: usize = 65536 * 16; // The map that returns response values
+    const FIZZLE_RSPBUF_SIZE: usize = 65536 * 16; // The map that returns response values
 
     let tui_monitor = TuiMonitor::builder()
         .enhanced_graphics(true)
@@ -580,18 +577,12 @@ fn main() {
         .version("0.1.0")
         .build();
 
-    /*
-    let mut fizzle_shmem_provider = UnixShMemProvider::new().unwrap();
-    let mut fizzle_shmem = fizzle_shmem_provider.new_shmem(FIZZLE_RSPBUF_SIZE).unwrap();
-    fizzle_shmem.write_to_env("FIZZLE_LIBAFL_RSPBUF").unwrap();
-    let fizzle_shmembuf = fizzle_shmem.as_slice_mut();
+    let mut shmem_provider = UnixShMemProvider::new().unwrap();
 
-    let fizzle_resp_observer = unsafe {
-        PacketResponseMapObserver::new(StdMapObserver::new("fizzle_responses", fizzle_shmembuf))
-    };
-    */
+    // libfizzle finds this buffer via the BUTTERFLY_RESPONSE_SHM variables and writes the responses of the target into it
+    let fizzle_resp_observer = ResponseObserver::new("fizzle_responses", &mut shmem_provider, FIZZLE_RSPBUF_SIZE).unwrap();
+    let fizzle_resp_envs = fizzle_resp_observer.envs();
 
-    let mut shmem_provider = UnixShMemProvider::new().unwrap();
     let mut shmem = shmem_provider.new_shmem(MAP_SIZE).unwrap();
     // write the id to the env var for the forkserver
     shmem.write_to_env("__AFL_SHM_ID").unwrap();
@@ -658,7 +649,8 @@ fn main() {
         .timeout(Duration::from_secs(2))
     */
         .env("LD_PRELOAD", "/fizzle/target/debug/libfizzle.so")
-        .build::<Packets<_>, _, _>(tuple_list!(state_observer, edges_observer)) // , fizzle_resp_observer
+        .envs(fizzle_resp_envs)
+        .build::<Packets<_>, _, _>(tuple_list!(state_observer, edges_observer, fizzle_resp_observer))
         .unwrap();
 
     let mut fizzle_executor = FizzleExecutor::new(fork_executor);
@@ -675,8 +667,7 @@ fn main() {
     fuzzer.fuzz_loop_for(&mut stages, &mut executor, &mut state, &mut mgr, 50).unwrap();
     
     // Manually print the stategraph
-    let observer = executor.observers();
-    let state_observer: &StateObserver<u32> = &observer[&Handle::new("ButterflyState".into())];
-    println!("{}", state_observer.get_statemachine());
+    let graph = state.named_metadata::<StateGraphMetadata>("ButterflyState").unwrap();
+    println!("{}", graph.to_dot());
     */
 }
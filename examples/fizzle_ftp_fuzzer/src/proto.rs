@@ -102,7 +102,7 @@ impl<P> HasPcapRepresentation<Packets<P>> for Packets<P>
 where 
     P: PacketProtocol
 {
-    fn from_pcap(capture: pcap::Capture<pcap::Offline>) -> Result<Packets<P>, libafl::Error> {
+    fn from_pcap(capture: butterfly::Capture) -> Result<Packets<P>, libafl::Error> {
         let pkts = P::from_pcap(capture).unwrap();
         
         Ok(Packets {
@@ -116,7 +116,7 @@ pub trait PacketProtocol: Clone + Debug + Hash + serde::Serialize {
 
     fn to_bytes_extend(&self, v: &mut Vec<u8>);
 
-    fn from_pcap(capture: pcap::Capture<pcap::Offline>) -> Option<Vec<Self>>;
+    fn from_pcap(capture: butterfly::Capture) -> Option<Vec<Self>>;
 
     fn parse_request(p: &mut Self::Parser, req: &Self) -> Option<u32> {
         unimplemented!()
@@ -159,7 +159,7 @@ impl PacketProtocol for OpaqueProtocol {
         }
     }
 
-    fn from_pcap(capture: pcap::Capture<pcap::Offline>) -> Option<Vec<Self>> {
+    fn from_pcap(capture: butterfly::Capture) -> Option<Vec<Self>> {
         Some(Vec::new()) // TODO: unimplemented
     }
 }
@@ -12,15 +12,14 @@ use butterfly::{
     HasCrossoverReplaceMutation, PacketCrossoverReplaceMutator,
     HasSpliceMutation, PacketSpliceMutator,
     HasHavocMutation, PacketHavocMutator, supported_havoc_mutations,
-    HasPcapRepresentation, load_pcaps, GraphvizMonitor,
+    HasPcapRepresentation, load_pcaps, GraphvizMonitor, Capture, Flow, FlowFilter,
+    StateExtractor, StatusCodeExtractor,
 };
 use serde::{Serialize, Deserialize};
 use std::marker::PhantomData;
 use std::fmt::{Debug, Formatter};
 use std::net::{TcpStream, SocketAddrV4, Ipv4Addr};
 use std::io::{Read, Write};
-use pcap::{Capture, Offline};
-use etherparse;
 
 fn parse_decimal(buf: &[u8]) -> (u32, usize) {
     let mut res = 0;
@@ -162,74 +161,48 @@ impl Input for FTPInput {
 
 // Add pcap support to FTPInput
 impl HasPcapRepresentation<FTPInput> for FTPInput {
-    fn from_pcap(mut capture: Capture<Offline>) -> Result<FTPInput, Error> {
+    fn from_pcap(capture: Capture) -> Result<FTPInput, Error> {
         // Packets extracted from pcap
         let mut packets = Vec::<FTPCommand>::new();
-        // Port numbers of the command connection: (client port, server port)
-        let mut command_connection = None;
-        
-        while let Ok(packet) = capture.next_packet() {
-            let packet = etherparse::PacketHeaders::from_ethernet_slice(&packet.data).unwrap();
-            
-            if let Some(etherparse::TransportHeader::Tcp(tcp)) = &packet.transport {
-                let packet_ports = (tcp.source_port, tcp.destination_port);
+        // We only care about the command connection to port 21.
+        // All other connections are data connections which we don't care about.
+        let flow = Flow::from_capture(&capture, &FlowFilter::Port(21))?;
+        
+        for message in flow.client_messages() {
+            // A message may contain multiple commands, each terminated by \r\n
+            for line in message.data().split(|c| *c == b'\n') {
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
                 
-                // Does the client make a connection to the server ?
-                if tcp.syn && !tcp.ack {
-                    // We only care about the first connection that is established as
-                    // it is the command connection.
-                    // All other connections are data connections which we don't care about.
-                    if command_connection.is_none() {
-                        command_connection = Some(packet_ports);
-                    }
-                }
-                // Was the command connection closed ?
-                else if tcp.fin || tcp.rst {
-                    if Some(packet_ports) == command_connection {
-                        break;
-                    }
+                if line.len() < 4 {
+                    continue;
                 }
-                // Was data transferred ?
-                else if packet.payload.len() > 4 {
-                    if Some(packet_ports) == command_connection {
-                        // First find the \r\n that terminates a command
-                        let mut linebreak = 0;
-                        while linebreak < packet.payload.len() - 1 {
-                            if packet.payload[linebreak] == b'\r' && packet.payload[linebreak + 1] == b'\n' {
-                                break;
-                            }
-                            linebreak += 1;
+                
+                // Then parse the command
+                let command = match &line[0..4] {
+                    b"USER" if line.len() > 5 => FTPCommand::USER(BytesInput::new(line[5..].to_vec())),
+                    b"PASS" if line.len() > 5 => FTPCommand::PASS(BytesInput::new(line[5..].to_vec())),
+                    b"CWD " => FTPCommand::CWD(BytesInput::new(line[4..].to_vec())),
+                    b"PASV" => FTPCommand::PASV,
+                    b"TYPE" if line.len() > 5 => {
+                        if line.len() > 7 {
+                            FTPCommand::TYPE(line[5], line[7])
+                        } else {
+                            FTPCommand::TYPE(line[5], b'N')
                         }
-                        assert!(linebreak < packet.payload.len() - 1);
-                        
-                        // Then parse the command
-                        let command = match &packet.payload[0..4] {
-                            b"USER" => FTPCommand::USER(BytesInput::new(packet.payload[5..linebreak].to_vec())),
-                            b"PASS" => FTPCommand::PASS(BytesInput::new(packet.payload[5..linebreak].to_vec())),
-                            b"CWD " => FTPCommand::CWD(BytesInput::new(packet.payload[4..linebreak].to_vec())),
-                            b"PASV" => FTPCommand::PASV,
-                            b"TYPE" => {
-                                if linebreak > 7 {
-                                    FTPCommand::TYPE(packet.payload[5], packet.payload[7])
-                                } else {
-                                    FTPCommand::TYPE(packet.payload[5], b'N')
-                                }
-                            },
-                            b"LIST" => {
-                                if linebreak > 5 {
-                                    FTPCommand::LIST(Some(BytesInput::new(packet.payload[5..linebreak].to_vec())))
-                                } else {
-                                    FTPCommand::LIST(None)
-                                }
-                            },
-                            b"QUIT" => FTPCommand::QUIT,
-                            // Ignore other commands:
-                            _ => continue,
-                        };
-                        
-                        packets.push(command);
-                    }
-                }
+                    },
+                    b"LIST" => {
+                        if line.len() > 5 {
+                            FTPCommand::LIST(Some(BytesInput::new(line[5..].to_vec())))
+                        } else {
+                            FTPCommand::LIST(None)
+                        }
+                    },
+                    b"QUIT" => FTPCommand::QUIT,
+                    // Ignore other commands:
+                    _ => continue,
+                };
+                
+                packets.push(command);
             }
         }
         
@@ -276,11 +249,10 @@ where
         }
         
         // Parse the status code
-        let (status_code, len) = parse_decimal(&self.buf[0..num_read]);
-        
-        if len != 3 {
-            return Some(0);
-        }
+        let status_code = match StatusCodeExtractor::new(3).unwrap().extract_state(&self.buf[0..num_read]) {
+            Some(status_code) => status_code,
+            None => return Some(0),
+        };
         
         // Tell butterfly the state that we entered
         let state_observer: &mut StateObserver<u32> = self.observers.match_name_mut("ButterflyFTPState").unwrap();
@@ -614,8 +586,7 @@ fn main() {
     fuzzer.fuzz_loop_for(&mut stages, &mut executor, &mut state, &mut mgr, 50).unwrap();
     
     // Manually print the stategraph
-    let observer = executor.observers();
-    let state_observer: &StateObserver<u32> = &observer[&Handle::new("ButterflyFTPState".into())];
-    println!("{}", state_observer.get_statemachine());
+    let graph = state.named_metadata::<StateGraphMetadata>("ButterflyFTPState").unwrap();
+    println!("{}", graph.to_dot());
     */
 }
@@ -0,0 +1,1051 @@
+use libafl::Error;
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+const PCAP_HEADER_LEN: usize = 24;
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+/// Same default as tcpdump, large enough for every frame that a PcapWriter synthesizes
+const PCAP_SNAPLEN: u32 = 262144;
+const LINKTYPE_ETHERNET: u32 = 1;
+
+const PCAPNG_SHB: u32 = 0x0a0d0d0a;
+const PCAPNG_IDB: u32 = 0x00000001;
+const PCAPNG_OPB: u32 = 0x00000002;
+const PCAPNG_SPB: u32 = 0x00000003;
+const PCAPNG_EPB: u32 = 0x00000006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const PCAPNG_VERSION_MAJOR: u16 = 1;
+const PCAPNG_OPT_ENDOFOPT: u16 = 0;
+const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+const PCAPNG_OPT_IF_TSOFFSET: u16 = 14;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const MICROS_PER_SEC: u64 = 1_000_000;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const TCP_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+
+/// Maximum number of payload bytes in a single synthesized TCP segment
+const TCP_MSS: usize = 1460;
+/// Maximum number of payload bytes in a single synthesized UDP datagram
+const UDP_MAX_PAYLOAD: usize = 65535 - IPV4_HEADER_LEN - UDP_HEADER_LEN;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+const CLIENT_ISN: u32 = 0x1000_0000;
+const SERVER_ISN: u32 = 0x2000_0000;
+const CLIENT_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+const SERVER_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+
+/// Time that passes between two synthesized frames
+const FRAME_INTERVAL: Duration = Duration::from_millis(1);
+
+fn malformed(reason: &str) -> Error {
+    Error::illegal_argument(format!("Malformed packet capture: {}", reason))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Endianness {
+    Little,
+    Big,
+}
+
+/// Bounds-checked reader of integers and byte slices.
+struct ByteReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    endianness: Endianness,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8], endianness: Endianness) -> Self {
+        Self {
+            data,
+            offset: 0,
+            endianness,
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.remaining() {
+            return Err(malformed("unexpected end of data"));
+        }
+
+        let bytes = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?.try_into().unwrap();
+
+        Ok(match self.endianness {
+            Endianness::Little => u16::from_le_bytes(bytes),
+            Endianness::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?.try_into().unwrap();
+
+        Ok(match self.endianness {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let bytes = self.bytes(8)?.try_into().unwrap();
+
+        Ok(match self.endianness {
+            Endianness::Little => u64::from_le_bytes(bytes),
+            Endianness::Big => u64::from_be_bytes(bytes),
+        })
+    }
+}
+
+/// An interface on which the packets of a [`Capture`] were recorded.
+///
+/// Classic pcap files always have exactly one interface,
+/// pcapng files can have multiple.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Interface {
+    linktype: u16,
+    snaplen: u32,
+    /// Number of timestamp units per second
+    ts_units: u64,
+    /// Seconds that get added to every timestamp
+    ts_offset: i64,
+}
+
+impl Interface {
+    /// The link-layer header type of the packets on this interface
+    /// as defined on <https://www.tcpdump.org/linktypes.html>.
+    pub fn linktype(&self) -> u16 {
+        self.linktype
+    }
+
+    /// The maximum number of bytes captured from each packet. 0 means unlimited or unknown.
+    pub fn snaplen(&self) -> u32 {
+        self.snaplen
+    }
+
+    fn timestamp(&self, ts: u64) -> Duration {
+        let secs = ts / self.ts_units;
+        let nanos = (ts % self.ts_units) as u128 * NANOS_PER_SEC as u128 / self.ts_units as u128;
+        let timestamp = Duration::new(secs, nanos as u32);
+        let offset = Duration::from_secs(self.ts_offset.unsigned_abs());
+
+        if self.ts_offset >= 0 {
+            timestamp.saturating_add(offset)
+        } else {
+            timestamp.saturating_sub(offset)
+        }
+    }
+}
+
+/// A single packet of a [`Capture`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedPacket {
+    timestamp: Duration,
+    interface: usize,
+    linktype: u16,
+    orig_len: u32,
+    data: Vec<u8>,
+}
+
+impl CapturedPacket {
+    /// Time since the unix epoch at which the packet was captured, with up to nanosecond precision.
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// Index into [`Capture::interfaces()`] of the interface on which the packet was captured.
+    pub fn interface(&self) -> usize {
+        self.interface
+    }
+
+    /// The link-layer header type of [`CapturedPacket::data()`], same as [`Interface::linktype()`].
+    pub fn linktype(&self) -> u16 {
+        self.linktype
+    }
+
+    /// The length of the packet on the wire. May be larger than [`CapturedPacket::data()`]
+    /// if the packet got truncated during capture.
+    pub fn orig_len(&self) -> u32 {
+        self.orig_len
+    }
+
+    /// The captured bytes of the packet, starting with the link-layer header.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// A packet capture that was read from a pcap or pcapng file.
+///
+/// This is a pure-rust reader that does not depend on libpcap.
+/// It supports
+/// - classic pcap files with microsecond and nanosecond timestamps
+/// - pcapng files with multiple sections and multiple interfaces of different link types
+/// - both endiannesses
+///
+/// # Example
+/// ```
+/// let mut capture = Capture::from_file("seed.pcapng")?;
+///
+/// while let Some(packet) = capture.next_packet() {
+///     println!("{:?}: {} bytes", packet.timestamp(), packet.data().len());
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Capture {
+    interfaces: Vec<Interface>,
+    packets: Vec<CapturedPacket>,
+    cursor: usize,
+}
+
+impl Capture {
+    /// Read a pcap or pcapng file. The format is detected automatically.
+    pub fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Parse the contents of a pcap or pcapng file. The format is detected automatically.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let magic = match data.get(0..4) {
+            Some(magic) => u32::from_le_bytes(magic.try_into().unwrap()),
+            None => return Err(malformed("file too short")),
+        };
+
+        match magic {
+            PCAPNG_SHB => Self::parse_pcapng(data),
+            PCAP_MAGIC => Self::parse_pcap(data, Endianness::Little, MICROS_PER_SEC),
+            PCAP_MAGIC_NANOS => Self::parse_pcap(data, Endianness::Little, NANOS_PER_SEC),
+            _ if magic == PCAP_MAGIC.swap_bytes() => Self::parse_pcap(data, Endianness::Big, MICROS_PER_SEC),
+            _ if magic == PCAP_MAGIC_NANOS.swap_bytes() => Self::parse_pcap(data, Endianness::Big, NANOS_PER_SEC),
+            _ => Err(malformed("unknown file format")),
+        }
+    }
+
+    /// Convert a capture opened with libpcap.
+    ///
+    /// __Only available with feature__: `libpcap`
+    #[cfg(feature = "libpcap")]
+    pub fn from_libpcap(mut capture: pcap::Capture<pcap::Offline>) -> Result<Self, Error> {
+        let interface = Interface {
+            linktype: capture.get_datalink().0 as u16,
+            snaplen: 0,
+            ts_units: MICROS_PER_SEC,
+            ts_offset: 0,
+        };
+        let mut packets = Vec::new();
+
+        loop {
+            match capture.next_packet() {
+                Ok(packet) => {
+                    let ts = packet.header.ts.tv_sec as u64 * MICROS_PER_SEC + packet.header.ts.tv_usec as u64;
+
+                    packets.push(CapturedPacket {
+                        timestamp: interface.timestamp(ts),
+                        interface: 0,
+                        linktype: interface.linktype,
+                        orig_len: packet.header.len,
+                        data: packet.data.to_vec(),
+                    });
+                },
+                Err(pcap::Error::NoMorePackets) => break,
+                Err(e) => return Err(Error::unknown(format!("libpcap: {}", e))),
+            }
+        }
+
+        Ok(Self {
+            interfaces: vec![interface],
+            packets,
+            cursor: 0,
+        })
+    }
+
+    /// All interfaces of the capture.
+    pub fn interfaces(&self) -> &[Interface] {
+        &self.interfaces
+    }
+
+    /// All packets of the capture in the order they appear in the file.
+    pub fn packets(&self) -> &[CapturedPacket] {
+        &self.packets
+    }
+
+    /// Get the next packet or `None` if all packets have been read.
+    pub fn next_packet(&mut self) -> Option<&CapturedPacket> {
+        let packet = self.packets.get(self.cursor)?;
+        self.cursor += 1;
+        Some(packet)
+    }
+
+    fn parse_pcap(data: &[u8], endianness: Endianness, ts_units: u64) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data, endianness);
+        let _magic = reader.u32()?;
+        let _version_major = reader.u16()?;
+        let _version_minor = reader.u16()?;
+        let _thiszone = reader.u32()?;
+        let _sigfigs = reader.u32()?;
+        let snaplen = reader.u32()?;
+        // The upper 16 bits contain FCS information that we don't care about
+        let linktype = reader.u32()? as u16;
+        debug_assert_eq!(reader.offset, PCAP_HEADER_LEN);
+
+        let interface = Interface {
+            linktype,
+            snaplen,
+            ts_units,
+            ts_offset: 0,
+        };
+        let mut packets = Vec::new();
+
+        while reader.remaining() > 0 {
+            let secs = reader.u32()? as u64;
+            let frac = reader.u32()? as u64;
+            let caplen = reader.u32()? as usize;
+            let orig_len = reader.u32()?;
+            let data = reader.bytes(caplen)?;
+
+            packets.push(CapturedPacket {
+                timestamp: interface.timestamp(secs * ts_units + frac),
+                interface: 0,
+                linktype,
+                orig_len,
+                data: data.to_vec(),
+            });
+        }
+
+        Ok(Self {
+            interfaces: vec![interface],
+            packets,
+            cursor: 0,
+        })
+    }
+
+    fn parse_pcapng(data: &[u8]) -> Result<Self, Error> {
+        let mut interfaces = Vec::new();
+        let mut packets = Vec::new();
+        let mut endianness = Endianness::Little;
+        // Interface IDs are local to a section
+        let mut section_start = 0;
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let mut header = ByteReader::new(&data[offset..], endianness);
+            let block_type = header.u32()?;
+
+            // The section header block determines the endianness of all subsequent blocks
+            if block_type == PCAPNG_SHB {
+                endianness = match data.get(offset + 8..offset + 12).map(|magic| u32::from_le_bytes(magic.try_into().unwrap())) {
+                    Some(PCAPNG_BYTE_ORDER_MAGIC) => Endianness::Little,
+                    Some(magic) if magic == PCAPNG_BYTE_ORDER_MAGIC.swap_bytes() => Endianness::Big,
+                    _ => return Err(malformed("invalid byte-order magic")),
+                };
+                header.endianness = endianness;
+                section_start = interfaces.len();
+            }
+
+            let block_len = header.u32()? as usize;
+
+            if block_len < 12 || !block_len.is_multiple_of(4) || block_len > data.len() - offset {
+                return Err(malformed("invalid block length"));
+            }
+
+            let mut body = ByteReader::new(&data[offset + 8..offset + block_len - 4], endianness);
+
+            match block_type {
+                PCAPNG_SHB => {
+                    let _magic = body.u32()?;
+
+                    if body.u16()? != PCAPNG_VERSION_MAJOR {
+                        return Err(malformed("unsupported pcapng version"));
+                    }
+                },
+                PCAPNG_IDB => {
+                    let linktype = body.u16()?;
+                    let _reserved = body.u16()?;
+                    let snaplen = body.u32()?;
+                    let mut interface = Interface {
+                        linktype,
+                        snaplen,
+                        ts_units: MICROS_PER_SEC,
+                        ts_offset: 0,
+                    };
+
+                    while body.remaining() >= 4 {
+                        let code = body.u16()?;
+                        let len = body.u16()? as usize;
+                        let value = body.bytes(len)?;
+                        body.bytes((4 - len % 4) % 4)?;
+
+                        match code {
+                            PCAPNG_OPT_ENDOFOPT => break,
+                            PCAPNG_OPT_IF_TSRESOL if len == 1 => {
+                                let exponent = (value[0] & 0x7f) as u32;
+
+                                interface.ts_units = if value[0] & 0x80 != 0 {
+                                    1u64.checked_shl(exponent).filter(|_| exponent < 64)
+                                } else {
+                                    10u64.checked_pow(exponent)
+                                }
+                                .ok_or_else(|| malformed("unsupported timestamp resolution"))?;
+                            },
+                            PCAPNG_OPT_IF_TSOFFSET if len == 8 => {
+                                interface.ts_offset = ByteReader::new(value, endianness).u64()? as i64;
+                            },
+                            _ => {},
+                        }
+                    }
+
+                    interfaces.push(interface);
+                },
+                PCAPNG_EPB | PCAPNG_OPB => {
+                    let interface_id = if block_type == PCAPNG_EPB {
+                        body.u32()? as usize
+                    } else {
+                        let id = body.u16()? as usize;
+                        let _drops = body.u16()?;
+                        id
+                    };
+                    let ts_high = body.u32()? as u64;
+                    let ts_low = body.u32()? as u64;
+                    let caplen = body.u32()? as usize;
+                    let orig_len = body.u32()?;
+                    let data = body.bytes(caplen)?;
+
+                    let index = section_start + interface_id;
+                    let interface = interfaces.get(index).ok_or_else(|| malformed("packet references unknown interface"))?;
+
+                    packets.push(CapturedPacket {
+                        timestamp: interface.timestamp(ts_high << 32 | ts_low),
+                        interface: index,
+                        linktype: interface.linktype,
+                        orig_len,
+                        data: data.to_vec(),
+                    });
+                },
+                PCAPNG_SPB => {
+                    let interface = interfaces.get(section_start).ok_or_else(|| malformed("packet references unknown interface"))?;
+                    let orig_len = body.u32()?;
+                    let mut caplen = std::cmp::min(orig_len as usize, body.remaining());
+
+                    if interface.snaplen > 0 {
+                        caplen = std::cmp::min(caplen, interface.snaplen as usize);
+                    }
+
+                    packets.push(CapturedPacket {
+                        // simple packet blocks carry no timestamp
+                        timestamp: Duration::ZERO,
+                        interface: section_start,
+                        linktype: interface.linktype,
+                        orig_len,
+                        data: body.bytes(caplen)?.to_vec(),
+                    });
+                },
+                _ => {},
+            }
+
+            offset += block_len;
+        }
+
+        Ok(Self {
+            interfaces,
+            packets,
+            cursor: 0,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transport {
+    Tcp,
+    Udp,
+}
+
+/// Computes the internet checksum (RFC 1071) over `data`, starting with a partial `sum`.
+fn checksum(mut sum: u32, data: &[u8]) -> u16 {
+    let mut chunks = data.chunks_exact(2);
+
+    for chunk in &mut chunks {
+        sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u32;
+    }
+
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Partial checksum over the IPv4 or IPv6 pseudo header used by TCP and UDP.
+///
+/// Both headers contain the addresses, the protocol and the length, only their layout differs,
+/// which doesn't matter for the ones' complement sum.
+fn pseudo_header_sum(src: &SocketAddr, dst: &SocketAddr, protocol: u8, len: usize) -> u32 {
+    let mut sum = 0;
+
+    for ip in [src.ip(), dst.ip()] {
+        let octets = match ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+
+        for word in octets.chunks_exact(2) {
+            sum += u16::from_be_bytes([word[0], word[1]]) as u32;
+        }
+    }
+
+    sum + protocol as u32 + len as u32
+}
+
+/// Convert the length of an IP packet or UDP datagram into its 16-bit header field
+fn length_field(len: usize) -> Result<u16, Error> {
+    u16::try_from(len).map_err(|_| Error::illegal_argument(format!("{} bytes do not fit into a single IP packet", len)))
+}
+
+/// Synthesizes a packet capture out of application-level messages.
+///
+/// Every message gets wrapped into Ethernet, IPv4 or IPv6 and TCP or UDP headers such that
+/// the resulting file can be opened with Wireshark or replayed with tcpreplay.
+/// For TCP a complete connection is synthesized: the three-way handshake gets written
+/// upon creation, sequence and acknowledgement numbers are tracked per direction,
+/// messages larger than the MSS are segmented and [`PcapWriter::finish()`] tears the
+/// connection down again.
+///
+/// The output is always a classic pcap file (not pcapng) with link type Ethernet and microsecond timestamps.
+/// Whether IPv4 or IPv6 is used depends on the addresses, client and server must be of the same family.
+///
+/// # Example
+/// ```
+/// let client = "127.0.0.1:40000".parse().unwrap();
+/// let server = "127.0.0.1:21".parse().unwrap();
+/// let mut writer = PcapWriter::tcp(File::create("input.pcap")?, client, server)?;
+/// writer.write_client(b"USER anonymous\r\n")?;
+/// writer.write_server(b"331 Password required\r\n")?;
+/// writer.finish()?;
+/// ```
+pub struct PcapWriter<W>
+where
+    W: Write,
+{
+    inner: W,
+    transport: Transport,
+    client: SocketAddr,
+    server: SocketAddr,
+    client_seq: u32,
+    server_seq: u32,
+    ip_id: u16,
+    timestamp: Duration,
+}
+
+impl<W> PcapWriter<W>
+where
+    W: Write,
+{
+    /// Create a new PcapWriter that synthesizes a TCP connection from `client` to `server`.
+    pub fn tcp(inner: W, client: SocketAddr, server: SocketAddr) -> Result<Self, Error> {
+        let mut writer = Self::new(inner, Transport::Tcp, client, server)?;
+
+        writer.write_tcp_frame(true, TCP_SYN, &[])?;
+        writer.client_seq = writer.client_seq.wrapping_add(1);
+        writer.write_tcp_frame(false, TCP_SYN | TCP_ACK, &[])?;
+        writer.server_seq = writer.server_seq.wrapping_add(1);
+        writer.write_tcp_frame(true, TCP_ACK, &[])?;
+
+        Ok(writer)
+    }
+
+    /// Create a new PcapWriter that synthesizes UDP datagrams between `client` and `server`.
+    pub fn udp(inner: W, client: SocketAddr, server: SocketAddr) -> Result<Self, Error> {
+        Self::new(inner, Transport::Udp, client, server)
+    }
+
+    fn new(mut inner: W, transport: Transport, client: SocketAddr, server: SocketAddr) -> Result<Self, Error> {
+        if client.is_ipv4() != server.is_ipv4() {
+            return Err(Error::illegal_argument(format!("Client {} and server {} must both be IPv4 or both be IPv6", client, server)));
+        }
+
+        inner.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        inner.write_all(&PCAP_VERSION_MAJOR.to_le_bytes())?;
+        inner.write_all(&PCAP_VERSION_MINOR.to_le_bytes())?;
+        inner.write_all(&0i32.to_le_bytes())?;
+        inner.write_all(&0u32.to_le_bytes())?;
+        inner.write_all(&PCAP_SNAPLEN.to_le_bytes())?;
+        inner.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+
+        Ok(Self {
+            inner,
+            transport,
+            client,
+            server,
+            client_seq: CLIENT_ISN,
+            server_seq: SERVER_ISN,
+            ip_id: 0,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+        })
+    }
+
+    /// Write a message that the client sent to the server.
+    pub fn write_client(&mut self, payload: &[u8]) -> Result<(), Error> {
+        self.write_message(true, payload)
+    }
+
+    /// Write a message that the server sent to the client.
+    pub fn write_server(&mut self, payload: &[u8]) -> Result<(), Error> {
+        self.write_message(false, payload)
+    }
+
+    /// Close the connection and return the underlying writer.
+    ///
+    /// For TCP this writes the FIN handshake, for UDP it only flushes the writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.transport == Transport::Tcp {
+            self.write_tcp_frame(true, TCP_FIN | TCP_ACK, &[])?;
+            self.client_seq = self.client_seq.wrapping_add(1);
+            self.write_tcp_frame(false, TCP_FIN | TCP_ACK, &[])?;
+            self.server_seq = self.server_seq.wrapping_add(1);
+            self.write_tcp_frame(true, TCP_ACK, &[])?;
+        }
+
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_message(&mut self, from_client: bool, payload: &[u8]) -> Result<(), Error> {
+        match self.transport {
+            Transport::Tcp => {
+                let segments = payload.chunks(TCP_MSS).count();
+
+                // Like a real network stack only the last segment of a message gets the PSH flag
+                for (i, segment) in payload.chunks(TCP_MSS).enumerate() {
+                    let flags = if i + 1 == segments {
+                        TCP_PSH | TCP_ACK
+                    } else {
+                        TCP_ACK
+                    };
+                    self.write_tcp_frame(from_client, flags, segment)?;
+
+                    if from_client {
+                        self.client_seq = self.client_seq.wrapping_add(segment.len() as u32);
+                    } else {
+                        self.server_seq = self.server_seq.wrapping_add(segment.len() as u32);
+                    }
+                }
+
+                Ok(())
+            },
+            Transport::Udp => {
+                if payload.len() > UDP_MAX_PAYLOAD {
+                    return Err(Error::illegal_argument(format!("UDP payload of {} bytes does not fit into a single datagram", payload.len())));
+                }
+
+                self.write_udp_frame(from_client, payload)
+            },
+        }
+    }
+
+    fn write_tcp_frame(&mut self, from_client: bool, flags: u8, payload: &[u8]) -> Result<(), Error> {
+        let (src, dst, seq, ack) = if from_client {
+            (self.client, self.server, self.client_seq, self.server_seq)
+        } else {
+            (self.server, self.client, self.server_seq, self.client_seq)
+        };
+        let ack = if flags & TCP_ACK != 0 {
+            ack
+        } else {
+            0
+        };
+
+        let mut segment = Vec::with_capacity(TCP_HEADER_LEN + payload.len());
+        segment.extend_from_slice(&src.port().to_be_bytes());
+        segment.extend_from_slice(&dst.port().to_be_bytes());
+        segment.extend_from_slice(&seq.to_be_bytes());
+        segment.extend_from_slice(&ack.to_be_bytes());
+        segment.push(((TCP_HEADER_LEN / 4) as u8) << 4);
+        segment.push(flags);
+        segment.extend_from_slice(&u16::MAX.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 0]);
+        segment.extend_from_slice(payload);
+
+        let sum = checksum(pseudo_header_sum(&src, &dst, IPPROTO_TCP, segment.len()), &segment);
+        segment[16..18].copy_from_slice(&sum.to_be_bytes());
+
+        self.write_ip_frame(from_client, IPPROTO_TCP, &segment)
+    }
+
+    fn write_udp_frame(&mut self, from_client: bool, payload: &[u8]) -> Result<(), Error> {
+        let (src, dst) = if from_client {
+            (self.client, self.server)
+        } else {
+            (self.server, self.client)
+        };
+
+        let mut datagram = Vec::with_capacity(UDP_HEADER_LEN + payload.len());
+        datagram.extend_from_slice(&src.port().to_be_bytes());
+        datagram.extend_from_slice(&dst.port().to_be_bytes());
+        datagram.extend_from_slice(&length_field(UDP_HEADER_LEN + payload.len())?.to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(payload);
+
+        // A checksum of zero means "no checksum" in UDP, so it is transmitted as all ones
+        let sum = match checksum(pseudo_header_sum(&src, &dst, IPPROTO_UDP, datagram.len()), &datagram) {
+            0 => 0xffff,
+            sum => sum,
+        };
+        datagram[6..8].copy_from_slice(&sum.to_be_bytes());
+
+        self.write_ip_frame(from_client, IPPROTO_UDP, &datagram)
+    }
+
+    fn write_ip_frame(&mut self, from_client: bool, protocol: u8, data: &[u8]) -> Result<(), Error> {
+        let (src, dst, src_mac, dst_mac) = if from_client {
+            (self.client, self.server, CLIENT_MAC, SERVER_MAC)
+        } else {
+            (self.server, self.client, SERVER_MAC, CLIENT_MAC)
+        };
+
+        let mut frame = Vec::with_capacity(ETHERNET_HEADER_LEN + IPV6_HEADER_LEN + data.len());
+        frame.extend_from_slice(&dst_mac);
+        frame.extend_from_slice(&src_mac);
+
+        match (src.ip(), dst.ip()) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+
+                let ip_start = frame.len();
+                frame.push(0x45);
+                frame.push(0);
+                frame.extend_from_slice(&length_field(IPV4_HEADER_LEN + data.len())?.to_be_bytes());
+                frame.extend_from_slice(&self.ip_id.to_be_bytes());
+                frame.extend_from_slice(&0x4000u16.to_be_bytes());
+                frame.push(64);
+                frame.push(protocol);
+                frame.extend_from_slice(&[0, 0]);
+                frame.extend_from_slice(&src.octets());
+                frame.extend_from_slice(&dst.octets());
+
+                let sum = checksum(0, &frame[ip_start..]);
+                frame[ip_start + 10..ip_start + 12].copy_from_slice(&sum.to_be_bytes());
+                self.ip_id = self.ip_id.wrapping_add(1);
+            },
+            (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                frame.extend_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+
+                // Version 6 without traffic class and flow label, the length excludes the header
+                frame.extend_from_slice(&[0x60, 0, 0, 0]);
+                frame.extend_from_slice(&length_field(data.len())?.to_be_bytes());
+                frame.push(protocol);
+                frame.push(64);
+                frame.extend_from_slice(&src.octets());
+                frame.extend_from_slice(&dst.octets());
+            },
+            // Checked in new()
+            _ => unreachable!(),
+        }
+
+        frame.extend_from_slice(data);
+        self.write_record(&frame)
+    }
+
+    fn write_record(&mut self, frame: &[u8]) -> Result<(), Error> {
+        // Frames beyond the snapshot length get cut off but keep their original length
+        let captured = &frame[..frame.len().min(PCAP_SNAPLEN as usize)];
+
+        self.inner.write_all(&(self.timestamp.as_secs() as u32).to_le_bytes())?;
+        self.inner.write_all(&self.timestamp.subsec_micros().to_le_bytes())?;
+        self.inner.write_all(&(captured.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.inner.write_all(captured)?;
+
+        self.timestamp += FRAME_INTERVAL;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLOBAL_HEADER_LEN: usize = 24;
+    const RECORD_HEADER_LEN: usize = 16;
+
+    fn records(data: &[u8]) -> Vec<&[u8]> {
+        let mut records = Vec::new();
+        let mut offset = GLOBAL_HEADER_LEN;
+
+        while offset < data.len() {
+            let len = u32::from_le_bytes(data[offset + 8..offset + 12].try_into().unwrap()) as usize;
+            offset += RECORD_HEADER_LEN;
+            records.push(&data[offset..offset + len]);
+            offset += len;
+        }
+
+        records
+    }
+
+    fn addresses() -> (SocketAddr, SocketAddr) {
+        ("10.0.0.1:40000".parse().unwrap(), "10.0.0.2:21".parse().unwrap())
+    }
+
+    #[test]
+    fn test_checksum() {
+        // Example from RFC 1071
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(0, &data), !0xddf2);
+    }
+
+    #[test]
+    fn test_tcp_connection() {
+        let (client, server) = addresses();
+        let mut writer = PcapWriter::tcp(Vec::new(), client, server).unwrap();
+        writer.write_client(b"USER anonymous\r\n").unwrap();
+        writer.write_server(b"331 ok\r\n").unwrap();
+        let data = writer.finish().unwrap();
+
+        assert_eq!(&data[0..4], &PCAP_MAGIC.to_le_bytes());
+
+        let records = records(&data);
+        // handshake + 2 messages + teardown
+        assert_eq!(records.len(), 8);
+
+        let tcp = ETHERNET_HEADER_LEN + IPV4_HEADER_LEN;
+        let seq = |record: &[u8]| u32::from_be_bytes(record[tcp + 4..tcp + 8].try_into().unwrap());
+        let ack = |record: &[u8]| u32::from_be_bytes(record[tcp + 8..tcp + 12].try_into().unwrap());
+
+        assert_eq!(records[0][tcp + 13], TCP_SYN);
+        assert_eq!(records[1][tcp + 13], TCP_SYN | TCP_ACK);
+        assert_eq!(ack(records[1]), CLIENT_ISN + 1);
+        assert_eq!(seq(records[3]), CLIENT_ISN + 1);
+        assert_eq!(&records[3][tcp + TCP_HEADER_LEN..], b"USER anonymous\r\n");
+        assert_eq!(seq(records[4]), SERVER_ISN + 1);
+        assert_eq!(ack(records[4]), CLIENT_ISN + 1 + 16);
+        assert_eq!(seq(records[5]), CLIENT_ISN + 1 + 16);
+        assert_eq!(records[5][tcp + 13], TCP_FIN | TCP_ACK);
+
+        for record in records {
+            // checksums over a valid header always yield zero
+            assert_eq!(checksum(0, &record[ETHERNET_HEADER_LEN..tcp]), 0);
+        }
+    }
+
+    #[test]
+    fn test_tcp_segmentation() {
+        let (client, server) = addresses();
+        let mut writer = PcapWriter::tcp(Vec::new(), client, server).unwrap();
+        writer.write_client(&[0x41; 2 * TCP_MSS + 1]).unwrap();
+        let data = writer.finish().unwrap();
+
+        assert_eq!(records(&data).len(), 3 + 3 + 3);
+    }
+
+    #[test]
+    fn test_udp_datagrams() {
+        let (client, server) = addresses();
+        let mut writer = PcapWriter::udp(Vec::new(), client, server).unwrap();
+        writer.write_client(b"ping").unwrap();
+        writer.write_server(b"pong").unwrap();
+        assert!(writer.write_client(&vec![0; UDP_MAX_PAYLOAD + 1]).is_err());
+        writer.write_client(&vec![0; UDP_MAX_PAYLOAD]).unwrap();
+        let data = writer.finish().unwrap();
+
+        let records = records(&data);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0][ETHERNET_HEADER_LEN + 9], IPPROTO_UDP);
+        assert_eq!(&records[1][ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + UDP_HEADER_LEN..], b"pong");
+        assert_eq!(records[2].len(), ETHERNET_HEADER_LEN + 65535);
+        assert_eq!(&records[2][ETHERNET_HEADER_LEN + 2..ETHERNET_HEADER_LEN + 4], &u16::MAX.to_be_bytes());
+    }
+
+    #[test]
+    fn test_ipv6() {
+        let client = "[fd00::1]:40000".parse().unwrap();
+        let server = "[fd00::2]:53".parse().unwrap();
+        let mut writer = PcapWriter::udp(Vec::new(), client, server).unwrap();
+        writer.write_client(b"ping").unwrap();
+        let data = writer.finish().unwrap();
+
+        let records = records(&data);
+        let ip = &records[0][ETHERNET_HEADER_LEN..];
+        assert_eq!(&records[0][12..14], &ETHERTYPE_IPV6.to_be_bytes());
+        assert_eq!(ip[0] >> 4, 6);
+        assert_eq!(u16::from_be_bytes([ip[4], ip[5]]) as usize, UDP_HEADER_LEN + 4);
+        assert_eq!(ip[6], IPPROTO_UDP);
+        assert_eq!(&ip[IPV6_HEADER_LEN + UDP_HEADER_LEN..], b"ping");
+
+        // the checksum over the pseudo header and the datagram yields zero
+        let udp = &ip[IPV6_HEADER_LEN..];
+        assert_eq!(checksum(pseudo_header_sum(&client, &server, IPPROTO_UDP, udp.len()), udp), 0);
+
+        let (client, _) = addresses();
+        assert!(PcapWriter::tcp(Vec::new(), client, server).is_err());
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8], big_endian: bool) -> Vec<u8> {
+        let to_bytes = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let len = 12 + body.len() as u32;
+
+        let mut block = Vec::new();
+        block.extend_from_slice(&to_bytes(block_type));
+        block.extend_from_slice(&to_bytes(len));
+        block.extend_from_slice(body);
+        block.extend_from_slice(&to_bytes(len));
+        block
+    }
+
+    #[test]
+    fn test_read_written_pcap() {
+        let (client, server) = addresses();
+        let mut writer = PcapWriter::tcp(Vec::new(), client, server).unwrap();
+        writer.write_client(b"USER anonymous\r\n").unwrap();
+        let data = writer.finish().unwrap();
+
+        let mut capture = Capture::from_bytes(&data).unwrap();
+        assert_eq!(capture.interfaces().len(), 1);
+        assert_eq!(capture.interfaces()[0].linktype(), LINKTYPE_ETHERNET as u16);
+        assert_eq!(capture.packets().len(), 7);
+
+        let records = records(&data);
+        let mut last = Duration::ZERO;
+
+        for record in records {
+            let packet = capture.next_packet().unwrap();
+            assert_eq!(packet.data(), record);
+            assert_eq!(packet.orig_len() as usize, record.len());
+            assert!(packet.timestamp() > last);
+            last = packet.timestamp();
+        }
+
+        assert!(capture.next_packet().is_none());
+    }
+
+    #[test]
+    fn test_read_pcap_big_endian_nanos() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&PCAP_MAGIC_NANOS.to_be_bytes());
+        data.extend_from_slice(&PCAP_VERSION_MAJOR.to_be_bytes());
+        data.extend_from_slice(&PCAP_VERSION_MINOR.to_be_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&PCAP_SNAPLEN.to_be_bytes());
+        data.extend_from_slice(&101u32.to_be_bytes());
+        data.extend_from_slice(&5u32.to_be_bytes());
+        data.extend_from_slice(&123456789u32.to_be_bytes());
+        data.extend_from_slice(&3u32.to_be_bytes());
+        data.extend_from_slice(&10u32.to_be_bytes());
+        data.extend_from_slice(b"abc");
+
+        let capture = Capture::from_bytes(&data).unwrap();
+        let packet = &capture.packets()[0];
+        assert_eq!(packet.linktype(), 101);
+        assert_eq!(packet.timestamp(), Duration::new(5, 123456789));
+        assert_eq!(packet.orig_len(), 10);
+        assert_eq!(packet.data(), b"abc");
+
+        // truncated record
+        assert!(Capture::from_bytes(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_read_pcapng() {
+        for big_endian in [false, true] {
+            let u16_bytes = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+            let u32_bytes = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+
+            let mut shb = Vec::new();
+            shb.extend_from_slice(&u32_bytes(PCAPNG_BYTE_ORDER_MAGIC));
+            shb.extend_from_slice(&u16_bytes(PCAPNG_VERSION_MAJOR));
+            shb.extend_from_slice(&u16_bytes(0));
+            shb.extend_from_slice(&[0xff; 8]);
+
+            // ethernet with default resolution
+            let mut idb0 = Vec::new();
+            idb0.extend_from_slice(&u16_bytes(1));
+            idb0.extend_from_slice(&u16_bytes(0));
+            idb0.extend_from_slice(&u32_bytes(0));
+
+            // raw ip with nanosecond resolution
+            let mut idb1 = Vec::new();
+            idb1.extend_from_slice(&u16_bytes(101));
+            idb1.extend_from_slice(&u16_bytes(0));
+            idb1.extend_from_slice(&u32_bytes(0));
+            idb1.extend_from_slice(&u16_bytes(PCAPNG_OPT_IF_TSRESOL));
+            idb1.extend_from_slice(&u16_bytes(1));
+            idb1.extend_from_slice(&[9, 0, 0, 0]);
+            idb1.extend_from_slice(&u16_bytes(PCAPNG_OPT_ENDOFOPT));
+            idb1.extend_from_slice(&u16_bytes(0));
+
+            let mut epb = Vec::new();
+            epb.extend_from_slice(&u32_bytes(1));
+            epb.extend_from_slice(&u32_bytes(0));
+            epb.extend_from_slice(&u32_bytes(1_500_000_000));
+            epb.extend_from_slice(&u32_bytes(5));
+            epb.extend_from_slice(&u32_bytes(5));
+            epb.extend_from_slice(b"hello\0\0\0");
+
+            let mut spb = Vec::new();
+            spb.extend_from_slice(&u32_bytes(3));
+            spb.extend_from_slice(b"abc\0");
+
+            let mut data = Vec::new();
+            data.extend(pcapng_block(PCAPNG_SHB, &shb, big_endian));
+            data.extend(pcapng_block(PCAPNG_IDB, &idb0, big_endian));
+            data.extend(pcapng_block(PCAPNG_IDB, &idb1, big_endian));
+            // unknown blocks are skipped
+            data.extend(pcapng_block(0x0bad, &[0; 4], big_endian));
+            data.extend(pcapng_block(PCAPNG_EPB, &epb, big_endian));
+            data.extend(pcapng_block(PCAPNG_SPB, &spb, big_endian));
+
+            let capture = Capture::from_bytes(&data).unwrap();
+            assert_eq!(capture.interfaces().len(), 2);
+            assert_eq!(capture.packets().len(), 2);
+
+            let packet = &capture.packets()[0];
+            assert_eq!(packet.interface(), 1);
+            assert_eq!(packet.linktype(), 101);
+            assert_eq!(packet.timestamp(), Duration::new(1, 500_000_000));
+            assert_eq!(packet.data(), b"hello");
+
+            let packet = &capture.packets()[1];
+            assert_eq!(packet.interface(), 0);
+            assert_eq!(packet.linktype(), 1);
+            assert_eq!(packet.data(), b"abc");
+        }
+    }
+
+    #[test]
+    fn test_read_garbage() {
+        assert!(Capture::from_bytes(b"").is_err());
+        assert!(Capture::from_bytes(b"GIF89a").is_err());
+        assert!(Capture::from_bytes(&pcapng_block(PCAPNG_SHB, &[0; 4], false)).is_err());
+    }
+}
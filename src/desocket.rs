@@ -0,0 +1,198 @@
+use crate::{extractor::StateExtractor, observer::StateObserver, response::ResponseObserver};
+use libafl_bolts::{
+    shmem::ShMem,
+    tuples::{Handle, MatchNameRef, RefIndexable},
+};
+use libafl::{
+    executors::{Executor, ExitKind, ForkserverExecutor, HasObservers, HasTimeout},
+    inputs::TargetBytesConverter,
+    observers::ObserversTuple,
+    state::HasExecutions,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Debug, Formatter},
+    hash::Hash,
+    time::Duration,
+};
+
+/// Derive the states of the target from the responses of the last run together with the index of their packet
+fn extract_states<RSHM, PS, E>(response_observer: &ResponseObserver<RSHM>, extractor: &mut E) -> Vec<(usize, PS)>
+where
+    RSHM: ShMem,
+    E: StateExtractor<PS>,
+{
+    response_observer.responses().filter_map(|(packet, response)| Some((packet, extractor.extract_state(response)?))).collect()
+}
+
+/// An executor for network servers that run under an AFL-style forkserver without real sockets.
+///
+/// The target gets started with a preload library that replaces the socket API ("desocketing").
+/// The [`ForkserverExecutor`] passes the serialized packet sequence to the target as its testcase,
+/// via shared memory if the target supports it.
+/// The preload library hands the packets to the target one by one and writes the responses
+/// into the buffer of a [`ResponseObserver`].
+///
+/// butterfly does not ship such a library. Existing desocketing libraries like libdesock
+/// don't know about the response buffer, so they have to be extended to write the layout
+/// that is described in the [`ResponseObserver`]. Without that the executor records no states. After every run the [`StateExtractor`] derives the states of the target from the responses,
+/// which get recorded in the [`StateObserver`].
+///
+/// For [`PacketBytesInput`](crate::PacketBytesInput) the serialized packet sequence is its wire encoding,
+/// other inputs choose the encoding with the `TargetBytesConverter` of the [`ForkserverExecutor`].
+///
+/// # Example
+/// ```
+/// let response_observer = ResponseObserver::new("responses", &mut shmem_provider, 65536)?;
+/// let state_observer = StateObserver::<u32>::new("state");
+/// let response_handle = response_observer.handle();
+/// let state_handle = state_observer.handle();
+///
+/// let forkserver = ForkserverExecutor::builder()
+///     .program("./server")
+///     // a desocketing library that writes the response buffer
+///     .env("LD_PRELOAD", "./libdesock-responses.so")
+///     .envs(response_observer.envs())
+///     .shmem_provider(&mut shmem_provider)
+///     .build(tuple_list!(response_observer, state_observer, edges_observer))?;
+///
+/// let executor = DesocketExecutor::new(forkserver, response_handle, state_handle, StatusCodeExtractor::new(3).unwrap());
+/// ```
+pub struct DesocketExecutor<I, OT, S, SHM, TC, RSHM, PS, E>
+where
+    RSHM: ShMem,
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    inner: ForkserverExecutor<I, OT, S, SHM, TC>,
+    response_handle: Handle<ResponseObserver<RSHM>>,
+    state_handle: Handle<StateObserver<PS>>,
+    extractor: E,
+}
+
+impl<I, OT, S, SHM, TC, RSHM, PS, E> DesocketExecutor<I, OT, S, SHM, TC, RSHM, PS, E>
+where
+    RSHM: ShMem,
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+    E: StateExtractor<PS>,
+{
+    /// Create a new DesocketExecutor.
+    ///
+    /// # Arguments
+    /// - `inner`: a [`ForkserverExecutor`] whose target got the environment variables of the [`ResponseObserver`]
+    /// - `response_observer`: the handle of the [`ResponseObserver`] in the observers of `inner`
+    /// - `state_observer`: the handle of the [`StateObserver`] in the observers of `inner`
+    /// - `extractor`: a [`StateExtractor`] that gets the state of the target from a response
+    pub fn new(inner: ForkserverExecutor<I, OT, S, SHM, TC>, response_observer: Handle<ResponseObserver<RSHM>>, state_observer: Handle<StateObserver<PS>>, extractor: E) -> Self {
+        Self {
+            inner,
+            response_handle: response_observer,
+            state_handle: state_observer,
+            extractor,
+        }
+    }
+
+    /// The forkserver executor that runs the target
+    pub fn inner(&self) -> &ForkserverExecutor<I, OT, S, SHM, TC> {
+        &self.inner
+    }
+}
+
+impl<I, OT, S, SHM, TC, RSHM, PS, E> Debug for DesocketExecutor<I, OT, S, SHM, TC, RSHM, PS, E>
+where
+    RSHM: ShMem,
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "DesocketExecutor {{ .. }}")
+    }
+}
+
+impl<I, OT, S, SHM, TC, RSHM, PS, E> HasObservers for DesocketExecutor<I, OT, S, SHM, TC, RSHM, PS, E>
+where
+    OT: ObserversTuple<I, S>,
+    RSHM: ShMem,
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    type Observers = OT;
+
+    fn observers(&self) -> RefIndexable<&OT, OT> {
+        self.inner.observers()
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut OT, OT> {
+        self.inner.observers_mut()
+    }
+}
+
+impl<I, OT, S, SHM, TC, RSHM, PS, E> HasTimeout for DesocketExecutor<I, OT, S, SHM, TC, RSHM, PS, E>
+where
+    RSHM: ShMem,
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    fn timeout(&self) -> Duration {
+        self.inner.timeout()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.inner.set_timeout(timeout);
+    }
+}
+
+impl<I, OT, S, SHM, TC, RSHM, PS, E, EM, Z> Executor<EM, I, S, Z> for DesocketExecutor<I, OT, S, SHM, TC, RSHM, PS, E>
+where
+    OT: ObserversTuple<I, S>,
+    S: HasExecutions,
+    SHM: ShMem,
+    TC: TargetBytesConverter<I>,
+    RSHM: ShMem,
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+    E: StateExtractor<PS>,
+{
+    fn run_target(&mut self, fuzzer: &mut Z, state: &mut S, mgr: &mut EM, input: &I) -> Result<ExitKind, Error> {
+        let exit_kind = self.inner.run_target(fuzzer, state, mgr, input)?;
+
+        // Both observers live in the same tuple, so extract the states before borrowing the state observer
+        let observers = self.inner.observers();
+        let response_observer = observers.get(&self.response_handle).ok_or_else(|| Error::key_not_found("ResponseObserver is not in the observers of the DesocketExecutor"))?;
+        let states = extract_states(response_observer, &mut self.extractor);
+
+        let mut observers = self.inner.observers_mut();
+        let state_observer = observers.get_mut(&self.state_handle).ok_or_else(|| Error::key_not_found("StateObserver is not in the observers of the DesocketExecutor"))?;
+
+        for (packet, state) in states {
+            state_observer.record_at(packet, &state);
+        }
+
+        Ok(exit_kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{extractor::StatusCodeExtractor, response::RESPONSE_BUFFER_ENV};
+    use libafl_bolts::shmem::{ShMemId, ShMemProvider, StdShMemProvider};
+
+    #[test]
+    fn test_extract_states() {
+        let mut provider = StdShMemProvider::new().unwrap();
+        let response_observer = ResponseObserver::new("responses", &mut provider, 256).unwrap();
+        let id = response_observer.envs().into_iter().find(|(key, _)| key == RESPONSE_BUFFER_ENV).unwrap().1;
+
+        // what the preload library writes: the banner and the responses to packets 0 and 2
+        let mut buf = 4u32.to_le_bytes().to_vec();
+
+        for (packet, response) in [(0u32, &b"220 Welcome\r\n"[..]), (0, b"331 Password required\r\n"), (2, b"garbage"), (2, b"230 Logged in\r\n")] {
+            buf.extend_from_slice(&packet.to_le_bytes());
+            buf.extend_from_slice(&(response.len() as u32).to_le_bytes());
+            buf.extend_from_slice(response);
+        }
+
+        let mut shmem = provider.shmem_from_id_and_size(ShMemId::from_string(&id), 256).unwrap();
+        shmem[..buf.len()].copy_from_slice(&buf);
+
+        let states = extract_states(&response_observer, &mut StatusCodeExtractor::new(3).unwrap());
+        assert_eq!(states, [(0, 220), (0, 331), (2, 230)]);
+    }
+}
@@ -12,6 +12,20 @@ pub static USER_STAT_NODES: &str = "statemachine_nodes";
 /// of the monitor with this key.
 pub static USER_STAT_EDGES: &str = "statemachine_edges";
 
+/// Key for user stats.
+///
+/// [`StateFeedback`](crate::StateFeedback) writes the number of edges that
+/// were taken at most 3 times into the user stats of the monitor with this key.
+/// Only present if the [`StateObserver`](crate::StateObserver) counts hits.
+pub static USER_STAT_COLD_EDGES: &str = "statemachine_cold_edges";
+
+/// Key for user stats.
+///
+/// [`StateFeedback`](crate::StateFeedback) writes the whole state-graph into the user stats
+/// of the monitor with this key whenever it changes. The value is an opaque string.
+/// [`HasStateStats`](crate::HasStateStats) merges the state-graphs of all instances with it.
+pub static USER_STAT_STATEGRAPH_DATA: &str = "statemachine_data";
+
 /// Key for user stats.
 ///
 /// [`StateFeedback`](crate::StateFeedback) writes a DOT representation
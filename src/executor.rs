@@ -0,0 +1,637 @@
+use crate::{extractor::StateExtractor, input::HasPackets, observer::StateObserver, scheduler::HasPacketKind};
+use libafl_bolts::tuples::{Handle, Handled, MatchNameRef, RefIndexable};
+use libafl::{
+    executors::{Executor, ExitKind, HasObservers, HasTimeout},
+    observers::ObserversTuple,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    hash::Hash,
+    io::{ErrorKind, Read, Write},
+    marker::PhantomData,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use std::{os::unix::net::UnixStream, path::PathBuf};
+
+/// Upper bound on the size of a single response
+const MAX_RESPONSE_LEN: usize = 65536;
+
+/// Where a [`NetworkExecutor`] sends its packets to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetworkTarget {
+    /// A TCP server
+    Tcp(SocketAddr),
+    /// A UDP server. Every packet is sent as a single datagram.
+    Udp(SocketAddr),
+    /// A server listening on a Unix domain socket of type `SOCK_STREAM`
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Display for NetworkTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            NetworkTarget::Tcp(addr) => write!(f, "tcp://{}", addr),
+            NetworkTarget::Udp(addr) => write!(f, "udp://{}", addr),
+            #[cfg(unix)]
+            NetworkTarget::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+/// An open connection to a [`NetworkTarget`]
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connection {
+    fn connect(target: &NetworkTarget, timeout: Duration) -> std::io::Result<Self> {
+        match target {
+            NetworkTarget::Tcp(addr) => {
+                let stream = TcpStream::connect_timeout(addr, timeout)?;
+                stream.set_nodelay(true)?;
+                Ok(Connection::Tcp(stream))
+            },
+            NetworkTarget::Udp(addr) => {
+                let local = match addr {
+                    SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                    SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                Ok(Connection::Udp(socket))
+            },
+            #[cfg(unix)]
+            NetworkTarget::Unix(path) => Ok(Connection::Unix(UnixStream::connect(path)?)),
+        }
+    }
+
+    fn set_timeout(&self, timeout: Duration) -> std::io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))
+            },
+            Connection::Udp(socket) => {
+                socket.set_read_timeout(Some(timeout))?;
+                socket.set_write_timeout(Some(timeout))
+            },
+            #[cfg(unix)]
+            Connection::Unix(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))
+            },
+        }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Tcp(stream) => stream.read(buf),
+            Connection::Udp(socket) => socket.recv(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Tcp(stream) => stream.write(buf),
+            Connection::Udp(socket) => socket.send(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.flush(),
+            Connection::Udp(_) => Ok(()),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Turns a packet into the bytes that a [`NetworkExecutor`] sends to the target.
+///
+/// Implemented for all closures of the form `FnMut(&P, &mut Vec<u8>)`.
+///
+/// # Example
+/// ```
+/// let serializer = |packet: &FTPCommand, buf: &mut Vec<u8>| {
+///     match packet {
+///         FTPCommand::USER(name) => buf.extend_from_slice(b"USER "),
+///         ...
+///     }
+/// };
+/// ```
+pub trait PacketSerializer<P> {
+    /// Append the serialized form of `packet` to `buf`
+    fn serialize_packet(&mut self, packet: &P, buf: &mut Vec<u8>);
+}
+
+impl<P, F> PacketSerializer<P> for F
+where
+    F: FnMut(&P, &mut Vec<u8>),
+{
+    fn serialize_packet(&mut self, packet: &P, buf: &mut Vec<u8>) {
+        self(packet, buf)
+    }
+}
+
+/// Decides which bytes that a [`NetworkExecutor`] receives make up a single response.
+pub trait ResponseReader {
+    /// Read one response from `conn` and append it to `buf`.
+    ///
+    /// Leaving `buf` empty signals that the target closed the connection.
+    /// Errors of the kind `WouldBlock` or `TimedOut` mean that no response arrived in time.
+    fn read_response(&mut self, conn: &mut dyn Read, buf: &mut Vec<u8>) -> std::io::Result<()>;
+}
+
+/// A [`ResponseReader`] that treats whatever a single `read()` returns as the response.
+///
+/// This is the right choice for UDP and for most request-response protocols
+/// where the target sends its reply in one go.
+#[derive(Clone, Debug, Default)]
+pub struct ReadOnce;
+
+impl ResponseReader for ReadOnce {
+    fn read_response(&mut self, conn: &mut dyn Read, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        let offset = buf.len();
+        buf.resize(offset + MAX_RESPONSE_LEN, 0);
+
+        let ret = conn.read(&mut buf[offset..]);
+        buf.truncate(offset + *ret.as_ref().unwrap_or(&0));
+
+        ret.map(|_| ())
+    }
+}
+
+/// A [`ResponseReader`] that reads until the response ends with a delimiter.
+///
+/// # Example
+/// ```
+/// // Text protocols like FTP or SMTP terminate their replies with "\r\n"
+/// let reader = ReadUntil::new(b"\r\n");
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReadUntil {
+    delimiter: Vec<u8>,
+}
+
+impl ReadUntil {
+    /// Create a new ReadUntil that stops reading after `delimiter`
+    pub fn new<D: AsRef<[u8]>>(delimiter: D) -> Self {
+        assert!(!delimiter.as_ref().is_empty(), "delimiter must not be empty");
+
+        Self {
+            delimiter: delimiter.as_ref().to_vec(),
+        }
+    }
+}
+
+impl ResponseReader for ReadUntil {
+    fn read_response(&mut self, conn: &mut dyn Read, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        let offset = buf.len();
+        let mut chunk = [0; 4096];
+
+        while !buf[offset..].ends_with(&self.delimiter) && buf.len() - offset < MAX_RESPONSE_LEN {
+            match conn.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => buf.extend_from_slice(&chunk[..len]),
+                // Return a partial response if the delimiter never arrives
+                Err(e) if buf.len() > offset && matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the [`NetworkExecutor`] does when an I/O operation fails
+fn exit_kind(error: &std::io::Error) -> Option<ExitKind> {
+    match error.kind() {
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe | ErrorKind::UnexpectedEof => Some(ExitKind::Crash),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => Some(ExitKind::Timeout),
+        _ => None,
+    }
+}
+
+/// Builds a [`NetworkExecutor`].
+///
+/// # Example
+/// ```
+/// let executor = NetworkExecutorBuilder::new(NetworkTarget::Tcp("127.0.0.1:2121".parse().unwrap()), &state_observer)
+///     .banner(true)
+///     .reader(ReadUntil::new(b"\r\n"))
+///     .packet_timeout(Duration::from_millis(100))
+///     .build(tuple_list!(state_observer), serializer, StatusCodeExtractor::new(3).unwrap());
+/// ```
+pub struct NetworkExecutorBuilder<PS, R = ReadOnce>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+    R: ResponseReader,
+{
+    handle: Handle<StateObserver<PS>>,
+    target: NetworkTarget,
+    reader: R,
+    packet_timeout: Duration,
+    timeout: Duration,
+    retries: usize,
+    backoff: Duration,
+    banner: bool,
+}
+
+impl<PS> NetworkExecutorBuilder<PS, ReadOnce>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new builder for an executor that talks to `target` and records
+    /// the states of the target in `state_observer`.
+    ///
+    /// The defaults are a [`ReadOnce`] reader, a packet timeout of 100ms, a global timeout of 1s
+    /// and 3 connection retries starting with a backoff of 10ms.
+    pub fn new(target: NetworkTarget, state_observer: &StateObserver<PS>) -> Self {
+        Self {
+            handle: state_observer.handle(),
+            target,
+            reader: ReadOnce,
+            packet_timeout: Duration::from_millis(100),
+            timeout: Duration::from_secs(1),
+            retries: 3,
+            backoff: Duration::from_millis(10),
+            banner: false,
+        }
+    }
+}
+
+impl<PS, R> NetworkExecutorBuilder<PS, R>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+    R: ResponseReader,
+{
+    /// Set how the executor splits the data it receives into responses
+    pub fn reader<R2>(self, reader: R2) -> NetworkExecutorBuilder<PS, R2>
+    where
+        R2: ResponseReader,
+    {
+        NetworkExecutorBuilder {
+            handle: self.handle,
+            target: self.target,
+            reader,
+            packet_timeout: self.packet_timeout,
+            timeout: self.timeout,
+            retries: self.retries,
+            backoff: self.backoff,
+            banner: self.banner,
+        }
+    }
+
+    /// Set how long to wait for the response to a single packet.
+    /// Packets without a response in time don't record a state.
+    pub fn packet_timeout(mut self, timeout: Duration) -> Self {
+        self.packet_timeout = timeout;
+        self
+    }
+
+    /// Set how long a whole run may take before it counts as [`ExitKind::Timeout`]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how often a failed connection attempt is retried and how long to wait before the first retry.
+    /// The wait time doubles with every retry.
+    pub fn retries(mut self, retries: usize, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set whether the target greets new connections with a banner before the first packet, like FTP or SMTP servers do.
+    ///
+    /// The state of the banner is recorded at packet index 0, the same index as the response to the first packet.
+    /// It is the state the target starts in, so the transition out of it is attributed to the first packet
+    /// and a new banner state makes the whole input the focus of mutations.
+    pub fn banner(mut self, banner: bool) -> Self {
+        self.banner = banner;
+        self
+    }
+
+    /// Create the executor.
+    ///
+    /// # Arguments
+    /// - `observers`: the observers of the executor. MUST contain the state observer passed to [`NetworkExecutorBuilder::new()`]
+    /// - `serializer`: a [`PacketSerializer`] that turns packets into bytes
+    /// - `extractor`: a [`StateExtractor`] that gets the state of the target from a response
+    pub fn build<OT, S, P, SER, E>(self, observers: OT, serializer: SER, extractor: E) -> NetworkExecutor<OT, S, PS, P, SER, R, E>
+    where
+        SER: PacketSerializer<P>,
+        E: StateExtractor<PS>,
+    {
+        NetworkExecutor {
+            observers,
+            handle: self.handle,
+            target: self.target,
+            serializer,
+            reader: self.reader,
+            extractor,
+            packet_timeout: self.packet_timeout,
+            timeout: self.timeout,
+            retries: self.retries,
+            backoff: self.backoff,
+            banner: self.banner,
+            buf: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// An executor for targets that receive their packets over the network.
+///
+/// For every run it opens a new connection to the target, sends the packets one by one
+/// and reads a response after each packet. The state of the target that the [`StateExtractor`]
+/// derives from a response gets recorded in the [`StateObserver`] automatically, together with the
+/// [kinds](HasPacketKind) of the packets so that the state-graph can show which kind of packet discovered a transition.
+///
+/// The exit kind of a run is
+/// - [`ExitKind::Crash`] if the target resets the connection, or if it closes the connection
+///   and does not accept a new one right after, because the process went down
+/// - [`ExitKind::Timeout`] if the run takes longer than the global timeout
+/// - [`ExitKind::Ok`] otherwise, also when the target closes the connection gracefully
+///
+/// If the target cannot be reached after all retries, the executor returns an error.
+/// If the target gets restarted by a supervisor after a crash, choose the retries so that
+/// they cover the time it takes to restart.
+///
+/// Use the [`NetworkExecutorBuilder`] to create it.
+pub struct NetworkExecutor<OT, S, PS, P, SER, R, E>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    observers: OT,
+    handle: Handle<StateObserver<PS>>,
+    target: NetworkTarget,
+    serializer: SER,
+    reader: R,
+    extractor: E,
+    packet_timeout: Duration,
+    timeout: Duration,
+    retries: usize,
+    backoff: Duration,
+    banner: bool,
+    buf: Vec<u8>,
+    phantom: PhantomData<(S, P)>,
+}
+
+impl<OT, S, PS, P, SER, R, E> NetworkExecutor<OT, S, PS, P, SER, R, E>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    fn connect(&self) -> std::io::Result<Connection> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+
+        loop {
+            match Connection::connect(&self.target, self.packet_timeout) {
+                Ok(conn) => return Ok(conn),
+                Err(e) if attempt >= self.retries => return Err(e),
+                Err(_) => {
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                },
+            }
+        }
+    }
+
+    /// Returns true if the target refuses new connections, which means that it is not running anymore
+    fn is_down(&self) -> bool {
+        if let NetworkTarget::Udp(_) = self.target {
+            return false;
+        }
+
+        match Connection::connect(&self.target, self.packet_timeout) {
+            Ok(_) => false,
+            Err(e) => matches!(e.kind(), ErrorKind::ConnectionRefused | ErrorKind::NotFound),
+        }
+    }
+}
+
+impl<OT, S, PS, P, SER, R, E> Debug for NetworkExecutor<OT, S, PS, P, SER, R, E>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "NetworkExecutor {{ target: {} }}", self.target)
+    }
+}
+
+impl<OT, S, PS, P, SER, R, E> HasObservers for NetworkExecutor<OT, S, PS, P, SER, R, E>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    type Observers = OT;
+
+    fn observers(&self) -> RefIndexable<&OT, OT> {
+        RefIndexable::from(&self.observers)
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut OT, OT> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+impl<OT, S, PS, P, SER, R, E> HasTimeout for NetworkExecutor<OT, S, PS, P, SER, R, E>
+where
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+{
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+}
+
+impl<OT, S, PS, P, SER, R, E, EM, I, Z> Executor<EM, I, S, Z> for NetworkExecutor<OT, S, PS, P, SER, R, E>
+where
+    OT: ObserversTuple<I, S>,
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+    I: HasPackets<P>,
+    P: HasPacketKind,
+    SER: PacketSerializer<P>,
+    R: ResponseReader,
+    E: StateExtractor<PS>,
+{
+    fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, input: &I) -> Result<ExitKind, Error> {
+        let deadline = Instant::now() + self.timeout;
+        let mut conn = self.connect().map_err(|e| Error::os_error(e, format!("Could not connect to {}", self.target)))?;
+        let state_observer = self.observers.get_mut(&self.handle).ok_or_else(|| Error::key_not_found("StateObserver is not in the observers of the NetworkExecutor"))?;
+        state_observer.record_packet_kinds(input.packets().iter().map(HasPacketKind::packet_kind));
+
+        // The banner is recorded at the index of the first packet
+        let banner = self.banner;
+        let responses = std::iter::once(None).filter(|_| banner).chain(input.packets().iter().map(Some));
+
+        for (i, packet) in responses.enumerate() {
+            let i = i.saturating_sub(banner as usize);
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Ok(ExitKind::Timeout);
+            }
+
+            conn.set_timeout(std::cmp::min(self.packet_timeout, deadline - now))?;
+
+            if let Some(packet) = packet {
+                self.buf.clear();
+                self.serializer.serialize_packet(packet, &mut self.buf);
+
+                if let Err(e) = conn.write_all(&self.buf) {
+                    return exit_kind(&e).ok_or_else(|| Error::os_error(e, format!("Could not send packet to {}", self.target)));
+                }
+            }
+
+            self.buf.clear();
+
+            match self.reader.read_response(&mut conn, &mut self.buf) {
+                // The target closed the connection, check whether it is still alive
+                Ok(()) if self.buf.is_empty() => {
+                    return Ok(if self.is_down() { ExitKind::Crash } else { ExitKind::Ok });
+                },
+                Ok(()) => {
+                    if let Some(state) = self.extractor.extract_state(&self.buf) {
+                        state_observer.record_at(i, &state);
+                    }
+                },
+                Err(e) => match exit_kind(&e) {
+                    // No response to this packet in time
+                    Some(ExitKind::Timeout) => continue,
+                    Some(exit_kind) => return Ok(exit_kind),
+                    None => return Err(Error::os_error(e, format!("Could not receive response from {}", self.target))),
+                },
+            }
+        }
+
+        Ok(ExitKind::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PacketBytesInput;
+    use libafl::{corpus::InMemoryCorpus, events::NopEventManager, inputs::BytesInput, state::StdState};
+    use libafl_bolts::{rands::StdRand, tuples::tuple_list};
+    use std::net::TcpListener;
+
+    fn serialize(packet: &BytesInput, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(packet.as_ref());
+    }
+
+    fn first_byte(response: &[u8]) -> Option<u8> {
+        response.first().copied()
+    }
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // echo server that greets with a banner and closes the connection on "Q"
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                stream.write_all(b"B\n").unwrap();
+                let mut buf = [0; 64];
+
+                loop {
+                    let len = stream.read(&mut buf).unwrap_or(0);
+
+                    if len == 0 || buf[0] == b'Q' {
+                        break;
+                    }
+
+                    stream.write_all(&buf[..len]).unwrap();
+                }
+            }
+        });
+
+        let state_observer = StateObserver::<u8>::new("state");
+        let handle = state_observer.handle();
+        let mut executor = NetworkExecutorBuilder::new(NetworkTarget::Tcp(addr), &state_observer).banner(true).reader(ReadUntil::new(b"\n")).timeout(Duration::from_secs(5)).build(tuple_list!(state_observer), serialize, first_byte);
+        let input = PacketBytesInput::from(vec![b"A\n".to_vec(), b"C\n".to_vec(), b"Q\n".to_vec(), b"X\n".to_vec()]);
+
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<PacketBytesInput>::new(), InMemoryCorpus::new(), &mut (), &mut ()).unwrap();
+
+        let exit_kind = executor.run_target(&mut (), &mut state, &mut NopEventManager::new(), &input).unwrap();
+        assert_eq!(exit_kind, ExitKind::Ok);
+
+        // the banner and the response to the first packet share index 0,
+        // the transition out of the banner is attributed to the first packet
+        let state_observer = &executor.observers()[&handle];
+        assert_eq!(state_observer.packets(), &[0, 0, 1]);
+        assert_eq!(state_observer.transitions().map(|(packet, _, _)| packet).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn test_target_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let input = PacketBytesInput::from(vec![b"A".to_vec()]);
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<PacketBytesInput>::new(), InMemoryCorpus::new(), &mut (), &mut ()).unwrap();
+
+        let state_observer = StateObserver::<u8>::new("state");
+        let mut executor = NetworkExecutorBuilder::new(NetworkTarget::Tcp(addr), &state_observer).packet_timeout(Duration::from_millis(500)).retries(1, Duration::from_millis(1)).build(tuple_list!(state_observer), serialize, first_byte);
+
+        // server that goes down after reading the first packet
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_exact(&mut [0; 1]).unwrap();
+            drop(listener);
+        });
+
+        // the input that brought the target down is the crash
+        let exit_kind = executor.run_target(&mut (), &mut state, &mut NopEventManager::new(), &input).unwrap();
+        assert_eq!(exit_kind, ExitKind::Crash);
+
+        // and the next input is not blamed for it
+        assert!(executor.run_target(&mut (), &mut state, &mut NopEventManager::new(), &input).is_err());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // server that never answers
+        std::thread::spawn(move || {
+            let (_stream, _) = listener.accept().unwrap();
+            std::thread::sleep(Duration::from_secs(2));
+        });
+
+        let state_observer = StateObserver::<u8>::new("state");
+        let mut executor = NetworkExecutorBuilder::new(NetworkTarget::Tcp(addr), &state_observer).packet_timeout(Duration::from_millis(50)).timeout(Duration::from_millis(200)).build(tuple_list!(state_observer), serialize, first_byte);
+        let input = PacketBytesInput::from(vec![b"A".to_vec(); 8]);
+
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<PacketBytesInput>::new(), InMemoryCorpus::new(), &mut (), &mut ()).unwrap();
+
+        let exit_kind = executor.run_target(&mut (), &mut state, &mut NopEventManager::new(), &input).unwrap();
+        assert_eq!(exit_kind, ExitKind::Timeout);
+    }
+}
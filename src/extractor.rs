@@ -0,0 +1,319 @@
+use libafl::Error;
+use regex::bytes::Regex;
+
+/// Offset basis of the 64-bit FNV-1a hash
+pub(crate) const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// Prime of the 64-bit FNV-1a hash
+pub(crate) const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Derives the state of the target from a response of the target.
+///
+/// Executors call this for every response and pass the result on to [`StateObserver::record()`](crate::StateObserver::record).
+/// Returning `None` means that the response carries no state information.
+///
+/// Implemented for all closures of the form `FnMut(&[u8]) -> Option<PS>`.
+///
+/// Ready implementations for common protocols are
+/// - [`StatusCodeExtractor`] for FTP, SMTP, POP3 and other protocols where responses start with a decimal status code
+/// - [`StatusLineExtractor`] for HTTP, RTSP, SIP and other protocols with an HTTP-like status line
+/// - [`BinaryFieldExtractor`] for binary protocols that have a message type or status at a fixed offset
+/// - [`RegexExtractor`] for everything else that can be described by a regular expression
+/// - [`ShapeExtractor`] as a fallback if nothing about the protocol is known
+pub trait StateExtractor<PS> {
+    /// Return the state of the target that `response` indicates
+    fn extract_state(&mut self, response: &[u8]) -> Option<PS>;
+}
+
+impl<PS, F> StateExtractor<PS> for F
+where
+    F: FnMut(&[u8]) -> Option<PS>,
+{
+    fn extract_state(&mut self, response: &[u8]) -> Option<PS> {
+        self(response)
+    }
+}
+
+/// Parse the decimal number at the start of `buf` and return it together with its number of digits
+fn parse_decimal(buf: &[u8]) -> (u32, usize) {
+    let len = buf.iter().take_while(|c| c.is_ascii_digit()).count();
+    let value = buf[..len].iter().fold(0u32, |value, c| value.wrapping_mul(10).wrapping_add((c - b'0') as u32));
+    (value, len)
+}
+
+/// Extracts the leading decimal status code of a response like `220 Service ready`.
+///
+/// The state is the status code as a `u32`.
+///
+/// # Example
+/// ```
+/// // FTP and SMTP replies have 3-digit status codes
+/// let extractor = StatusCodeExtractor::new(3).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct StatusCodeExtractor {
+    digits: usize,
+}
+
+impl StatusCodeExtractor {
+    /// Create a new StatusCodeExtractor for status codes with exactly `digits` digits.
+    /// `digits` must be between 1 and 9 so that every status code fits into a `u32`.
+    pub fn new(digits: usize) -> Result<Self, Error> {
+        if !(1..=9).contains(&digits) {
+            return Err(Error::illegal_argument(format!("Status codes must have between 1 and 9 digits, got {}", digits)));
+        }
+
+        Ok(Self {
+            digits,
+        })
+    }
+}
+
+impl StateExtractor<u32> for StatusCodeExtractor {
+    fn extract_state(&mut self, response: &[u8]) -> Option<u32> {
+        let (code, len) = parse_decimal(response);
+
+        // The status code must not be followed by more digits
+        if len == self.digits {
+            Some(code)
+        } else {
+            None
+        }
+    }
+}
+
+/// Extracts the status code of an HTTP-like status line like `HTTP/1.1 404 Not Found` or `RTSP/1.0 200 OK`.
+///
+/// The state is the status code as a `u32`. The protocol name is not checked.
+#[derive(Clone, Debug, Default)]
+pub struct StatusLineExtractor;
+
+impl StatusLineExtractor {
+    /// Create a new StatusLineExtractor
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl StateExtractor<u32> for StatusLineExtractor {
+    fn extract_state(&mut self, response: &[u8]) -> Option<u32> {
+        let protocol_len = response.iter().position(|c| *c == b' ')?;
+
+        if !response[..protocol_len].contains(&b'/') {
+            return None;
+        }
+
+        let (code, len) = parse_decimal(&response[protocol_len + 1..]);
+
+        if len == 3 {
+            Some(code)
+        } else {
+            None
+        }
+    }
+}
+
+/// Extracts an unsigned integer at a fixed offset of a binary response.
+///
+/// The state is the value of the field as a `u64`.
+/// Responses that are too short to contain the field have no state.
+///
+/// # Example
+/// ```
+/// // A 16-bit big-endian message type after a 4-byte length
+/// let extractor = BinaryFieldExtractor::new(4, 2, true).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct BinaryFieldExtractor {
+    offset: usize,
+    width: usize,
+    big_endian: bool,
+}
+
+impl BinaryFieldExtractor {
+    /// Create a new BinaryFieldExtractor for a field of `width` bytes at `offset`.
+    /// `width` must be between 1 and 8 and the field must end within the address space.
+    pub fn new(offset: usize, width: usize, big_endian: bool) -> Result<Self, Error> {
+        if !(1..=8).contains(&width) {
+            return Err(Error::illegal_argument(format!("Width of a binary field must be between 1 and 8 bytes, got {}", width)));
+        }
+
+        if offset.checked_add(width).is_none() {
+            return Err(Error::illegal_argument(format!("Binary field of {} bytes at offset {} is out of bounds", width, offset)));
+        }
+
+        Ok(Self {
+            offset,
+            width,
+            big_endian,
+        })
+    }
+}
+
+impl StateExtractor<u64> for BinaryFieldExtractor {
+    fn extract_state(&mut self, response: &[u8]) -> Option<u64> {
+        let field = response.get(self.offset..self.offset.checked_add(self.width)?)?;
+        let mut bytes = [0; 8];
+
+        if self.big_endian {
+            bytes[8 - self.width..].copy_from_slice(field);
+            Some(u64::from_be_bytes(bytes))
+        } else {
+            bytes[..self.width].copy_from_slice(field);
+            Some(u64::from_le_bytes(bytes))
+        }
+    }
+}
+
+/// Extracts the first capture group of a regular expression.
+///
+/// The state is the content of the capture group as a `Vec<u8>`.
+/// Responses that don't match have no state.
+///
+/// # Example
+/// ```
+/// // The reply code of an IMAP server after the tag
+/// let extractor = RegexExtractor::new(r"^\S+ (OK|NO|BAD)").unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct RegexExtractor {
+    regex: Regex,
+}
+
+impl RegexExtractor {
+    /// Create a new RegexExtractor from a regular expression with at least one capture group
+    pub fn new(regex: &str) -> Result<Self, Error> {
+        let regex = Regex::new(regex).map_err(|err| Error::illegal_argument(format!("Invalid regular expression: {}", err)))?;
+
+        // Group 0 is the whole match
+        if regex.captures_len() < 2 {
+            return Err(Error::illegal_argument("The regular expression needs at least one capture group"));
+        }
+
+        Ok(Self {
+            regex,
+        })
+    }
+}
+
+impl StateExtractor<Vec<u8>> for RegexExtractor {
+    fn extract_state(&mut self, response: &[u8]) -> Option<Vec<u8>> {
+        let captures = self.regex.captures(response)?;
+        Some(captures.get(1)?.as_bytes().to_vec())
+    }
+}
+
+/// Classes of bytes that make up the shape of a response
+fn byte_class(byte: u8) -> u8 {
+    match byte {
+        b'0'..=b'9' => 0,
+        b'a'..=b'z' | b'A'..=b'Z' => 1,
+        b' ' | b'\t' => 2,
+        b'\r' | b'\n' => 3,
+        // Keep punctuation because it often carries the structure of a response
+        byte if byte.is_ascii_punctuation() => byte,
+        _ => 4,
+    }
+}
+
+/// Extracts the "shape" of a response as a fallback if nothing about the protocol is known.
+///
+/// Every byte is replaced by its class (digit, letter, whitespace, line break, binary) while
+/// punctuation is kept as-is. Consecutive bytes of the same class are merged
+/// and the result is hashed. So `331 Password required for alice` and
+/// `331 Password required for bob` have the same shape while `230 Logged in` has a different one.
+/// Numbers are part of the shape only by their presence, not their value.
+///
+/// The state is the 64-bit FNV-1a hash of the classes. It does not depend on the platform
+/// or the version of butterfly, so it can be stored and compared across runs.
+/// Empty responses have no state.
+///
+/// This produces many more states than a protocol-specific extractor,
+/// so only use it if there is no better option.
+#[derive(Clone, Debug)]
+pub struct ShapeExtractor {
+    max_len: usize,
+}
+
+impl ShapeExtractor {
+    /// Create a new ShapeExtractor that only looks at the first `max_len` bytes of a response
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+        }
+    }
+}
+
+impl StateExtractor<u64> for ShapeExtractor {
+    fn extract_state(&mut self, response: &[u8]) -> Option<u64> {
+        if response.is_empty() {
+            return None;
+        }
+
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut last = None;
+
+        for class in response.iter().take(self.max_len).map(|byte| byte_class(*byte)) {
+            if last != Some(class) {
+                hash = (hash ^ class as u64).wrapping_mul(FNV_PRIME);
+                last = Some(class);
+            }
+        }
+
+        Some(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_code() {
+        let mut extractor = StatusCodeExtractor::new(3).unwrap();
+        assert_eq!(extractor.extract_state(b"220 Service ready\r\n"), Some(220));
+        assert_eq!(extractor.extract_state(b"250-PIPELINING\r\n"), Some(250));
+        assert_eq!(extractor.extract_state(b"2200 Service ready\r\n"), None);
+        assert_eq!(extractor.extract_state(b"ERROR\r\n"), None);
+        assert!(StatusCodeExtractor::new(0).is_err());
+        assert!(StatusCodeExtractor::new(10).is_err());
+
+        let mut extractor = StatusLineExtractor::new();
+        assert_eq!(extractor.extract_state(b"HTTP/1.1 404 Not Found\r\n"), Some(404));
+        assert_eq!(extractor.extract_state(b"RTSP/1.0 200 OK\r\n"), Some(200));
+        assert_eq!(extractor.extract_state(b"200 OK\r\n"), None);
+    }
+
+    #[test]
+    fn test_binary_field() {
+        let response = [0x00, 0x00, 0x00, 0x08, 0x12, 0x34];
+        assert_eq!(BinaryFieldExtractor::new(4, 2, true).unwrap().extract_state(&response), Some(0x1234));
+        assert_eq!(BinaryFieldExtractor::new(4, 2, false).unwrap().extract_state(&response), Some(0x3412));
+        assert_eq!(BinaryFieldExtractor::new(5, 2, true).unwrap().extract_state(&response), None);
+        assert!(BinaryFieldExtractor::new(0, 9, true).is_err());
+        assert!(BinaryFieldExtractor::new(usize::MAX, 1, true).is_err());
+    }
+
+    #[test]
+    fn test_regex() {
+        let mut extractor = RegexExtractor::new(r"^\S+ (OK|NO|BAD)").unwrap();
+        assert_eq!(extractor.extract_state(b"a001 OK LOGIN completed\r\n"), Some(b"OK".to_vec()));
+        assert_eq!(extractor.extract_state(b"* 18 EXISTS\r\n"), None);
+        assert!(RegexExtractor::new(r"^\S+ OK").is_err());
+        assert!(RegexExtractor::new(r"(").is_err());
+    }
+
+    #[test]
+    fn test_shape() {
+        let mut extractor = ShapeExtractor::new(64);
+        let a = extractor.extract_state(b"331 Password required for alice\r\n");
+        let b = extractor.extract_state(b"331 Password required for bob\r\n");
+        let c = extractor.extract_state(b"230 Logged in.\r\n");
+        assert!(a.is_some());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        // the hash is stable
+        assert_eq!(c, Some(0x7a18ca73b5916ebc));
+        assert_eq!(extractor.extract_state(b""), None);
+    }
+}
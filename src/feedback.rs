@@ -1,13 +1,19 @@
 use crate::{
-    event::{USER_STAT_EDGES, USER_STAT_NODES},
-    observer::StateObserver,
+    event::{USER_STAT_COLD_EDGES, USER_STAT_EDGES, USER_STAT_NODES, USER_STAT_STATEGRAPH_DATA},
+    extractor::{FNV_OFFSET_BASIS, FNV_PRIME},
+    observer::{transitions, StateGraphMetadata, StateNovelty, StateObserver},
 };
 
 #[cfg(feature = "graphviz")]
 use crate::event::USER_STAT_STATEGRAPH;
 
-use libafl_bolts::Named;
+use libafl_bolts::{
+    tuples::{Handle, Handled, MatchNameRef},
+    Named,
+};
 use libafl::{
+    common::{HasMetadata, HasNamedMetadata},
+    corpus::Testcase,
     events::{Event, EventFirer},
     executors::ExitKind,
     feedbacks::{Feedback, StateInitializer},
@@ -19,17 +25,120 @@ use libafl::{
 };
 use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, cmp::Eq};
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::marker::PhantomData;
 
+/// Testcase metadata that stores why the [`StateFeedback`] considered an input interesting.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct StateNoveltyMetadata {
+    novelty: StateNovelty,
+    packet: Option<usize>,
+}
+
+impl StateNoveltyMetadata {
+    /// Create new metadata for an input that produced `novelty`, starting at packet `packet`
+    pub fn new(novelty: StateNovelty, packet: Option<usize>) -> Self {
+        Self {
+            novelty,
+            packet,
+        }
+    }
+
+    /// The kinds of novelty that the input produced
+    pub fn novelty(&self) -> StateNovelty {
+        self.novelty
+    }
+
+    /// Index of the first packet that led to a new state or transition.
+    /// The packets before it are needed to reach the new state.
+    pub fn packet(&self) -> Option<usize> {
+        self.packet
+    }
+}
+
+/// Testcase metadata with the states that the [`StateObserver`] recorded while executing the input.
+///
+/// Every state is given by its id in the [`StateGraphMetadata`] together with the
+/// index of the packet at which it was recorded.
+/// The [`StateFeedback`] attaches it to every new testcase.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StateTraceMetadata {
+    states: Vec<u32>,
+    packets: Vec<usize>,
+}
+
+impl StateTraceMetadata {
+    /// Create a new trace from state ids and the packet index at which each state was recorded.
+    /// Returns an error if the number of states and packet indices differ.
+    pub fn new(states: Vec<u32>, packets: Vec<usize>) -> Result<Self, Error> {
+        if states.len() != packets.len() {
+            return Err(Error::illegal_argument(format!("Got {} states but {} packet indices", states.len(), packets.len())));
+        }
+
+        Ok(Self {
+            states,
+            packets,
+        })
+    }
+
+    /// The ids of the states in the order they were recorded
+    pub fn states(&self) -> &[u32] {
+        &self.states
+    }
+
+    /// The packet index at which each state was recorded
+    pub fn packets(&self) -> &[usize] {
+        &self.packets
+    }
+
+    /// Iterate over all (packet index, state id) pairs
+    pub fn iter(&self) -> impl Iterator<Item = (usize, u32)> + '_ {
+        self.packets.iter().copied().zip(self.states.iter().copied())
+    }
+
+    /// Iterate over all transitions as (packet index, from, to) where
+    /// the packet is the one that moved the target from state `from` to state `to`
+    pub fn transitions(&self) -> impl Iterator<Item = (usize, u32, u32)> + '_ {
+        transitions(&self.states, &self.packets)
+    }
+
+    /// Number of recorded states
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns true if no states were recorded
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
 /// Determines that an input is interesting if it led to new states or transitions in the previous run.
+///
+/// By default new states and new transitions between two different states are interesting.
+/// New self-loops and, if the [`StateObserver`] counts hits, new hit count buckets can be
+/// enabled with [`StateFeedback::with_novelty()`]. They keep many more testcases.
+///
+/// The novelty that made an input interesting is attached to its testcase
+/// as [`StateNoveltyMetadata`] and the states that the input went through
+/// as [`StateTraceMetadata`].
+///
+/// The feedback inserts the [`StateGraphMetadata`] of its observer into the state
+/// when the state is created and reports the size of the graph to the monitor.
+/// Observers received from other fuzzer instances are merged into this graph first,
+/// so the instances of a [`Launcher`](libafl::events::Launcher) share their states and transitions.
+/// See the limits of this in the docs of [`StateObserver`].
 #[derive(Debug)]
 pub struct StateFeedback<PS>
 where
     PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
 {
-    observer_name: String,
+    observer_handle: Handle<StateObserver<PS>>,
+    novelty: StateNovelty,
+    last_novelty: StateNovelty,
+    last_packet: Option<usize>,
     phantom: PhantomData<PS>,
 }
 
@@ -37,10 +146,19 @@ impl<PS> StateFeedback<PS>
 where
     PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
 {
-    /// Create a new StateFeedback from a StateObserver
+    /// Create a new StateFeedback from a StateObserver that considers new states and transitions interesting
     pub fn new(observer: &StateObserver<PS>) -> Self {
+        Self::with_novelty(observer, StateNovelty::NEW_NODE | StateNovelty::NEW_EDGE)
+    }
+
+    /// Create a new StateFeedback from a StateObserver that considers only
+    /// the kinds of novelty in `novelty` interesting.
+    pub fn with_novelty(observer: &StateObserver<PS>, novelty: StateNovelty) -> Self {
         Self {
-            observer_name: observer.name().to_string(),
+            observer_handle: observer.handle(),
+            novelty,
+            last_novelty: StateNovelty::NONE,
+            last_packet: None,
             phantom: PhantomData,
         }
     }
@@ -58,11 +176,13 @@ where
 impl<PS, S> StateInitializer<S> for StateFeedback<PS>
 where
     PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    S: HasNamedMetadata,
 {
     /// Initializes the feedback state.
     /// This method is called after that the `State` is created.
-    fn init_state(&mut self, _state: &mut S) -> Result<(), Error> {
-        Ok(()) // TODO: BUG: does this need anything else?
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.named_metadata_or_insert_with(self.observer_handle.name(), StateGraphMetadata::default);
+        Ok(())
     }
 }
 
@@ -72,7 +192,7 @@ where
     PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
 {
     fn observer_name(&self) -> &str {
-        &self.observer_name
+        self.observer_handle.name()
     }
 }
 */
@@ -82,17 +202,44 @@ where
     EM: EventFirer<I, S>,
     I: Input,
     OT: ObserversTuple<I, S>,
-    S: HasClientPerfMonitor,
+    S: HasClientPerfMonitor + HasNamedMetadata,
     PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
 {
     fn is_interesting(&mut self, state: &mut S, mgr: &mut EM, _input: &I, observers: &OT, _exit_kind: &ExitKind) -> Result<bool, Error>
     {
-        let state_observer = observers.match_name::<StateObserver<PS>>(&self.observer_name).unwrap();
+        let state_observer = observers.get(&self.observer_handle).unwrap();
+
+        let (novelty, packet) = if state_observer.is_remote() {
+            let graph = state.named_metadata_or_insert_with(self.observer_handle.name(), StateGraphMetadata::default);
+            state_observer.merge(graph)?
+        } else {
+            (state_observer.novelty(), state_observer.first_new_packet())
+        };
+
+        self.last_novelty = novelty & self.novelty;
+        self.last_packet = packet;
+        let ret = !self.last_novelty.is_empty();
 
-        let ret = state_observer.had_new_transitions();
+        // The monitor merges the state-graphs of all instances, so send every change of the graph,
+        // even if it does not make the input interesting.
+        if !novelty.is_empty() {
+            let data = state.named_metadata::<StateGraphMetadata>(self.observer_handle.name())?.encode()?;
+
+            mgr.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: Cow::Borrowed(USER_STAT_STATEGRAPH_DATA),
+                    value: UserStats::new(UserStatsValue::String(Cow::Owned(data)), AggregatorOps::None),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
 
         if ret {
-            let (nodes, edges) = state_observer.info();
+            let graph = state.named_metadata::<StateGraphMetadata>(self.observer_handle.name())?;
+            let (nodes, edges, cold_edges) = (graph.nodes(), graph.edges(), graph.cold_edges());
+            #[cfg(feature = "graphviz")]
+            let dot = graph.to_dot();
 
             mgr.fire(
                 state,
@@ -111,13 +258,24 @@ where
                 },
             )?;
 
+            if let Some(cold_edges) = cold_edges {
+                mgr.fire(
+                    state,
+                    Event::UpdateUserStats {
+                        name: Cow::Borrowed(USER_STAT_COLD_EDGES),
+                        value: UserStats::new(UserStatsValue::Number(cold_edges as u64), AggregatorOps::Avg),
+                        phantom: PhantomData,
+                    },
+                )?;
+            }
+
             #[cfg(feature = "graphviz")]
             {
                 mgr.fire(
                     state,
                     Event::UpdateUserStats {
                         name: Cow::Borrowed(USER_STAT_STATEGRAPH),
-                        value: UserStats::new(UserStatsValue::String(Cow::Owned(state_observer.get_statemachine())), AggregatorOps::None),
+                        value: UserStats::new(UserStatsValue::String(Cow::Owned(dot)), AggregatorOps::None),
                         phantom: PhantomData,
                     },
                 )?;
@@ -129,7 +287,253 @@ where
 
     #[inline]
     fn last_result(&self) -> Result<bool, Error> {
-        Ok(false)
+        Ok(!self.last_novelty.is_empty())
     }
 
+    fn append_metadata(&mut self, state: &mut S, _manager: &mut EM, observers: &OT, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        let state_observer = observers.get(&self.observer_handle).unwrap();
+
+        testcase.add_metadata(StateTraceMetadata::new(state_observer.local_path(state)?, state_observer.packets().to_vec())?);
+
+        if !self.last_novelty.is_empty() {
+            testcase.add_metadata(StateNoveltyMetadata::new(self.last_novelty, self.last_packet));
+        }
+
+        Ok(())
+    }
+}
+
+/// The n-grams and paths that a [`StatePathFeedback`] has already seen.
+///
+/// Stored as named metadata in the state.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StatePathMetadata {
+    ngrams: HashSet<u64>,
+    paths: HashSet<u64>,
+}
+
+impl StatePathMetadata {
+    /// Number of unique state n-grams
+    pub fn ngrams(&self) -> usize {
+        self.ngrams.len()
+    }
+
+    /// Number of unique state paths
+    pub fn paths(&self) -> usize {
+        self.paths.len()
+    }
+}
+
+/// Hash a sequence of state ids with 64-bit FNV-1a.
+///
+/// Unlike the `DefaultHasher` the result is the same on every platform and in every version of Rust,
+/// so the hashes in a stored [`StatePathMetadata`] stay valid.
+fn hash_states(states: &[u32]) -> u64 {
+    states.iter().flat_map(|state| state.to_le_bytes()).fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Determines that an input is interesting if the sequence of states it went through is new.
+///
+/// The [`StateObserver`] records the path of states that the target went through in a run.
+/// An input is interesting if its path contains an n-gram, a sequence of `n` consecutive states,
+/// that has never been seen before. A path shorter than `n` counts as a single n-gram.
+/// This is similar to the state-sequence novelty of AFLNet and keeps finding new inputs
+/// after the edges of a small state-graph have all been discovered.
+///
+/// Optionally an input is also interesting if the hash of its whole path is new.
+/// This is off by default for `n > 0` because every new number of iterations of a loop
+/// in the state-graph is a new path, which floods the corpus for protocols with loops.
+/// A `n` of 0 only considers whole paths.
+///
+/// Use it in addition to the [`StateFeedback`], e.g. with `feedback_or!(StateFeedback::new(&observer), StatePathFeedback::new(&observer, 3))`.
+/// The seen n-grams and paths are stored as [`StatePathMetadata`] in the state.
+#[derive(Debug)]
+pub struct StatePathFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    name: Cow<'static, str>,
+    observer_handle: Handle<StateObserver<PS>>,
+    n: usize,
+    full_path: bool,
+    last_result: bool,
+    phantom: PhantomData<PS>,
+}
+
+impl<PS> StatePathFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new StatePathFeedback from a StateObserver that looks at n-grams of `n` states
+    pub fn new(observer: &StateObserver<PS>, n: usize) -> Self {
+        Self {
+            name: Cow::Owned(format!("StatePathFeedback_{}_{}", observer.name(), n)),
+            observer_handle: observer.handle(),
+            n,
+            full_path: n == 0,
+            last_result: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Set whether a new whole path makes an input interesting, in addition to new n-grams
+    pub fn with_full_path(mut self, full_path: bool) -> Self {
+        self.full_path = full_path;
+        self
+    }
+
+    /// Insert the n-grams and the hash of `path` into `metadata`, returns true if any of them were new
+    fn add_path(&self, metadata: &mut StatePathMetadata, path: &[u32]) -> bool {
+        if path.is_empty() {
+            return false;
+        }
+
+        let mut ret = metadata.paths.insert(hash_states(path)) && self.full_path;
+
+        if self.n > path.len() {
+            // A path shorter than n counts as a single, shorter n-gram.
+            // It can't collide with a full n-gram because the lengths differ.
+            ret |= metadata.ngrams.insert(hash_states(path));
+        } else if self.n > 0 {
+            for ngram in path.windows(self.n) {
+                ret |= metadata.ngrams.insert(hash_states(ngram));
+            }
+        }
+
+        ret
+    }
+}
+
+impl<PS> Named for StatePathFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<PS, S> StateInitializer<S> for StatePathFeedback<PS>
+where
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+    S: HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.named_metadata_or_insert_with(&self.name, StatePathMetadata::default);
+        Ok(())
+    }
+}
+
+impl<EM, I, OT, S, PS> Feedback<EM, I, OT, S> for StatePathFeedback<PS>
+where
+    I: Input,
+    OT: ObserversTuple<I, S>,
+    S: HasNamedMetadata,
+    PS: Debug + Clone + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn is_interesting(&mut self, state: &mut S, _mgr: &mut EM, _input: &I, observers: &OT, _exit_kind: &ExitKind) -> Result<bool, Error> {
+        let state_observer = observers.get(&self.observer_handle).unwrap();
+        let path = state_observer.local_path(state)?;
+        let metadata = state.named_metadata_or_insert_with(&self.name, StatePathMetadata::default);
+
+        self.last_result = self.add_path(metadata, &path);
+        Ok(self.last_result)
+    }
+
+    #[inline]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(self.last_result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PacketBytesInput;
+    use libafl::{corpus::InMemoryCorpus, events::NopEventManager, observers::Observer, state::StdState};
+    use libafl_bolts::{rands::StdRand, tuples::tuple_list};
+
+    #[test]
+    fn test_trace_metadata() {
+        let mut observers = tuple_list!(StateObserver::<u32>::new("state"));
+        let mut feedback = StateFeedback::new(&observers.0);
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<PacketBytesInput>::new(), InMemoryCorpus::new(), &mut feedback, &mut ()).unwrap();
+        let mut mgr = NopEventManager::new();
+        let input = PacketBytesInput::from(vec![b"USER".to_vec(), b"PASS".to_vec()]);
+
+        observers.0.pre_exec(&mut state, &input).unwrap();
+        for (packet, s) in [(0, 220), (1, 331), (1, 230)] {
+            observers.0.record_at(packet, &s);
+        }
+        observers.0.post_exec(&mut state, &input, &ExitKind::Ok).unwrap();
+
+        assert!(feedback.is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok).unwrap());
+        let mut testcase = Testcase::new(input);
+        feedback.append_metadata(&mut state, &mut mgr, &observers, &mut testcase).unwrap();
+
+        let trace = testcase.metadata::<StateTraceMetadata>().unwrap();
+        assert_eq!(trace.iter().collect::<Vec<_>>(), [(0, 0), (1, 1), (1, 2)]);
+        assert_eq!(trace.transitions().collect::<Vec<_>>(), [(1, 0, 1), (1, 1, 2)]);
+        let novelty = testcase.metadata::<StateNoveltyMetadata>().unwrap();
+        assert!(novelty.novelty().contains(StateNovelty::NEW_EDGE));
+        assert_eq!(novelty.packet(), Some(0));
+
+        assert!(StateTraceMetadata::new(vec![0, 1], vec![0]).is_err());
+    }
+
+    #[test]
+    fn test_path_novelty() {
+        let observer = StateObserver::<u32>::new("state");
+        let feedback = StatePathFeedback::new(&observer, 2);
+        let mut metadata = StatePathMetadata::default();
+
+        assert!(!feedback.add_path(&mut metadata, &[]));
+        assert!(feedback.add_path(&mut metadata, &[0, 1, 2]));
+        assert!(!feedback.add_path(&mut metadata, &[0, 1, 2]));
+        // new path but no new 2-gram
+        assert!(!feedback.add_path(&mut metadata, &[0, 1]));
+        assert_eq!(metadata.ngrams(), 2);
+        // new 2-gram (2, 0)
+        assert!(feedback.add_path(&mut metadata, &[1, 2, 0, 1]));
+        assert_eq!(metadata.ngrams(), 3);
+        assert_eq!(metadata.paths(), 3);
+
+        let feedback = StatePathFeedback::new(&observer, 2).with_full_path(true);
+        let mut metadata = StatePathMetadata::default();
+        assert!(feedback.add_path(&mut metadata, &[0, 1, 2]));
+        assert!(feedback.add_path(&mut metadata, &[0, 1]));
+
+        let feedback = StatePathFeedback::new(&observer, 0);
+        let mut metadata = StatePathMetadata::default();
+        assert!(feedback.add_path(&mut metadata, &[0, 1, 2]));
+        assert!(feedback.add_path(&mut metadata, &[0, 1]));
+        assert_eq!(metadata.ngrams(), 0);
+
+        // paths shorter than n are a single n-gram
+        let feedback = StatePathFeedback::new(&observer, 3);
+        let mut metadata = StatePathMetadata::default();
+        assert!(feedback.add_path(&mut metadata, &[0, 1]));
+        assert!(!feedback.add_path(&mut metadata, &[0, 1]));
+        assert!(feedback.add_path(&mut metadata, &[0]));
+        assert_eq!(metadata.ngrams(), 2);
+
+        // the hash is stable
+        assert_eq!(hash_states(&[1, 2]), 0xc9c28939c99668c6);
+    }
+
+    #[test]
+    fn test_self_loops() {
+        let observer = StateObserver::<u32>::new("state");
+        let feedback = StatePathFeedback::new(&observer, 3);
+        let mut metadata = StatePathMetadata::default();
+
+        // A -> B -> B -> ... -> B with more and more iterations of the self-loop
+        assert!(feedback.add_path(&mut metadata, &[0, 1, 1, 1]));
+
+        for len in 5..32 {
+            let mut path = vec![1; len];
+            path[0] = 0;
+            assert!(!feedback.add_path(&mut metadata, &path));
+        }
+    }
 }
@@ -0,0 +1,202 @@
+use crate::{
+    capture::{Capture, PcapWriter},
+    input::{
+        flow::{Flow, FlowFilter},
+        HasPackets, HasPcapRepresentation,
+    },
+};
+use libafl::{
+    inputs::{BytesInput, HasTargetBytes, Input},
+    Error,
+};
+use libafl_bolts::{fs::write_file_atomic, ownedref::OwnedSlice, HasLen};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::Path;
+
+/// Size of the length prefix of every packet in the wire encoding
+const LENGTH_PREFIX_LEN: usize = 4;
+
+/// Client address used by `to_pcap()`
+const DEFAULT_CLIENT: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 40000));
+/// Server address used by `to_pcap()`
+const DEFAULT_SERVER: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8080));
+
+/// A ready-made input for protocols where packets are opaque bytearrays.
+///
+/// It implements all traits that butterflys components need, so it can be used
+/// with all mutators whose packet type is [`BytesInput`](libafl::inputs::BytesInput)
+/// without writing any boilerplate.
+///
+/// # Wire encoding
+/// [`HasTargetBytes::target_bytes()`](libafl::inputs::HasTargetBytes::target_bytes) and the
+/// on-disk corpus files use the following encoding:
+/// every packet is written as a 32-bit little-endian length followed by
+/// the bytes of the packet. There is no header and no padding.
+/// ```text
+/// | len(packet 0): u32 | packet 0 | len(packet 1): u32 | packet 1 | ...
+/// ```
+/// [`PacketBytesInput::from_bytes()`] decodes this encoding.
+///
+/// # Pcap representation
+/// [`from_pcap()`](crate::HasPcapRepresentation::from_pcap) reassembles the first flow of
+/// the capture with [`Flow::from_capture_with_push()`] and turns every message that the client sent into a packet.
+/// [`to_pcap()`](crate::HasPcapRepresentation::to_pcap) writes every packet as a message
+/// of a TCP connection from `127.0.0.1:40000` to `127.0.0.1:8080`.
+/// Since every message ends with a PSH flag, `from_pcap()` gets back the same packets.
+/// Only empty packets get lost because they don't show up in a TCP stream.
+/// Use [`PacketBytesInput::from_flow()`] and [`PacketBytesInput::write_pcap()`]
+/// for other flows or addresses.
+#[derive(Hash, Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketBytesInput {
+    packets: Vec<BytesInput>,
+}
+
+impl PacketBytesInput {
+    /// Create a new PacketBytesInput from a list of packets
+    pub fn new(packets: Vec<BytesInput>) -> Self {
+        Self {
+            packets,
+        }
+    }
+
+    /// Create a new PacketBytesInput out of all messages that the client sent in `flow`
+    pub fn from_flow(flow: &Flow) -> Self {
+        Self::new(flow.client_messages().map(|message| BytesInput::new(message.data().to_vec())).collect())
+    }
+
+    /// Decode an input from the length-prefixed wire encoding
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, Error> {
+        let mut packets = Vec::new();
+
+        while !bytes.is_empty() {
+            if bytes.len() < LENGTH_PREFIX_LEN {
+                return Err(Error::illegal_argument("Truncated length prefix in packet encoding"));
+            }
+
+            let len = u32::from_le_bytes(bytes[..LENGTH_PREFIX_LEN].try_into().unwrap()) as usize;
+            bytes = &bytes[LENGTH_PREFIX_LEN..];
+
+            if bytes.len() < len {
+                return Err(Error::illegal_argument("Truncated packet in packet encoding"));
+            }
+
+            packets.push(BytesInput::new(bytes[..len].to_vec()));
+            bytes = &bytes[len..];
+        }
+
+        Ok(Self::new(packets))
+    }
+
+    /// Encode the input in the length-prefixed wire encoding
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = self.packets.iter().map(|packet| LENGTH_PREFIX_LEN + packet.len()).sum();
+        let mut bytes = Vec::with_capacity(len);
+
+        for packet in &self.packets {
+            bytes.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(packet.as_ref());
+        }
+
+        bytes
+    }
+
+    /// Write all packets as client messages into `writer`
+    pub fn write_pcap<W>(&self, writer: &mut PcapWriter<W>) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        for packet in &self.packets {
+            writer.write_client(packet.as_ref())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Vec<Vec<u8>>> for PacketBytesInput {
+    fn from(packets: Vec<Vec<u8>>) -> Self {
+        Self::new(packets.into_iter().map(BytesInput::new).collect())
+    }
+}
+
+impl Input for PacketBytesInput {
+    fn to_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        write_file_atomic(path, &self.to_bytes())
+    }
+
+    fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+impl HasPackets<BytesInput> for PacketBytesInput {
+    fn packets(&self) -> &[BytesInput] {
+        &self.packets
+    }
+
+    fn packets_mut(&mut self) -> &mut Vec<BytesInput> {
+        &mut self.packets
+    }
+}
+
+impl HasLen for PacketBytesInput {
+    fn len(&self) -> usize {
+        self.packets.len()
+    }
+}
+
+impl HasTargetBytes for PacketBytesInput {
+    fn target_bytes(&self) -> OwnedSlice<'_, u8> {
+        OwnedSlice::from(self.to_bytes())
+    }
+}
+
+impl HasPcapRepresentation<PacketBytesInput> for PacketBytesInput {
+    fn from_pcap(capture: Capture) -> Result<Self, Error> {
+        Ok(Self::from_flow(&Flow::from_capture_with_push(&capture, &FlowFilter::First)?))
+    }
+
+    fn to_pcap<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let mut writer = PcapWriter::tcp(writer, DEFAULT_CLIENT, DEFAULT_SERVER)?;
+        self.write_pcap(&mut writer)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_encoding() {
+        let input = PacketBytesInput::from(vec![b"USER a\r\n".to_vec(), Vec::new(), b"QUIT\r\n".to_vec()]);
+        let bytes = input.to_bytes();
+
+        assert_eq!(&bytes[..12], b"\x08\x00\x00\x00USER a\r\n");
+        assert_eq!(&bytes[12..16], b"\x00\x00\x00\x00");
+        assert_eq!(input.target_bytes().as_ref(), &bytes[..]);
+        assert_eq!(PacketBytesInput::from_bytes(&bytes).unwrap(), input);
+        assert_eq!(PacketBytesInput::from_bytes(&[]).unwrap().len(), 0);
+        assert!(PacketBytesInput::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(PacketBytesInput::from_bytes(&[1, 0]).is_err());
+    }
+
+    #[test]
+    fn test_pcap_roundtrip() {
+        let input = PacketBytesInput::from(vec![b"hello".to_vec(), b"world".to_vec(), vec![0x41; 3000], b"!".to_vec()]);
+        let mut pcap = Vec::new();
+        input.to_pcap(&mut pcap).unwrap();
+
+        let output = PacketBytesInput::from_pcap(Capture::from_bytes(&pcap).unwrap()).unwrap();
+        assert_eq!(output, input);
+    }
+}
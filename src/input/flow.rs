@@ -0,0 +1,738 @@
+use crate::capture::Capture;
+use libafl::Error;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+const LINKTYPE_NULL: u16 = 0;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW_OPENBSD: u16 = 12;
+const LINKTYPE_RAW_BSDOS: u16 = 14;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LOOP: u16 = 108;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: [u16; 3] = [0x8100, 0x88a8, 0x9100];
+
+const IPPROTO_HOPOPTS: u8 = 0;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ROUTING: u8 = 43;
+const IPPROTO_FRAGMENT: u8 = 44;
+const IPPROTO_DSTOPTS: u8 = 60;
+
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+#[inline]
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().unwrap()))
+}
+
+#[inline]
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().unwrap()))
+}
+
+/// The transport layer protocol of a [`Flow`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransportProtocol {
+    /// Data of a TCP flow is a byte stream
+    Tcp,
+    /// Data of an UDP flow is a sequence of datagrams
+    Udp,
+}
+
+/// The direction in which a [`FlowMessage`] was sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Sent by the endpoint that initiated the flow
+    ClientToServer,
+    /// Sent by the endpoint that accepted the flow
+    ServerToClient,
+}
+
+/// Selects which flow of a capture gets reassembled by [`Flow::from_capture()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlowFilter {
+    /// The flow of the first TCP or UDP packet in the capture
+    First,
+    /// The first flow where one endpoint uses the given port.
+    /// That endpoint is considered to be the server.
+    Port(u16),
+    /// The flow with the given protocol, client address and server address
+    Exact(TransportProtocol, SocketAddr, SocketAddr),
+}
+
+/// An application-level message of a [`Flow`].
+///
+/// For TCP this is all data one endpoint sent before the other endpoint sent data,
+/// for UDP this is a single datagram.
+/// [`Flow::from_capture_with_push()`] additionally ends TCP messages at the PSH flag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlowMessage {
+    direction: Direction,
+    timestamp: Duration,
+    data: Vec<u8>,
+}
+
+impl FlowMessage {
+    /// Who sent this message
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Timestamp of the packet that started this message
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// The application data of this message
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Bookkeeping for the fragments of a single IP packet.
+#[derive(Default)]
+struct Fragments {
+    pieces: BTreeMap<usize, Vec<u8>>,
+    total_len: Option<usize>,
+}
+
+impl Fragments {
+    /// Add a fragment and return the complete payload once all fragments are present.
+    fn insert(&mut self, offset: usize, data: &[u8], more_fragments: bool) -> Option<Vec<u8>> {
+        if !more_fragments {
+            self.total_len = Some(offset + data.len());
+        }
+
+        match self.pieces.get(&offset) {
+            Some(piece) if piece.len() >= data.len() => {},
+            _ => {
+                self.pieces.insert(offset, data.to_vec());
+            },
+        }
+
+        let total_len = self.total_len?;
+        let mut payload = Vec::with_capacity(total_len);
+
+        for (offset, piece) in &self.pieces {
+            if *offset > payload.len() {
+                return None;
+            }
+
+            let end = std::cmp::min(offset + piece.len(), total_len);
+
+            if end > payload.len() {
+                payload.extend_from_slice(&piece[payload.len() - offset..end - offset]);
+            }
+        }
+
+        if payload.len() == total_len {
+            Some(payload)
+        } else {
+            None
+        }
+    }
+}
+
+type FragmentKey = (IpAddr, IpAddr, u32, u8);
+
+/// Reassembles IP fragments across the packets of a capture.
+#[derive(Default)]
+struct Defragmenter {
+    packets: HashMap<FragmentKey, Fragments>,
+}
+
+impl Defragmenter {
+    fn insert(&mut self, key: FragmentKey, offset: usize, data: &[u8], more_fragments: bool) -> Option<Vec<u8>> {
+        let payload = self.packets.entry(key).or_default().insert(offset, data, more_fragments)?;
+        self.packets.remove(&key);
+        Some(payload)
+    }
+}
+
+/// A decoded TCP segment or UDP datagram.
+struct Segment {
+    protocol: TransportProtocol,
+    src: SocketAddr,
+    dst: SocketAddr,
+    seq: u32,
+    flags: u8,
+    payload: Vec<u8>,
+    timestamp: Duration,
+}
+
+/// Strips the link-layer header off a frame and returns the IP packet.
+fn network_layer(linktype: u16, data: &[u8]) -> Option<&[u8]> {
+    let (ethertype, offset) = match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut ethertype = be16(data, 12)?;
+            let mut offset = 14;
+
+            while ETHERTYPE_VLAN.contains(&ethertype) {
+                ethertype = be16(data, offset + 2)?;
+                offset += 4;
+            }
+
+            (ethertype, offset)
+        },
+        LINKTYPE_NULL | LINKTYPE_LOOP => {
+            // The address family is in host byte order for NULL and network byte order for LOOP
+            let mut family = u32::from_le_bytes(data.get(0..4)?.try_into().unwrap());
+
+            if family > 0xffff {
+                family = family.swap_bytes();
+            }
+
+            match family {
+                2 => (ETHERTYPE_IPV4, 4),
+                24 | 28 | 30 => (ETHERTYPE_IPV6, 4),
+                _ => return None,
+            }
+        },
+        LINKTYPE_LINUX_SLL => (be16(data, 14)?, 16),
+        LINKTYPE_LINUX_SLL2 => (be16(data, 0)?, 20),
+        LINKTYPE_RAW | LINKTYPE_RAW_OPENBSD | LINKTYPE_RAW_BSDOS | LINKTYPE_IPV4 | LINKTYPE_IPV6 => match data.first()? >> 4 {
+            4 => (ETHERTYPE_IPV4, 0),
+            6 => (ETHERTYPE_IPV6, 0),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    match ethertype {
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => data.get(offset..),
+        _ => None,
+    }
+}
+
+/// Decodes an IP packet and returns the transport protocol, source, destination and transport payload.
+/// Fragments are handed to the defragmenter and yield a result once the packet is complete.
+fn transport_layer(packet: &[u8], defragmenter: &mut Defragmenter) -> Option<(u8, IpAddr, IpAddr, Vec<u8>)> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header_len = ((packet[0] & 0x0f) as usize) * 4;
+            let total_len = std::cmp::min(be16(packet, 2)? as usize, packet.len());
+            let id = be16(packet, 4)?;
+            let flags_offset = be16(packet, 6)?;
+            let protocol = *packet.get(9)?;
+            let src = IpAddr::V4(Ipv4Addr::from(be32(packet, 12)?));
+            let dst = IpAddr::V4(Ipv4Addr::from(be32(packet, 16)?));
+            let payload = packet.get(header_len..total_len)?;
+
+            let more_fragments = flags_offset & 0x2000 != 0;
+            let offset = ((flags_offset & 0x1fff) as usize) * 8;
+
+            if more_fragments || offset > 0 {
+                let payload = defragmenter.insert((src, dst, id as u32, protocol), offset, payload, more_fragments)?;
+                Some((protocol, src, dst, payload))
+            } else {
+                Some((protocol, src, dst, payload.to_vec()))
+            }
+        },
+        6 => {
+            let payload_len = be16(packet, 4)? as usize;
+            let src = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(packet.get(8..24)?).unwrap()));
+            let dst = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(packet.get(24..40)?).unwrap()));
+            let end = std::cmp::min(40 + payload_len, packet.len());
+            let mut next_header = packet[6];
+            let mut offset = 40;
+
+            loop {
+                match next_header {
+                    IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                        next_header = *packet.get(offset)?;
+                        offset += (*packet.get(offset + 1)? as usize + 1) * 8;
+                    },
+                    IPPROTO_FRAGMENT => {
+                        let protocol = *packet.get(offset)?;
+                        let offset_flags = be16(packet, offset + 2)?;
+                        let id = be32(packet, offset + 4)?;
+                        let payload = packet.get(offset + 8..end)?;
+
+                        let fragment_offset = ((offset_flags >> 3) as usize) * 8;
+                        let more_fragments = offset_flags & 1 != 0;
+                        let payload = defragmenter.insert((src, dst, id, protocol), fragment_offset, payload, more_fragments)?;
+
+                        return Some((protocol, src, dst, payload));
+                    },
+                    protocol => return Some((protocol, src, dst, packet.get(offset..end)?.to_vec())),
+                }
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Decodes all TCP segments and UDP datagrams of a capture.
+fn segments(capture: &Capture) -> Vec<Segment> {
+    let mut defragmenter = Defragmenter::default();
+    let mut segments = Vec::new();
+
+    for packet in capture.packets() {
+        let Some(ip) = network_layer(packet.linktype(), packet.data()) else {
+            continue;
+        };
+        let Some((protocol, src, dst, data)) = transport_layer(ip, &mut defragmenter) else {
+            continue;
+        };
+
+        let segment = match protocol {
+            IPPROTO_TCP => {
+                let (Some(src_port), Some(dst_port), Some(seq), Some(&data_offset), Some(&flags)) = (be16(&data, 0), be16(&data, 2), be32(&data, 4), data.get(12), data.get(13)) else {
+                    continue;
+                };
+                let Some(payload) = data.get(((data_offset >> 4) as usize) * 4..) else {
+                    continue;
+                };
+
+                Segment {
+                    protocol: TransportProtocol::Tcp,
+                    src: SocketAddr::new(src, src_port),
+                    dst: SocketAddr::new(dst, dst_port),
+                    seq,
+                    flags,
+                    payload: payload.to_vec(),
+                    timestamp: packet.timestamp(),
+                }
+            },
+            IPPROTO_UDP => {
+                let (Some(src_port), Some(dst_port), Some(len)) = (be16(&data, 0), be16(&data, 2), be16(&data, 4)) else {
+                    continue;
+                };
+                let Some(payload) = data.get(8..std::cmp::min(len as usize, data.len())) else {
+                    continue;
+                };
+
+                Segment {
+                    protocol: TransportProtocol::Udp,
+                    src: SocketAddr::new(src, src_port),
+                    dst: SocketAddr::new(dst, dst_port),
+                    seq: 0,
+                    flags: 0,
+                    payload: payload.to_vec(),
+                    timestamp: packet.timestamp(),
+                }
+            },
+            _ => continue,
+        };
+
+        segments.push(segment);
+    }
+
+    segments
+}
+
+/// Reassembles one direction of a TCP connection.
+#[derive(Default)]
+struct StreamReassembler {
+    /// Sequence number of the first byte of the stream
+    base: Option<u32>,
+    /// Offset of the next byte that we expect
+    next: u64,
+    /// Segments that arrived out-of-order, keyed by offset
+    pending: BTreeMap<u64, Vec<u8>>,
+}
+
+impl StreamReassembler {
+    fn syn(&mut self, seq: u32) {
+        if self.base.is_none() {
+            self.base = Some(seq.wrapping_add(1));
+        }
+    }
+
+    /// Add a segment and return the bytes that became contiguous with it.
+    fn push(&mut self, seq: u32, payload: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        if payload.is_empty() {
+            return data;
+        }
+
+        let base = *self.base.get_or_insert(seq);
+        let mut offset = seq.wrapping_sub(base);
+        let mut payload = payload;
+
+        // The segment (partially) lies before the start of the stream
+        if offset >= 1 << 31 {
+            let skip = base.wrapping_sub(seq) as usize;
+
+            if skip >= payload.len() {
+                return data;
+            }
+
+            payload = &payload[skip..];
+            offset = 0;
+        }
+
+        let offset = offset as u64;
+
+        // Retransmission of data that we already have
+        if offset + payload.len() as u64 <= self.next {
+            return data;
+        }
+
+        match self.pending.get(&offset) {
+            Some(pending) if pending.len() >= payload.len() => {},
+            _ => {
+                self.pending.insert(offset, payload.to_vec());
+            },
+        }
+
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() > self.next {
+                break;
+            }
+
+            let offset = *entry.key();
+            let segment = entry.remove();
+            let end = offset + segment.len() as u64;
+
+            if end > self.next {
+                data.extend_from_slice(&segment[(self.next - offset) as usize..]);
+                self.next = end;
+            }
+        }
+
+        data
+    }
+}
+
+/// The reassembled application data of a single TCP connection or UDP flow.
+///
+/// Use it in [`HasPcapRepresentation::from_pcap()`](crate::HasPcapRepresentation::from_pcap)
+/// to get the application messages out of a capture. All the lower layers are taken care of:
+/// - link layers: Ethernet (with VLAN tags), Linux cooked capture v1 and v2, BSD loopback and raw IP
+/// - IPv4 and IPv6 including fragment reassembly
+/// - TCP stream reassembly that handles retransmissions and out-of-order segments
+/// - captures with multiple flows, of which one gets selected by a [`FlowFilter`]
+///
+/// # Example
+/// ```
+/// impl HasPcapRepresentation<FTPInput> for FTPInput {
+///     fn from_pcap(capture: Capture) -> Result<FTPInput, Error> {
+///         let flow = Flow::from_capture(&capture, &FlowFilter::Port(21))?;
+///         let mut packets = Vec::new();
+///
+///         for message in flow.client_messages() {
+///             packets.extend(parse_commands(message.data()));
+///         }
+///
+///         Ok(FTPInput { packets })
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Flow {
+    protocol: TransportProtocol,
+    client: SocketAddr,
+    server: SocketAddr,
+    messages: Vec<FlowMessage>,
+}
+
+impl Flow {
+    /// Select a flow from a capture and reassemble its messages.
+    ///
+    /// Returns an error if no flow in the capture matches `filter`.
+    pub fn from_capture(capture: &Capture, filter: &FlowFilter) -> Result<Self, Error> {
+        Self::reassemble(capture, filter, false)
+    }
+
+    /// Like [`Flow::from_capture()`] but a TCP message also ends at a segment with the PSH flag.
+    ///
+    /// Network stacks set PSH on the last segment of a write, so this recovers the individual
+    /// writes of an endpoint instead of everything it sent in a row.
+    /// The [`PcapWriter`](crate::PcapWriter) does the same, so captures that it wrote keep their message boundaries.
+    pub fn from_capture_with_push(capture: &Capture, filter: &FlowFilter) -> Result<Self, Error> {
+        Self::reassemble(capture, filter, true)
+    }
+
+    fn reassemble(capture: &Capture, filter: &FlowFilter, split_at_push: bool) -> Result<Self, Error> {
+        let segments = segments(capture);
+
+        let first = segments
+            .iter()
+            .find(|segment| match filter {
+                FlowFilter::First => true,
+                FlowFilter::Port(port) => segment.src.port() == *port || segment.dst.port() == *port,
+                FlowFilter::Exact(protocol, client, server) => segment.protocol == *protocol && ((segment.src == *client && segment.dst == *server) || (segment.src == *server && segment.dst == *client)),
+            })
+            .ok_or_else(|| Error::empty(format!("No flow matching {:?} in capture", filter)))?;
+
+        let protocol = first.protocol;
+        let (client, server) = match filter {
+            FlowFilter::Port(port) if first.src.port() == *port => (first.dst, first.src),
+            FlowFilter::Port(_) => (first.src, first.dst),
+            FlowFilter::Exact(_, client, server) => (*client, *server),
+            // The SYN/ACK gets sent by the server
+            FlowFilter::First if first.flags & (TCP_SYN | TCP_ACK) == TCP_SYN | TCP_ACK => (first.dst, first.src),
+            FlowFilter::First => (first.src, first.dst),
+        };
+
+        let mut flow = Self {
+            protocol,
+            client,
+            server,
+            messages: Vec::new(),
+        };
+        let mut client_stream = StreamReassembler::default();
+        let mut server_stream = StreamReassembler::default();
+        // Whether the last message must not be extended anymore
+        let mut closed = false;
+
+        for segment in segments.iter().filter(|segment| segment.protocol == protocol) {
+            let (direction, stream) = if segment.src == client && segment.dst == server {
+                (Direction::ClientToServer, &mut client_stream)
+            } else if segment.src == server && segment.dst == client {
+                (Direction::ServerToClient, &mut server_stream)
+            } else {
+                continue;
+            };
+
+            let data = match protocol {
+                TransportProtocol::Tcp => {
+                    if segment.flags & TCP_SYN != 0 {
+                        stream.syn(segment.seq);
+                        continue;
+                    }
+
+                    stream.push(segment.seq, &segment.payload)
+                },
+                TransportProtocol::Udp => segment.payload.clone(),
+            };
+
+            let delivered = !data.is_empty();
+            flow.deliver(direction, segment.timestamp, data, closed);
+
+            if delivered {
+                closed = split_at_push && segment.flags & TCP_PSH != 0;
+            }
+        }
+
+        Ok(flow)
+    }
+
+    fn deliver(&mut self, direction: Direction, timestamp: Duration, data: Vec<u8>, closed: bool) {
+        if data.is_empty() && self.protocol == TransportProtocol::Tcp {
+            return;
+        }
+
+        match self.messages.last_mut() {
+            Some(message) if self.protocol == TransportProtocol::Tcp && message.direction == direction && !closed => message.data.extend(data),
+            _ => self.messages.push(FlowMessage {
+                direction,
+                timestamp,
+                data,
+            }),
+        }
+    }
+
+    /// The transport protocol of the flow
+    pub fn protocol(&self) -> TransportProtocol {
+        self.protocol
+    }
+
+    /// Address of the endpoint that initiated the flow
+    pub fn client(&self) -> SocketAddr {
+        self.client
+    }
+
+    /// Address of the endpoint that accepted the flow
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// All messages in both directions in the order they were sent
+    pub fn messages(&self) -> &[FlowMessage] {
+        &self.messages
+    }
+
+    /// All messages that the client sent
+    pub fn client_messages(&self) -> impl Iterator<Item = &FlowMessage> {
+        self.messages.iter().filter(|message| message.direction == Direction::ClientToServer)
+    }
+
+    /// All messages that the server sent
+    pub fn server_messages(&self) -> impl Iterator<Item = &FlowMessage> {
+        self.messages.iter().filter(|message| message.direction == Direction::ServerToClient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::PcapWriter;
+    use std::net::SocketAddrV4;
+
+    fn tcp_capture(port: u16, messages: &[(bool, &[u8])]) -> Vec<u8> {
+        let client = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 40000);
+        let server = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), port);
+        let mut writer = PcapWriter::tcp(Vec::new(), client.into(), server.into()).unwrap();
+
+        for (from_client, data) in messages {
+            if *from_client {
+                writer.write_client(data).unwrap();
+            } else {
+                writer.write_server(data).unwrap();
+            }
+        }
+
+        writer.finish().unwrap()
+    }
+
+    fn raw_ip_capture(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&65535u32.to_le_bytes());
+        data.extend_from_slice(&(LINKTYPE_RAW as u32).to_le_bytes());
+
+        for packet in packets {
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            data.extend_from_slice(packet);
+        }
+
+        data
+    }
+
+    fn ipv4_fragment(id: u16, offset: usize, more_fragments: bool, payload: &[u8]) -> Vec<u8> {
+        let flags_offset = (offset / 8) as u16 | if more_fragments { 0x2000 } else { 0 };
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&((20 + payload.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&id.to_be_bytes());
+        packet.extend_from_slice(&flags_offset.to_be_bytes());
+        packet.extend_from_slice(&[64, IPPROTO_UDP, 0, 0]);
+        packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn test_stream_out_of_order() {
+        let mut stream = StreamReassembler::default();
+        stream.syn(99);
+
+        assert_eq!(stream.push(105, b"world"), b"");
+        assert_eq!(stream.push(100, b"hello"), b"helloworld");
+        // retransmission
+        assert_eq!(stream.push(100, b"hello"), b"");
+        // overlapping retransmission with new data
+        assert_eq!(stream.push(108, b"ld!"), b"!");
+        // segment before the start of the stream
+        assert_eq!(stream.push(90, b"0123456789"), b"");
+    }
+
+    #[test]
+    fn test_stream_wraparound() {
+        let mut stream = StreamReassembler::default();
+        stream.syn(u32::MAX - 2);
+
+        assert_eq!(stream.push(u32::MAX - 1, b"abcd"), b"abcd");
+        assert_eq!(stream.push(2, b"ef"), b"ef");
+    }
+
+    #[test]
+    fn test_fragments() {
+        let mut fragments = Fragments::default();
+
+        assert_eq!(fragments.insert(8, b"89abcdef", true), None);
+        assert_eq!(fragments.insert(16, b"XY", false), None);
+        assert_eq!(fragments.insert(0, b"01234567", true), Some(b"0123456789abcdefXY".to_vec()));
+    }
+
+    #[test]
+    fn test_tcp_flow() {
+        let data = tcp_capture(21, &[(false, b"220 welcome\r\n"), (true, b"USER a\r\n"), (true, b"PASS b\r\n"), (false, b"230 ok\r\n")]);
+        let flow = Flow::from_capture(&Capture::from_bytes(&data).unwrap(), &FlowFilter::First).unwrap();
+
+        assert_eq!(flow.protocol(), TransportProtocol::Tcp);
+        assert_eq!(flow.server().port(), 21);
+        assert_eq!(flow.messages().len(), 3);
+        assert_eq!(flow.messages()[0].direction(), Direction::ServerToClient);
+        assert_eq!(flow.client_messages().map(|m| m.data()).collect::<Vec<_>>(), vec![b"USER a\r\nPASS b\r\n"]);
+        assert_eq!(flow.server_messages().count(), 2);
+    }
+
+    #[test]
+    fn test_ipv6_flow() {
+        let client = "[fd00::1]:40000".parse().unwrap();
+        let server = "[fd00::2]:21".parse().unwrap();
+        let mut writer = PcapWriter::tcp(Vec::new(), client, server).unwrap();
+        writer.write_client(b"USER a\r\n").unwrap();
+        writer.write_server(b"331 ok\r\n").unwrap();
+        let data = writer.finish().unwrap();
+
+        let flow = Flow::from_capture(&Capture::from_bytes(&data).unwrap(), &FlowFilter::First).unwrap();
+        assert_eq!(flow.client(), client);
+        assert_eq!(flow.server(), server);
+        assert_eq!(flow.messages().len(), 2);
+        assert_eq!(flow.messages()[1].data(), b"331 ok\r\n");
+    }
+
+    #[test]
+    fn test_split_at_push() {
+        let data = tcp_capture(21, &[(true, b"USER a\r\n"), (true, &[0x41; 2000]), (false, b"331 ok\r\n"), (true, b"PASS b\r\n")]);
+        let capture = Capture::from_bytes(&data).unwrap();
+
+        let flow = Flow::from_capture(&capture, &FlowFilter::First).unwrap();
+        assert_eq!(flow.client_messages().count(), 2);
+
+        let flow = Flow::from_capture_with_push(&capture, &FlowFilter::First).unwrap();
+        let messages: Vec<&[u8]> = flow.client_messages().map(|m| m.data()).collect();
+        assert_eq!(messages, vec![&b"USER a\r\n"[..], &[0x41; 2000], b"PASS b\r\n"]);
+    }
+
+    #[test]
+    fn test_select_flow() {
+        let mut data = tcp_capture(80, &[(true, b"GET / HTTP/1.1\r\n\r\n")]);
+        let other = tcp_capture(21, &[(true, b"QUIT\r\n")]);
+        // skip the global header of the second capture
+        data.extend_from_slice(&other[24..]);
+        let capture = Capture::from_bytes(&data).unwrap();
+
+        let flow = Flow::from_capture(&capture, &FlowFilter::First).unwrap();
+        assert_eq!(flow.messages()[0].data(), b"GET / HTTP/1.1\r\n\r\n");
+
+        let flow = Flow::from_capture(&capture, &FlowFilter::Port(21)).unwrap();
+        assert_eq!(flow.messages()[0].data(), b"QUIT\r\n");
+
+        let client = "10.0.0.1:40000".parse().unwrap();
+        let server = "10.0.0.2:80".parse().unwrap();
+        let flow = Flow::from_capture(&capture, &FlowFilter::Exact(TransportProtocol::Tcp, client, server)).unwrap();
+        assert_eq!(flow.client(), client);
+        assert_eq!(flow.messages().len(), 1);
+
+        assert!(Flow::from_capture(&capture, &FlowFilter::Port(25)).is_err());
+        assert!(Flow::from_capture(&capture, &FlowFilter::Exact(TransportProtocol::Udp, client, server)).is_err());
+    }
+
+    #[test]
+    fn test_fragmented_udp() {
+        let mut datagram = Vec::new();
+        datagram.extend_from_slice(&5353u16.to_be_bytes());
+        datagram.extend_from_slice(&53u16.to_be_bytes());
+        datagram.extend_from_slice(&28u16.to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(b"0123456789abcdefghij");
+
+        let data = raw_ip_capture(&[ipv4_fragment(1, 16, false, &datagram[16..]), ipv4_fragment(1, 0, true, &datagram[..16])]);
+        let flow = Flow::from_capture(&Capture::from_bytes(&data).unwrap(), &FlowFilter::Port(53)).unwrap();
+
+        assert_eq!(flow.protocol(), TransportProtocol::Udp);
+        assert_eq!(flow.client().port(), 5353);
+        assert_eq!(flow.messages().len(), 1);
+        assert_eq!(flow.messages()[0].data(), b"0123456789abcdefghij");
+    }
+}
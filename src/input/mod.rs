@@ -0,0 +1,189 @@
+mod bytes;
+mod flow;
+
+pub use bytes::PacketBytesInput;
+pub use flow::{Direction, Flow, FlowFilter, FlowMessage, TransportProtocol};
+
+use crate::capture::Capture;
+use libafl::{
+    corpus::Corpus,
+    inputs::Input,
+    Error, Evaluator,
+};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// Signifies that an input consists of packets.
+///
+/// The type of a packet can be anything.    
+/// If you want your packets to be plain bytearrays
+/// then use [`BytesInput`](libafl::inputs::BytesInput) as `I`
+/// but packets can also hold some semantical or meta information
+/// as structs or enums.
+///
+/// # Example
+/// The simplest type of input:
+/// ```
+/// struct PacketInput {
+///     packets: Vec<BytesInput>,
+/// }
+///
+/// impl HasPackets<BytesInput> for PacketInput {
+///     fn packets(&self) -> &[BytesInput] {
+///         &self.packets
+///     }
+///         
+///     fn packets_mut(&mut self) -> &mut Vec<BytesInput> {
+///         &mut self.packets
+///     }
+/// }
+/// ```
+/// A more complicated packet type:
+/// ```
+/// enum FTPCommand {
+///     USER(BytesInput),
+///     PASS(BytesInput),
+///     // and so on...
+/// }
+///
+/// struct FTPInput {
+///     packets: Vec<FTPCommand>,
+/// }
+///
+/// impl HasPackets<FTPCommand> for FTPInput {
+///     fn packets(&self) -> &[FTPCommand] {
+///         &self.packets
+///     }
+///         
+///     fn packets_mut(&mut self) -> &mut Vec<FTPCommand> {
+///         &mut self.packets
+///     }
+/// }
+/// ```
+pub trait HasPackets<I> {
+    /// Get the inputs packets
+    fn packets(&self) -> &[I];
+
+    /// Get the inputs packets
+    fn packets_mut(&mut self) -> &mut Vec<I>;
+}
+
+/// Signifies that an input can be constructed from a packet capture.
+///
+/// Use it in conjunction with [`load_pcaps`] and [`dump_pcaps`].
+pub trait HasPcapRepresentation<I> {
+    /// Given a packet capture, parse the packets and construct an input
+    ///
+    /// The capture is read by butterflys own pcap/pcapng parser.
+    /// Iterate over its packets with [`Capture::next_packet()`](crate::Capture::next_packet)
+    /// or use [`Flow::from_capture()`](crate::Flow::from_capture) to get the reassembled application data.
+    fn from_pcap(capture: Capture) -> Result<I, Error>;
+
+    /// Serialize the input into a packet capture written to `writer`.
+    ///
+    /// Implementations usually wrap `writer` in a [`PcapWriter`](crate::PcapWriter)
+    /// and hand it the application data of each packet. It writes classic pcap files
+    /// with Ethernet, IPv4 or IPv6 and synthesized TCP or UDP headers.
+    /// The default implementation returns [`Error::NotImplemented`](libafl::Error::NotImplemented).
+    ///
+    /// # Example
+    /// ```
+    /// fn to_pcap<W: Write>(&self, writer: W) -> Result<(), Error> {
+    ///     let client = "127.0.0.1:40000".parse().unwrap();
+    ///     let server = "127.0.0.1:21".parse().unwrap();
+    ///     let mut writer = PcapWriter::tcp(writer, client, server)?;
+    ///
+    ///     for packet in &self.packets {
+    ///         writer.write_client(packet.bytes())?;
+    ///     }
+    ///
+    ///     writer.finish()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    fn to_pcap<W: Write>(&self, _writer: W) -> Result<(), Error> {
+        Err(Error::not_implemented("to_pcap() is not implemented for this input"))
+    }
+}
+
+/// Helper function that loads pcap files from a given directory into the corpus.
+///
+/// It scans the directory for files ending with `.pcap` or `.pcapng` and loads them
+/// via [`HasPcapRepresentation::from_pcap()`](crate::HasPcapRepresentation::from_pcap).
+///
+/// This is an equivalent to [`load_initial_inputs()`](libafl::state::StdState::load_initial_inputs) from LibAFL.
+///
+/// # Arguments
+/// - `state`: libafls state
+/// - `fuzzer`: libafls fuzzer
+/// - `executor`: libafls executor
+/// - `mgr`: libafls event manager
+/// - `in_dir`: path to directory with pcap files
+pub fn load_pcaps<S, Z, E, EM, I, P>(state: &mut S, fuzzer: &mut Z, executor: &mut E, mgr: &mut EM, in_dir: P) -> Result<(), Error>
+where
+    Z: Evaluator<E, EM, I, S>,
+    I: HasPcapRepresentation<I>,
+    P: Into<PathBuf>,
+{
+    for entry in std::fs::read_dir(in_dir.into())? {
+        let entry = entry?;
+        let path = entry.path();
+
+        let attributes = std::fs::metadata(&path);
+
+        if attributes.is_err() {
+            continue;
+        }
+
+        let attr = attributes?;
+
+        if attr.is_file() && attr.len() > 0 {
+            if path.extension() == Some(OsStr::new("pcapng")) || path.extension() == Some(OsStr::new("pcap")) {
+                println!("[butterfly] Loading pcap {}...", path.display());
+                let input = I::from_pcap(Capture::from_file(path)?)?;
+                let _ = fuzzer.evaluate_input(state, executor, mgr, &input)?;
+            }
+        } else if attr.is_dir() {
+            load_pcaps(state, fuzzer, executor, mgr, path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Helper function that writes all inputs of a corpus as pcap files into a given directory.
+///
+/// This is the counterpart to [`load_pcaps`]. Every input gets serialized via
+/// [`HasPcapRepresentation::to_pcap()`](crate::HasPcapRepresentation::to_pcap) into
+/// a file named after the testcase with the extension `.pcap`, so the format must be
+/// classic pcap, which is what [`PcapWriter`](crate::PcapWriter) writes.
+/// Pass `state.solutions()` to export all crashing inputs or `state.corpus()`
+/// to export the whole corpus.
+///
+/// # Arguments
+/// - `corpus`: libafls corpus
+/// - `out_dir`: path to directory where the pcap files are created
+pub fn dump_pcaps<C, I, P>(corpus: &C, out_dir: P) -> Result<(), Error>
+where
+    C: Corpus<I>,
+    I: Input + HasPcapRepresentation<I>,
+    P: Into<PathBuf>,
+{
+    let out_dir = out_dir.into();
+    std::fs::create_dir_all(&out_dir)?;
+
+    for id in corpus.ids() {
+        let input = corpus.cloned_input_for_id(id)?;
+        let name = corpus.get(id)?.borrow().filename().clone().unwrap_or_else(|| format!("id_{}", id));
+        let path = out_dir.join(format!("{}.pcap", name));
+
+        println!("[butterfly] Writing pcap {}...", path.display());
+        let mut writer = BufWriter::new(File::create(&path)?);
+        input.to_pcap(&mut writer)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
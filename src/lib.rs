@@ -15,25 +15,67 @@
 //! # Components
 //! - **Input**   
 //!   - In order to create a new, working input type you MUST implement the following traits:       
-//!   [`Hash`](core::hash::Hash), [`Debug`](core::fmt::Debug), [`Clone`](core::clone::Clone), [`Serialize`](serde::Serialize), [`Deserialize`](serde::Deserialize), [`Input`](libafl::inputs::Input)     
+//!     [`Hash`](core::hash::Hash), [`Debug`](core::fmt::Debug), [`Clone`](core::clone::Clone), [`Serialize`](serde::Serialize), [`Deserialize`](serde::Deserialize), [`Input`](libafl::inputs::Input)     
 //!   - To make it usable by other butterfly components, implement [`HasPackets`], [`HasLen`](libafl_bolts::HasLen)
-//!   - If you want to load it from a PCAP file, implement [`HasPcapRepresentation`]
+//!   - If your packets are opaque bytearrays you can skip all of this and use [`PacketBytesInput`]
+//!   - If your packets are enums of bytearrays, `#[derive(PacketMutations)]` implements all per-packet mutation traits for you
+//!   - If you want to load it from a PCAP file, implement [`HasPcapRepresentation`].
+//!     [`Flow`] reassembles the TCP or UDP flow of a capture so that you only have to parse application data
+//!   - If you want to export it as a PCAP file, implement [`HasPcapRepresentation::to_pcap()`] with the help of a [`PcapWriter`]
 //! - **Mutators**
 //!   - havoc: [`PacketHavocMutator`] gets a list of havoc mutators and uses [`HasHavocMutation`] to mutate a selected packet.      
 //!     Not all of libafls havoc mutators work with packet-based inputs, though. [`supported_havoc_mutations`] gives you all havoc
 //!     mutators that work
+//!   - [`PacketHavocMutatorBuilder`] configures how many mutations the [`PacketHavocMutator`] stacks ([`StackDepth`])
+//!     and over how many packets it spreads them
+//!   - a [`PacketSelector`] decides which packet the [`PacketHavocMutator`] mutates. The [`FocusedPacketSelector`]
+//!     only mutates packets at or after the first packet that reached a new state
 //!   - packet-mutators:
 //!     - [`PacketDeleteMutator`], [`PacketDuplicateMutator`], [`PacketReorderMutator`]
+//!     - [`PacketRangeDeleteMutator`], [`PacketRangeDuplicateMutator`], [`PacketRangeMoveMutator`], [`PacketRotateMutator`]
+//!       and [`PacketReverseMutator`] do the same for ranges of consecutive packets
 //!   - crossover mutators:
 //!     - [`PacketCrossoverInsertMutator`] and [`PacketCrossoverReplaceMutator`]
+//!     - [`PacketCorpusInsertMutator`] and [`PacketCorpusReplaceMutator`] take whole packets from other corpus entries
 //!   - splicing mutators:
 //!     - [`PacketSpliceMutator`]
+//!   - segmentation mutators:
+//!     - [`PacketSplitMutator`] and [`PacketMergeMutator`] split a packet in two or merge adjacent packets
+//!       to test how the target handles messages that arrive in multiple reads or multiple messages in one read
+//!   - token mutators:
+//!     - [`PacketTokenInsertMutator`] and [`PacketTokenReplaceMutator`] insert dictionary tokens into a packet.
+//!       Load an AFL-style dictionary with [`load_dictionary`] or extract tokens from your seeds with [`load_pcap_tokens`]
+//!   - all of the above should be wrapped in a [`PacketMutationScheduler`] that executes one of them per run
+//!     or in a [`PotencyMutationScheduler`] that learns which mutators and packets are worth mutating.
+//!     It tells packets apart by their [`HasPacketKind`]
+//! - **Executor**
+//!   - [`NetworkExecutor`] sends the packets of an input to a TCP, UDP or Unix socket server and reads a response after every packet.
+//!     It records the state of every response and the [`HasPacketKind`] of every packet in the [`StateObserver`] for you.
+//!     Create it with a [`NetworkExecutorBuilder`]
+//!   - a [`PacketSerializer`] turns packets into bytes and a [`ResponseReader`] like [`ReadOnce`] or [`ReadUntil`] decides
+//!     what makes up a response
+//!   - a [`StateExtractor`] derives the state of the target from a response. butterfly comes with extractors for
+//!     status codes ([`StatusCodeExtractor`], [`StatusLineExtractor`]), binary fields ([`BinaryFieldExtractor`]),
+//!     regular expressions ([`RegexExtractor`]) and a generic fallback ([`ShapeExtractor`])
+//!   - `DesocketExecutor` runs the target under an AFL-style forkserver with a preload library that replaces the
+//!     socket API. The target writes its responses into the shared memory of a [`ResponseObserver`] (unix only).
+//!     The preload library is not part of butterfly, see the docs of `DesocketExecutor`
 //! - **Observer**
 //!   - [`StateObserver`] builds a state-graph
 //!   - The executor is responsible for calling [`StateObserver::record()`] with state information inferred from
 //!     the fuzz target
+//!   - The state-graph is stored as [`StateGraphMetadata`] in the state, so it survives restarts
+//!   - [`ResponseObserver`] owns a shared memory buffer that a target writes its responses into
+//!     and gives feedbacks and state extractors access to the responses of every packet
 //! - **Feedback**
-//!   - [`StateFeedback`] determines if a [`StateObserver`] has seen new states in the last run
+//!   - [`StateFeedback`] determines if a [`StateObserver`] has seen new states in the last run.
+//!     Which kinds of [`StateNovelty`] count as new can be configured
+//!   - [`StatePathFeedback`] determines if the sequence of states in the last run contained new n-grams
+//!   - With multiple fuzzer instances the feedbacks merge the states of received testcases into
+//!     the local state-graph so that the instances share their states and transitions
+//! - **Scheduler**
+//!   - [`StateAwareScheduler`] is a corpus scheduler that picks a target state of the state-graph first
+//!     and then a corpus entry that reaches it
 //! - **Monitor**
 //!   - butterfly provides a [`StateMonitor`] that prints information about the state-graph in addition to
 //!     all the other info
@@ -43,9 +85,19 @@
 //! # Features
 //! - `graphviz`
 //!   - Adds [`GraphvizMonitor`] that writes a DOT representation of the state graph to a file
+//! - `derive`
+//!   - Adds `#[derive(PacketMutations)]` that implements [`HasHavocMutation`], [`HasSpliceMutation`],
+//!     [`HasCrossoverInsertMutation`], [`HasCrossoverReplaceMutation`], [`HasSplitMutation`], [`HasMergeMutation`] and [`HasPacketKind`] for a packet type
+//! - `libpcap`
+//!   - Adds `Capture::from_libpcap()` to read captures via the C libpcap.
+//!     By default butterfly parses pcap and pcapng files itself and does not link against libpcap.
 //! - `safe_only`
 //!   - By default butterfly uses some unsafe code for performance reasons
-//!     but this can be disabled with this feature
+//!     but this can be disabled with this feature.
+//!     It also removes the `MatchName` impl of [`StateObserver`] that allows using a single [`StateObserver`]
+//!     without a `tuple_list!`. Look up observers with a `Handle` instead.
+//!     butterfly then contains no unsafe code at all. Because of that it cannot register its metadata types
+//!     for deserialization at startup, invoke [`register_metadata!`] at the start of `main()` instead.
 //!
 //! # Tutorials, examples and more...
 //! ... can be found in our [repository](https://github.com/fkie-cad/butterfly) and [wiki](https://github.com/fkie-cad/butterfly/wiki).
@@ -53,30 +105,58 @@
 #![deny(missing_docs)]
 #![deny(rustdoc::broken_intra_doc_links)]
 #![allow(clippy::new_without_default)]
-#![cfg_attr(feature = "safe_only", forbid(unsafe_code))]
+#![cfg_attr(all(feature = "safe_only", not(test)), forbid(unsafe_code))]
+#![cfg_attr(all(feature = "safe_only", test), deny(unsafe_code))]
 
+mod capture;
+#[cfg(unix)]
+mod desocket;
 mod event;
+mod executor;
+mod extractor;
 mod feedback;
 mod input;
 mod monitor;
 mod mutators;
 mod observer;
+mod registry;
+mod response;
 mod scheduler;
-
-pub use event::{USER_STAT_EDGES, USER_STAT_NODES};
-pub use feedback::StateFeedback;
-pub use input::{load_pcaps, HasPackets, HasPcapRepresentation};
+mod tokens;
+
+pub use capture::{Capture, CapturedPacket, Interface, PcapWriter};
+#[cfg(unix)]
+pub use desocket::DesocketExecutor;
+pub use event::{USER_STAT_COLD_EDGES, USER_STAT_EDGES, USER_STAT_NODES, USER_STAT_STATEGRAPH_DATA};
+pub use executor::{NetworkExecutor, NetworkExecutorBuilder, NetworkTarget, PacketSerializer, ReadOnce, ReadUntil, ResponseReader};
+pub use extractor::{BinaryFieldExtractor, RegexExtractor, ShapeExtractor, StateExtractor, StatusCodeExtractor, StatusLineExtractor};
+pub use feedback::{StateFeedback, StateNoveltyMetadata, StatePathFeedback, StatePathMetadata, StateTraceMetadata};
+pub use input::{dump_pcaps, load_pcaps, Direction, Flow, FlowFilter, FlowMessage, HasPackets, HasPcapRepresentation, PacketBytesInput, TransportProtocol};
 pub use monitor::{HasStateStats, StateMonitor};
 pub use mutators::{
-    supported_havoc_mutations, HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasSpliceMutation, PacketCrossoverInsertMutator, PacketCrossoverReplaceMutator, PacketDeleteMutator, PacketDuplicateMutator, PacketHavocMutator,
-    PacketReorderMutator, PacketSpliceMutator, SupportedHavocMutationsType,
+    supported_havoc_mutations, FocusedPacketSelector, HasCrossoverInsertMutation, HasCrossoverReplaceMutation, HasHavocMutation, HasMergeMutation, HasSpliceMutation, HasSplitMutation, PacketCorpusInsertMutator, PacketCorpusReplaceMutator,
+    PacketCrossoverInsertMutator, PacketCrossoverReplaceMutator, PacketDeleteMutator, PacketDuplicateMutator, PacketHavocMutator, PacketHavocMutatorBuilder, PacketMergeMutator, PacketRangeDeleteMutator, PacketRangeDuplicateMutator, PacketRangeMoveMutator,
+    PacketReorderMutator, PacketReverseMutator, PacketRotateMutator, PacketSelector, PacketSpliceMutator, PacketSplitMutator, PacketTokenInsertMutator, PacketTokenReplaceMutator, RandomPacketSelector, StackDepth, SupportedHavocMutationsType,
 };
-pub use observer::StateObserver;
-pub use scheduler::PacketMutationScheduler;
+pub use observer::{StateGraphMetadata, StateNovelty, StateObserver};
+pub use response::{ResponseObserver, RESPONSE_BUFFER_ENV};
+pub use scheduler::{HasPacketKind, PacketMutationScheduler, Potency, PotencyMetadata, PotencyMutationScheduler, ScheduledState, StateAwareScheduler, StateSchedulerMetadata};
+pub use tokens::{load_dictionary, load_pcap_tokens};
 
 #[cfg(feature = "graphviz")]
 pub use {event::USER_STAT_STATEGRAPH, monitor::GraphvizMonitor};
 
+#[cfg(feature = "derive")]
+pub use butterfly_derive::PacketMutations;
+
+/// Used by the code that `#[derive(PacketMutations)]` generates.
+/// Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use libafl;
+    pub use libafl_bolts;
+}
+
 /// The tests below are just for checking that harnesses compile
 /// with the butterfly components. We don't actually want to execute
 /// any harness.
@@ -87,13 +167,14 @@ mod tests {
             core_affinity::Cores,
             rands::StdRand,
             shmem::{ShMemProvider, StdShMemProvider},
-            tuples::{tuple_list, RefIndexable},
+            tuples::{tuple_list, Handle, MatchNameRef, RefIndexable},
             HasLen,
         };
     use libafl::{
         corpus::{CorpusId, InMemoryCorpus},
         events::{EventConfig, Launcher, SimpleEventManager},
         executors::{Executor, ExitKind, HasObservers},
+        feedback_or,
         feedbacks::CrashFeedback,
         inputs::{BytesInput, Input},
         mutators::{MutationId, MutationResult, MutatorsTuple},
@@ -103,8 +184,8 @@ mod tests {
         state::{HasMaxSize, HasRand, StdState},
         Error, Fuzzer, StdFuzzer,
     };
-    use pcap::{Capture, Offline};
     use serde::{Deserialize, Serialize};
+    use std::borrow::Cow;
     use std::fmt::{Debug, Formatter};
     use std::marker::PhantomData;
 
@@ -204,7 +285,7 @@ mod tests {
         }
     }
     impl HasPcapRepresentation<PacketInput> for PacketInput {
-        fn from_pcap(mut _capture: Capture<Offline>) -> Result<Self, Error> {
+        fn from_pcap(_capture: Capture) -> Result<Self, Error> {
             todo!();
         }
     }
@@ -242,7 +323,7 @@ mod tests {
         OT: ObserversTuple<PacketInput, S>,
     {
         fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, input: &PacketInput) -> Result<ExitKind, Error> {
-            let state_observer: &mut StateObserver<TargetState> = self.observers.match_name_mut("state").unwrap();
+            let state_observer = self.observers.get_mut(&Handle::<StateObserver<TargetState>>::new(Cow::Borrowed("state"))).unwrap();
 
             for _packet in &input.packets {
                 // do some I/O with packet data
@@ -291,7 +372,9 @@ mod tests {
                 PacketCrossoverInsertMutator::new(),
                 PacketCrossoverReplaceMutator::new(),
                 PacketDeleteMutator::new(4),
-                PacketDuplicateMutator::new(16)
+                PacketDuplicateMutator::new(16),
+                PacketTokenInsertMutator::new(),
+                PacketTokenReplaceMutator::new()
             ));
             let mut stages = tuple_list!(StdMutationalStage::new(mutator));
             let mut executor = ExampleExecutor::new(tuple_list!(state_observer));
@@ -330,45 +413,16 @@ mod tests {
         fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr).unwrap();
     }
 
-    #[derive(Hash, Debug, Clone, Serialize, Deserialize)]
-    struct RawInput {
-        packets: Vec<BytesInput>,
-    }
-    impl Input for RawInput {
-        fn generate_name(&self, _id: Option<CorpusId>) -> String {
-            todo!();
-        }
-    }
-    impl HasPackets<BytesInput> for RawInput {
-        fn packets(&self) -> &[BytesInput] {
-            &self.packets
-        }
-
-        fn packets_mut(&mut self) -> &mut Vec<BytesInput> {
-            &mut self.packets
-        }
-    }
-    impl HasLen for RawInput {
-        fn len(&self) -> usize {
-            self.packets.len()
-        }
-    }
-    impl HasPcapRepresentation<RawInput> for RawInput {
-        fn from_pcap(mut _capture: Capture<Offline>) -> Result<Self, Error> {
-            todo!();
-        }
-    }
-
     struct RawExecutor<OT, S>
     where
-        OT: ObserversTuple<RawInput, S>,
+        OT: ObserversTuple<PacketBytesInput, S>,
     {
         observers: OT,
         phantom: PhantomData<S>,
     }
     impl<OT, S> RawExecutor<OT, S>
     where
-        OT: ObserversTuple<RawInput, S>,
+        OT: ObserversTuple<PacketBytesInput, S>,
     {
         fn new(observers: OT) -> Self {
             Self {
@@ -379,20 +433,20 @@ mod tests {
     }
     impl<OT, S> Debug for RawExecutor<OT, S>
     where
-        OT: ObserversTuple<RawInput, S>,
+        OT: ObserversTuple<PacketBytesInput, S>,
     {
         fn fmt(&self, _f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
             todo!();
         }
     }
-    impl<OT, S, EM, Z> Executor<EM, RawInput, S, Z> for RawExecutor<OT, S>
+    impl<OT, S, EM, Z> Executor<EM, PacketBytesInput, S, Z> for RawExecutor<OT, S>
     where
-        OT: ObserversTuple<RawInput, S>,
+        OT: ObserversTuple<PacketBytesInput, S>,
     {
-        fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, input: &RawInput) -> Result<ExitKind, Error> {
-            let state_observer: &mut StateObserver<TargetState> = self.observers.match_name_mut("state").unwrap();
+        fn run_target(&mut self, _fuzzer: &mut Z, _state: &mut S, _mgr: &mut EM, input: &PacketBytesInput) -> Result<ExitKind, Error> {
+            let state_observer = self.observers.get_mut(&Handle::<StateObserver<TargetState>>::new(Cow::Borrowed("state"))).unwrap();
 
-            for _packet in &input.packets {
+            for _packet in input.packets() {
                 // do some I/O with packet data
 
                 // the executor is responsible for getting state information
@@ -407,7 +461,7 @@ mod tests {
     }
     impl<OT, S> HasObservers for RawExecutor<OT, S>
     where
-        OT: ObserversTuple<RawInput, S>,
+        OT: ObserversTuple<PacketBytesInput, S>,
     {
         type Observers = OT;
         fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
@@ -424,12 +478,12 @@ mod tests {
         let mon = StateMonitor::new();
         let mut mgr = SimpleEventManager::new(mon);
         let state_observer = StateObserver::<TargetState>::new("state");
-        let mut feedback = StateFeedback::new(&state_observer);
+        let mut feedback = feedback_or!(StateFeedback::new(&state_observer), StatePathFeedback::new(&state_observer, 3));
         let mut objective = CrashFeedback::new();
         let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::new(), InMemoryCorpus::new(), &mut feedback, &mut objective).unwrap();
-        let scheduler = QueueScheduler::new();
+        let scheduler = StateAwareScheduler::new(&state_observer);
         let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
-        let mutator = PacketMutationScheduler::new(tuple_list!(
+        let mutator = PotencyMutationScheduler::new(tuple_list!(
             PacketHavocMutator::new(supported_havoc_mutations()),
             PacketReorderMutator::new(),
             PacketSpliceMutator::new(4),
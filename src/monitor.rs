@@ -1,4 +1,7 @@
-use crate::event::{USER_STAT_EDGES, USER_STAT_NODES};
+use crate::{
+    event::{USER_STAT_COLD_EDGES, USER_STAT_EDGES, USER_STAT_NODES, USER_STAT_STATEGRAPH_DATA},
+    observer::StateGraphMetadata,
+};
 use libafl_bolts::{current_time, format_duration_hms, ClientId};
 use libafl::monitors::Monitor;
 use libafl::monitors::stats::{ClientStats, ClientStatsManager, UserStatsValue};
@@ -30,6 +33,58 @@ pub trait HasStateStats: Monitor {
         sum.stats_div(stats.len()).unwrap()
     }
 
+    /// Helper function used by the other functions.
+    fn calculate_max(&mut self, stat: &str, manager: &mut ClientStatsManager) -> UserStatsValue {
+        let mut max = UserStatsValue::Number(0);
+
+        for client_stat in manager.client_stats().iter() {
+            if let Some(user_stats) = client_stat.get_user_stats(stat) {
+                max = max.stats_max(user_stats.value()).unwrap();
+            }
+        }
+
+        max
+    }
+
+    /// Merge the state-graphs of all instances into the global state-graph.
+    ///
+    /// Every instance sends its whole state-graph whenever it changes, so this is the union
+    /// of all states and transitions that any instance has seen.
+    /// Returns None if no instance has sent its state-graph yet.
+    fn global_stategraph(&mut self, manager: &mut ClientStatsManager) -> Option<StateGraphMetadata> {
+        let mut global: Option<StateGraphMetadata> = None;
+
+        for client_stat in manager.client_stats().iter() {
+            if let Some(UserStatsValue::String(data)) = client_stat.get_user_stats(USER_STAT_STATEGRAPH_DATA).map(|s| s.value()) {
+                if let Some(graph) = StateGraphMetadata::decode(data) {
+                    global.get_or_insert_with(StateGraphMetadata::default).merge(&graph);
+                }
+            }
+        }
+
+        global
+    }
+
+    /// Get the number of vertices in the global state-graph.
+    ///
+    /// This is the size of the union of the state-graphs of all instances (see [`global_stategraph()`](HasStateStats::global_stategraph)).
+    /// If no instance has sent its state-graph yet, it falls back to the largest number of vertices reported by an instance.
+    fn global_statemachine_nodes(&mut self, manager: &mut ClientStatsManager) -> UserStatsValue {
+        match self.global_stategraph(manager) {
+            Some(graph) => UserStatsValue::Number(graph.nodes() as u64),
+            None => self.calculate_max(USER_STAT_NODES, manager),
+        }
+    }
+
+    /// Get the number of edges in the global state-graph.
+    /// Like [`global_statemachine_nodes()`](HasStateStats::global_statemachine_nodes) this is the size of the union.
+    fn global_statemachine_edges(&mut self, manager: &mut ClientStatsManager) -> UserStatsValue {
+        match self.global_stategraph(manager) {
+            Some(graph) => UserStatsValue::Number(graph.edges() as u64),
+            None => self.calculate_max(USER_STAT_EDGES, manager),
+        }
+    }
+
     /// Get the average number of vertices in the state-graphs across all instances.
     fn avg_statemachine_nodes(&mut self, manager: &mut ClientStatsManager) -> UserStatsValue {
         self.calculate_average(USER_STAT_NODES, manager)
@@ -39,10 +94,23 @@ pub trait HasStateStats: Monitor {
     fn avg_statemachine_edges(&mut self, manager: &mut ClientStatsManager) -> UserStatsValue {
         self.calculate_average(USER_STAT_EDGES, manager)
     }
+
+    /// Get the average number of rarely taken edges in the state-graphs across all instances.
+    /// Returns None if no instance counts hits.
+    fn avg_statemachine_cold_edges(&mut self, manager: &mut ClientStatsManager) -> Option<UserStatsValue> {
+        if manager.client_stats().iter().any(|client_stat| client_stat.get_user_stats(USER_STAT_COLD_EDGES).is_some()) {
+            Some(self.calculate_average(USER_STAT_COLD_EDGES, manager))
+        } else {
+            None
+        }
+    }
 }
 
 /// A monitor that prints information about the state-graph in addition to all other info.
 ///
+/// The number of nodes and edges is that of the union of the state-graphs of all
+/// fuzzer instances.
+///
 /// Works as a drop-in replacement for all other monitors.
 #[derive(Clone, Debug)]
 pub struct StateMonitor {
@@ -57,16 +125,6 @@ impl StateMonitor {
             start_time: current_time(),
         }
     }
-
-    fn max_corpus_size(&self) -> u64 {
-        let mut val = 0;
-
-        for client_stat in &self.client_stats {
-            val = std::cmp::max(val, client_stat.corpus_size());
-        }
-
-        val
-    }
 }
 
 impl HasStateStats for StateMonitor {}
@@ -93,18 +151,24 @@ impl Monitor for StateMonitor {
     fn display(&mut self,
         mgr: &mut ClientStatsManager,
         event_msg: &str,
-        sender_id: ClientId
+        _sender_id: ClientId
     ) {
-        let num_nodes = self.avg_statemachine_nodes(mgr);
-        let num_edges = self.avg_statemachine_edges(mgr);
+        let (num_nodes, num_edges) = match self.global_stategraph(mgr) {
+            Some(graph) => (UserStatsValue::Number(graph.nodes() as u64), UserStatsValue::Number(graph.edges() as u64)),
+            None => (self.calculate_max(USER_STAT_NODES, mgr), self.calculate_max(USER_STAT_EDGES, mgr)),
+        };
         let corpus_size = mgr.client_stats().iter().fold(0u64, |acc, x| acc + x.corpus_size());
         let objective_size = mgr.client_stats().iter().fold(0u64, |acc, x| acc + x.objective_size());       
         let execs = mgr.client_stats().iter().fold(0u64, |acc, x| acc + x.executions());
         let execs_per_sec = execs as f64 / ((current_time() - self.start_time).as_secs() as f64);
         let cores = std::cmp::max(1, self.client_stats.len().saturating_sub(1));
+        let cold_edges = match self.avg_statemachine_cold_edges(mgr) {
+            Some(cold_edges) => format!(" | cold edges: {}", cold_edges),
+            None => String::new(),
+        };
 
         println!(
-            "[butterfly::{}] uptime: {} | cores: {} | corpus: {} | objectives: {} | total execs: {} | exec/s: {} | nodes: {} | edges: {}",
+            "[butterfly::{}] uptime: {} | cores: {} | corpus: {} | objectives: {} | total execs: {} | exec/s: {} | nodes: {} | edges: {}{}",
             event_msg,
             format_duration_hms(&(current_time() - self.start_time)),
             cores,
@@ -114,6 +178,7 @@ impl Monitor for StateMonitor {
             execs_per_sec,
             num_nodes,
             num_edges,
+            cold_edges,
         );
     }
 }
@@ -215,3 +280,44 @@ where
         self.base.display(client_stats_manager, event_msg, sender_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::monitors::stats::{AggregatorOps, UserStats};
+    use std::borrow::Cow;
+
+    fn send(manager: &mut ClientStatsManager, client: u32, stat: &'static str, value: UserStatsValue) {
+        manager.client_stats_insert(ClientId(client));
+        manager.update_client_stats_for(ClientId(client), |client_stat| {
+            client_stat.update_user_stats(Cow::Borrowed(stat), UserStats::new(value, AggregatorOps::None));
+        });
+    }
+
+    fn graph(states: &[u32]) -> StateGraphMetadata {
+        let mut graph = StateGraphMetadata::default();
+        let path: Vec<u32> = states.iter().map(|s| graph.state_id(postcard::to_allocvec(s).unwrap()).0).collect();
+        let packets: Vec<usize> = (0..path.len()).collect();
+        graph.add_path(&path, &packets, &[], 0);
+        graph
+    }
+
+    #[test]
+    fn test_global_stategraph() {
+        let mut monitor = StateMonitor::new();
+        let mut manager = ClientStatsManager::new();
+
+        send(&mut manager, 1, USER_STAT_NODES, UserStatsValue::Number(2));
+        send(&mut manager, 2, USER_STAT_NODES, UserStatsValue::Number(3));
+        assert!(monitor.global_stategraph(&mut manager).is_none());
+        assert_eq!(monitor.global_statemachine_nodes(&mut manager).to_string(), "3");
+
+        let (a, b) = (graph(&[1, 2]), graph(&[2, 1, 3]));
+        send(&mut manager, 1, USER_STAT_STATEGRAPH_DATA, UserStatsValue::String(Cow::Owned(a.encode().unwrap())));
+        send(&mut manager, 2, USER_STAT_STATEGRAPH_DATA, UserStatsValue::String(Cow::Owned(b.encode().unwrap())));
+
+        // 1->2 and 2->1->3 share no edge
+        assert_eq!(monitor.global_statemachine_nodes(&mut manager).to_string(), "3");
+        assert_eq!(monitor.global_statemachine_edges(&mut manager).to_string(), "3");
+    }
+}
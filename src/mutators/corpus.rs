@@ -0,0 +1,285 @@
+use crate::input::HasPackets;
+use libafl_bolts::{rands::Rand, HasLen, Named};
+use libafl::{
+    corpus::{Corpus, CorpusId},
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    random_corpus_id,
+    state::{HasCorpus, HasRand},
+    Error,
+};
+use std::{borrow::Cow, marker::PhantomData, mem, num::NonZero};
+
+/// Pick a random corpus entry that is not the one currently being fuzzed
+fn other_testcase<I, S>(state: &mut S) -> Option<CorpusId>
+where
+    S: HasCorpus<I> + HasRand,
+{
+    if state.corpus().count() == 0 {
+        return None;
+    }
+
+    let id = random_corpus_id!(state.corpus(), state.rand_mut());
+
+    if *state.corpus().current() == Some(id) {
+        None
+    } else {
+        Some(id)
+    }
+}
+
+/// Returns true if `a` and `b` contain the same types of packets in the same order.
+/// For enums the type of a packet is its variant.
+fn compatible<P>(a: &[P], b: &[P]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| mem::discriminant(a) == mem::discriminant(b))
+}
+
+/// A mutator that inserts a sequence of packets from another corpus entry into the input.
+///
+/// This is the packet-level equivalent of LibAFLs [`CrossoverInsertMutator`](libafl::mutators::CrossoverInsertMutator).
+/// It respects an upper bound on the number of packets passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we never exceed 16 packets in an input
+/// let mutator = PacketCorpusInsertMutator::new(16);
+/// ```
+pub struct PacketCorpusInsertMutator<P>
+where
+    P: Clone,
+{
+    max_packets: usize,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketCorpusInsertMutator<P>
+where
+    P: Clone,
+{
+    /// Create a new PacketCorpusInsertMutator with an upper bound on the number of packets
+    pub fn new(max_packets: usize) -> Self {
+        Self {
+            max_packets,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketCorpusInsertMutator<P>
+where
+    P: Clone,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasCorpus<I> + HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() >= self.max_packets {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let Some(id) = other_testcase(state) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let other = state.corpus().cloned_input_for_id(id)?;
+
+        if other.len() == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let max_len = other.len().min(self.max_packets - input.len());
+        let len = 1 + state.rand_mut().below(NonZero::new(max_len).unwrap());
+        let from = state.rand_mut().below(NonZero::new(other.len() - len + 1).unwrap());
+        let to = state.rand_mut().below(NonZero::new(input.len() + 1).unwrap());
+
+        input.packets_mut().splice(to..to, other.packets()[from..from + len].iter().cloned());
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketCorpusInsertMutator<P>
+where
+    P: Clone,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketCorpusInsertMutator")
+    }
+}
+
+/// A mutator that overwrites a sequence of packets with packets from another corpus entry.
+///
+/// Only sequences that consist of the same types of packets get replaced, so an `A` packet is
+/// only ever replaced by another `A` packet. The mutator picks a sequence in the input
+/// and searches the other corpus entry for a compatible sequence of the same length.
+/// The number of packets stays the same.
+///
+/// # Example
+/// ```
+/// let mutator = PacketCorpusReplaceMutator::new();
+/// ```
+pub struct PacketCorpusReplaceMutator<P>
+where
+    P: Clone,
+{
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketCorpusReplaceMutator<P>
+where
+    P: Clone,
+{
+    /// Create a new PacketCorpusReplaceMutator
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketCorpusReplaceMutator<P>
+where
+    P: Clone,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasCorpus<I> + HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let Some(id) = other_testcase(state) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let other = state.corpus().cloned_input_for_id(id)?;
+
+        if other.len() == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let max_len = other.len().min(input.len());
+        let len = 1 + state.rand_mut().below(NonZero::new(max_len).unwrap());
+        let to = state.rand_mut().below(NonZero::new(input.len() - len + 1).unwrap());
+
+        let destination = &input.packets()[to..to + len];
+        let candidates = (0..=other.len() - len).filter(|from| compatible(destination, &other.packets()[*from..*from + len]));
+
+        let Some(from) = state.rand_mut().choose(candidates) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        input.packets_mut()[to..to + len].clone_from_slice(&other.packets()[from..from + len]);
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketCorpusReplaceMutator<P>
+where
+    P: Clone,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketCorpusReplaceMutator")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PacketBytesInput;
+    use libafl::{
+        corpus::{InMemoryCorpus, Testcase},
+        inputs::BytesInput,
+        state::StdState,
+    };
+    use libafl_bolts::rands::StdRand;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
+    enum Packet {
+        A(u8),
+        B(u8),
+    }
+
+    #[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
+    struct PacketInput {
+        packets: Vec<Packet>,
+    }
+    impl Input for PacketInput {}
+    impl HasPackets<Packet> for PacketInput {
+        fn packets(&self) -> &[Packet] {
+            &self.packets
+        }
+
+        fn packets_mut(&mut self) -> &mut Vec<Packet> {
+            &mut self.packets
+        }
+    }
+    impl HasLen for PacketInput {
+        fn len(&self) -> usize {
+            self.packets.len()
+        }
+    }
+
+    #[test]
+    fn test_compatible() {
+        assert!(compatible(&[Packet::A(0), Packet::B(1)], &[Packet::A(2), Packet::B(3)]));
+        assert!(!compatible(&[Packet::A(0), Packet::B(1)], &[Packet::B(2), Packet::A(3)]));
+        assert!(!compatible(&[Packet::A(0)], &[Packet::A(0), Packet::A(0)]));
+    }
+
+    #[test]
+    fn test_corpus_mutators() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<PacketBytesInput>::new(), InMemoryCorpus::new(), &mut (), &mut ()).unwrap();
+        let input = PacketBytesInput::from(vec![b"A".to_vec(), b"B".to_vec()]);
+        let id = state.corpus_mut().add(Testcase::new(input.clone())).unwrap();
+        *state.corpus_mut().current_mut() = Some(id);
+
+        // the only corpus entry is the one being fuzzed
+        let mut insert = PacketCorpusInsertMutator::<BytesInput>::new(4);
+        let mut replace = PacketCorpusReplaceMutator::<BytesInput>::new();
+        assert_eq!(insert.mutate(&mut state, &mut input.clone()).unwrap(), MutationResult::Skipped);
+
+        state.corpus_mut().add(Testcase::new(PacketBytesInput::from(vec![b"X".to_vec(), b"Y".to_vec(), b"Z".to_vec()]))).unwrap();
+
+        for _ in 0..100 {
+            let mut mutated = input.clone();
+
+            if insert.mutate(&mut state, &mut mutated).unwrap() == MutationResult::Mutated {
+                assert!((3..=4).contains(&mutated.len()));
+            }
+
+            let mut mutated = input.clone();
+
+            if replace.mutate(&mut state, &mut mutated).unwrap() == MutationResult::Mutated {
+                assert_eq!(mutated.len(), 2);
+                assert_ne!(mutated, input);
+            }
+        }
+
+        // inputs at the upper bound are left alone
+        let mut full = PacketBytesInput::from(vec![b"A".to_vec(); 4]);
+        assert_eq!(insert.mutate(&mut state, &mut full).unwrap(), MutationResult::Skipped);
+    }
+
+    #[test]
+    fn test_replace_searches_compatible_range() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<PacketInput>::new(), InMemoryCorpus::new(), &mut (), &mut ()).unwrap();
+        let input = PacketInput {
+            packets: vec![Packet::A(0), Packet::B(0)],
+        };
+
+        // [A, B] only occurs at the end of the only corpus entry
+        let other = PacketInput {
+            packets: vec![Packet::B(1), Packet::B(2), Packet::A(3), Packet::B(4)],
+        };
+        state.corpus_mut().add(Testcase::new(other)).unwrap();
+
+        let mut replace = PacketCorpusReplaceMutator::<Packet>::new();
+
+        for _ in 0..100 {
+            let mut mutated = input.clone();
+            assert_eq!(replace.mutate(&mut state, &mut mutated).unwrap(), MutationResult::Mutated);
+            assert_ne!(mutated, input);
+            assert!(compatible(mutated.packets(), input.packets()));
+        }
+    }
+}
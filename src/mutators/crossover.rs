@@ -16,6 +16,9 @@ use std::{borrow::Cow, marker::PhantomData, num::NonZero};
 ///
 /// Already implemented for
 /// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - [`Option<T>`](core::option::Option) if `T` implements this trait. `None` is never mutated.
+///
+/// With the `derive` feature, `#[derive(PacketMutations)]` generates the impl shown below.
 ///
 /// # Example
 /// Suppose we have the following packet type
@@ -73,8 +76,8 @@ where
         let other_len = NonZero::new(other.len()).unwrap();
 
         let from = state.rand_mut().below(other_len);
-        let to = state.rand_mut().below(self_len) as usize;
-        let len = state.rand_mut().below(NonZero::new(other.len() - from).unwrap()) as usize + 1;
+        let to = state.rand_mut().below(self_len);
+        let len = state.rand_mut().below(NonZero::new(other.len() - from).unwrap()) + 1;
 
         // Make room for `len` additional bytes
         self.as_mut().resize(usize::from(self_len) + len, 0);
@@ -89,6 +92,19 @@ where
     }
 }
 
+impl<S, T> HasCrossoverInsertMutation<S> for Option<T>
+where
+    T: HasCrossoverInsertMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_insert(&mut self, state: &mut S, other: &Self) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (Some(packet), Some(other_packet)) => packet.mutate_crossover_insert(state, other_packet),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
 /// Like libafls [`CrossoverInsertMutator`](libafl::mutators::mutations::CrossoverInsertMutator)
 /// but for two packets in one seed.
 ///
@@ -126,8 +142,8 @@ where
         }
 
         let input_len = NonZero::new(input.len()).unwrap();
-        let packet = state.rand_mut().below(input_len) as usize;
-        let other = state.rand_mut().below(input_len) as usize;
+        let packet = state.rand_mut().below(input_len);
+        let other = state.rand_mut().below(input_len);
 
         if packet == other {
             return Ok(MutationResult::Skipped);
@@ -165,6 +181,9 @@ where
 ///
 /// Already implemented for
 /// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - [`Option<T>`](core::option::Option) if `T` implements this trait. `None` is never mutated.
+///
+/// With the `derive` feature, `#[derive(PacketMutations)]` generates the impl shown below.
 ///
 /// # Example
 /// Suppose we have the following packet type
@@ -231,6 +250,19 @@ where
     }
 }
 
+impl<S, T> HasCrossoverReplaceMutation<S> for Option<T>
+where
+    T: HasCrossoverReplaceMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_crossover_replace(&mut self, state: &mut S, other: &Self) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (Some(packet), Some(other_packet)) => packet.mutate_crossover_replace(state, other_packet),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
 /// Like libafls [`CrossoverReplaceMutator`](libafl::mutators::mutations::CrossoverReplaceMutator)
 /// but for two packets in one seed.
 ///
@@ -267,8 +299,8 @@ where
             return Ok(MutationResult::Skipped);
         }
 
-        let packet = state.rand_mut().below(NonZero::new(input.len()).unwrap()) as usize;
-        let other = state.rand_mut().below(NonZero::new(input.len()).unwrap()) as usize;
+        let packet = state.rand_mut().below(NonZero::new(input.len()).unwrap());
+        let other = state.rand_mut().below(NonZero::new(input.len()).unwrap());
 
         if packet == other {
             return Ok(MutationResult::Skipped);
@@ -43,7 +43,7 @@ where
             return Ok(MutationResult::Skipped);
         }
 
-        let idx = state.rand_mut().below(NonZero::new(input.len()).unwrap()) as usize;
+        let idx = state.rand_mut().below(NonZero::new(input.len()).unwrap());
         input.packets_mut().remove(idx);
 
         Ok(MutationResult::Mutated)
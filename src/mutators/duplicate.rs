@@ -50,8 +50,8 @@ where
             return Ok(MutationResult::Skipped);
         }
 
-        let from = state.rand_mut().below(NonZero::new(input.len()).unwrap()) as usize;
-        let to = state.rand_mut().below(NonZero::new(input.len() + 1).unwrap()) as usize;
+        let from = state.rand_mut().below(NonZero::new(input.len()).unwrap());
+        let to = state.rand_mut().below(NonZero::new(input.len() + 1).unwrap());
 
         if from == to {
             return Ok(MutationResult::Skipped);
@@ -1,4 +1,7 @@
-use crate::input::HasPackets;
+use crate::{
+    input::HasPackets,
+    mutators::select::{PacketSelector, RandomPacketSelector},
+};
 use libafl_bolts::{
     rands::Rand,
     tuples::tuple_list,
@@ -94,6 +97,9 @@ pub fn supported_havoc_mutations() -> SupportedHavocMutationsType {
 ///
 /// Already implemented for:
 /// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - [`Option<T>`](core::option::Option) if `T` implements this trait. `None` is never mutated.
+///
+/// With the `derive` feature, `#[derive(PacketMutations)]` generates the impl shown below.
 ///
 /// # Example
 /// Suppose we have the following packet type
@@ -143,10 +149,63 @@ where
     }
 }
 
+impl<MT, S, T> HasHavocMutation<MT, S> for Option<T>
+where
+    T: HasHavocMutation<MT, S>,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_havoc(&mut self, state: &mut S, mutations: &mut MT, mutation: MutationId) -> Result<MutationResult, Error> {
+        match self {
+            Some(packet) => packet.mutate_havoc(state, mutations, mutation),
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+/// How many havoc mutations the [`PacketHavocMutator`] stacks in a single run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackDepth {
+    /// Always apply the given number of mutations
+    Fixed(usize),
+    /// Apply between 1 and the given number of mutations, all equally likely
+    Uniform(usize),
+    /// Apply 2, 4, 8, ... up to 2^n mutations, all powers equally likely. This is what AFL does with n = 7.
+    /// `n` is clamped to 16, so at most 65536 mutations are stacked.
+    PowerOfTwo(u32),
+}
+
+impl StackDepth {
+    /// Draw the number of mutations for the next run. This is at least 1.
+    pub fn sample<R>(&self, rand: &mut R) -> usize
+    where
+        R: Rand,
+    {
+        match *self {
+            StackDepth::Fixed(n) => n.max(1),
+            StackDepth::Uniform(n) => 1 + rand.below(NonZero::new(n.max(1)).unwrap()),
+            StackDepth::PowerOfTwo(n) => 1 << (1 + rand.below(NonZero::new(n.clamp(1, 16) as usize).unwrap())),
+        }
+    }
+}
+
+impl Default for StackDepth {
+    fn default() -> Self {
+        StackDepth::Uniform(16)
+    }
+}
+
 /// A mutator that applies a set of havoc mutations to a single packet.
 ///
 /// `P` denotes the packet type that MUST implement [`HasHavocMutation`].
-pub struct PacketHavocMutator<I, MT, S, P>
+///
+/// The packet is chosen by a [`PacketSelector`], by default the
+/// [`RandomPacketSelector`]. Use [`PacketHavocMutator::with_selector()`] with a
+/// [`FocusedPacketSelector`](crate::FocusedPacketSelector) to focus on packets after newly reached states.
+///
+/// By default it stacks 1 to 16 mutations on one packet. Use a [`PacketHavocMutatorBuilder`]
+/// to change the [`StackDepth`] or to spread the mutations over multiple packets.
+pub struct PacketHavocMutator<I, MT, S, P, SEL = RandomPacketSelector>
 where
     P: HasHavocMutation<MT, S>,
     I: Input + HasLen + HasPackets<P>,
@@ -155,6 +214,9 @@ where
 {
     /// These mutation operators must exclusively be for BytesInputs
     mutations: MT,
+    selector: SEL,
+    depth: StackDepth,
+    packets: usize,
     phantom: PhantomData<(I, S, P)>,
 }
 
@@ -168,15 +230,25 @@ where
     /// Create a new PacketHavocMutator with mutators that can be
     /// applied to [`BytesInputs`](libafl::inputs::BytesInput).
     pub fn new(mutations: MT) -> Self {
-        Self {
-            mutations,
-            phantom: PhantomData,
-        }
+        Self::with_selector(mutations, RandomPacketSelector)
+    }
+}
+
+impl<I, MT, S, P, SEL> PacketHavocMutator<I, MT, S, P, SEL>
+where
+    P: HasHavocMutation<MT, S>,
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<BytesInput, S>,
+    S: HasRand + HasMaxSize,
+{
+    /// Create a new PacketHavocMutator that mutates the packets chosen by `selector`.
+    pub fn with_selector(mutations: MT, selector: SEL) -> Self {
+        PacketHavocMutatorBuilder::new(mutations).selector(selector).build()
     }
 
     /// Get the number of stacked mutations to apply
-    fn iterations(&self, state: &mut S) -> u64 {
-        state.rand_mut().below(NonZero::new(16).unwrap()) as u64
+    fn iterations(&self, state: &mut S) -> usize {
+        self.depth.sample(state.rand_mut())
     }
 
     /// Get the next mutation to apply (index into mutation list)
@@ -185,12 +257,13 @@ where
     }
 }
 
-impl<I, MT, S, P> Mutator<I, S> for PacketHavocMutator<I, MT, S, P>
+impl<I, MT, S, P, SEL> Mutator<I, S> for PacketHavocMutator<I, MT, S, P, SEL>
 where
     P: HasHavocMutation<MT, S>,
     I: Input + HasLen + HasPackets<P>,
     MT: MutatorsTuple<BytesInput, S>,
     S: HasRand + HasMaxSize,
+    SEL: PacketSelector<I, S>,
 {
     fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
         if input.len() == 0 {
@@ -199,10 +272,19 @@ where
 
         let mut result = MutationResult::Skipped;
         let iters = self.iterations(state);
-        let packet = state.rand_mut().below(NonZero::new(input.len()).unwrap()) as usize;
+        let mut packets = Vec::with_capacity(self.packets);
+
+        for _ in 0..self.packets.min(input.len()) {
+            let packet = self.selector.select_packet(state, input);
+
+            if !packets.contains(&packet) {
+                packets.push(packet);
+            }
+        }
 
         for _ in 0..iters {
             let mutation = self.schedule(state);
+            let packet = packets[state.rand_mut().below(NonZero::new(packets.len()).unwrap())];
 
             let outcome = input.packets_mut()[packet].mutate_havoc(state, &mut self.mutations, mutation)?;
 
@@ -215,7 +297,80 @@ where
     }
 }
 
-impl<I, MT, S, P> Named for PacketHavocMutator<I, MT, S, P>
+/// Builds a [`PacketHavocMutator`] with a custom configuration.
+///
+/// # Example
+/// ```
+/// // AFL-style stacking of up to 128 mutations on up to 3 packets
+/// let mutator = PacketHavocMutatorBuilder::new(supported_havoc_mutations())
+///     .stack_depth(StackDepth::PowerOfTwo(7))
+///     .packets(3)
+///     .selector(FocusedPacketSelector::new())
+///     .build();
+/// ```
+#[derive(Debug)]
+pub struct PacketHavocMutatorBuilder<MT, SEL = RandomPacketSelector> {
+    mutations: MT,
+    selector: SEL,
+    depth: StackDepth,
+    packets: usize,
+}
+
+impl<MT> PacketHavocMutatorBuilder<MT> {
+    /// Create a new builder with mutators that can be
+    /// applied to [`BytesInputs`](libafl::inputs::BytesInput).
+    pub fn new(mutations: MT) -> Self {
+        Self {
+            mutations,
+            selector: RandomPacketSelector,
+            depth: StackDepth::default(),
+            packets: 1,
+        }
+    }
+}
+
+impl<MT, SEL> PacketHavocMutatorBuilder<MT, SEL> {
+    /// How many mutations to stack in a single run. Default: `StackDepth::Uniform(16)`
+    pub fn stack_depth(mut self, depth: StackDepth) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Spread the stacked mutations over up to `packets` different packets. Default: 1
+    pub fn packets(mut self, packets: usize) -> Self {
+        self.packets = packets.max(1);
+        self
+    }
+
+    /// The [`PacketSelector`] that chooses the packets to mutate. Default: [`RandomPacketSelector`]
+    pub fn selector<SEL2>(self, selector: SEL2) -> PacketHavocMutatorBuilder<MT, SEL2> {
+        PacketHavocMutatorBuilder {
+            mutations: self.mutations,
+            selector,
+            depth: self.depth,
+            packets: self.packets,
+        }
+    }
+
+    /// Create the [`PacketHavocMutator`]
+    pub fn build<I, S, P>(self) -> PacketHavocMutator<I, MT, S, P, SEL>
+    where
+        P: HasHavocMutation<MT, S>,
+        I: Input + HasLen + HasPackets<P>,
+        MT: MutatorsTuple<BytesInput, S>,
+        S: HasRand + HasMaxSize,
+    {
+        PacketHavocMutator {
+            mutations: self.mutations,
+            selector: self.selector,
+            depth: self.depth,
+            packets: self.packets,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, MT, S, P, SEL> Named for PacketHavocMutator<I, MT, S, P, SEL>
 where
     P: HasHavocMutation<MT, S>,
     I: Input + HasLen + HasPackets<P>,
@@ -226,3 +381,95 @@ where
         &Cow::Borrowed("PacketHavocMutator")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PacketBytesInput;
+    use libafl_bolts::rands::StdRand;
+
+    struct TestState {
+        rand: StdRand,
+        max_size: usize,
+    }
+    impl TestState {
+        fn new() -> Self {
+            Self {
+                rand: StdRand::with_seed(0),
+                max_size: 1024,
+            }
+        }
+    }
+    impl HasRand for TestState {
+        type Rand = StdRand;
+
+        fn rand(&self) -> &StdRand {
+            &self.rand
+        }
+
+        fn rand_mut(&mut self) -> &mut StdRand {
+            &mut self.rand
+        }
+    }
+    impl HasMaxSize for TestState {
+        fn max_size(&self) -> usize {
+            self.max_size
+        }
+
+        fn set_max_size(&mut self, max_size: usize) {
+            self.max_size = max_size;
+        }
+    }
+
+    /// Selects the packets in order so that the tests don't depend on the seed
+    struct CyclingSelector(usize);
+    impl<S> PacketSelector<PacketBytesInput, S> for CyclingSelector {
+        fn select_packet(&mut self, _state: &mut S, input: &PacketBytesInput) -> usize {
+            self.0 += 1;
+            (self.0 - 1) % input.len()
+        }
+    }
+
+    #[test]
+    fn test_stack_depth() {
+        let mut rand = StdRand::with_seed(0);
+
+        for _ in 0..1000 {
+            assert!((1..=16).contains(&StackDepth::Uniform(16).sample(&mut rand)));
+            assert!([2, 4, 8].contains(&StackDepth::PowerOfTwo(3).sample(&mut rand)));
+        }
+
+        // the exponent is clamped
+        for _ in 0..1000 {
+            let depth = StackDepth::PowerOfTwo(u32::MAX).sample(&mut rand);
+            assert!(depth.is_power_of_two() && (2..=1 << 16).contains(&depth));
+        }
+
+        assert_eq!(StackDepth::Uniform(0).sample(&mut rand), 1);
+        assert_eq!(StackDepth::Fixed(0).sample(&mut rand), 1);
+    }
+
+    #[test]
+    fn test_multi_packet_havoc() {
+        let mut state = TestState::new();
+        let input = PacketBytesInput::from(vec![vec![0; 8]; 4]);
+
+        // a single mutation always succeeds
+        let mut mutator = PacketHavocMutatorBuilder::new(tuple_list!(BitFlipMutator::new())).stack_depth(StackDepth::Fixed(1)).build();
+        for _ in 0..100 {
+            assert_eq!(mutator.mutate(&mut state, &mut input.clone()).unwrap(), MutationResult::Mutated);
+        }
+
+        // only the selected packets are mutated
+        let mut mutator = PacketHavocMutatorBuilder::new(tuple_list!(BitFlipMutator::new())).selector(CyclingSelector(0)).stack_depth(StackDepth::Fixed(64)).packets(2).build();
+        let mut mutated = input.clone();
+        assert_eq!(mutator.mutate(&mut state, &mut mutated).unwrap(), MutationResult::Mutated);
+        assert_eq!(mutated.packets()[2..], input.packets()[2..]);
+
+        let mut mutator = PacketHavocMutator::new(tuple_list!(BitFlipMutator::new()));
+        let mut mutated = input.clone();
+        mutator.mutate(&mut state, &mut mutated).unwrap();
+        let changed = mutated.packets().iter().zip(input.packets()).filter(|(a, b)| a != b).count();
+        assert!(changed <= 1);
+    }
+}
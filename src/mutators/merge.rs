@@ -0,0 +1,152 @@
+use crate::input::HasPackets;
+use libafl_bolts::{rands::Rand, HasLen, Named};
+use libafl::{
+    inputs::{BytesInput, Input},
+    mutators::{MutationResult, Mutator},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use std::{borrow::Cow, marker::PhantomData, num::NonZero};
+
+/// Signifies that a packet type supports the [`PacketMergeMutator`] mutator.
+///
+/// If you want to use the [`PacketMergeMutator`] your Input type must have a vector
+/// of packets that implement this trait.
+/// IMPORTANT: This must be implemented on the packet type, NOT the Input type.
+///
+/// Already implemented for:
+/// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - [`Option<T>`](core::option::Option) if `T` implements this trait. `None` is never mutated.
+///
+/// With the `derive` feature, `#[derive(PacketMutations)]` generates the impl shown below.
+///
+/// # Example
+/// Suppose we have the following packet type
+/// ```
+/// enum PacketType {
+///    A(BytesInput),
+///    B(BytesInput),
+/// }
+/// ```
+/// Then we can implement this trait as follows
+/// ```
+/// impl<S> HasMergeMutation<S> for PacketType
+/// where
+///    S: HasRand + HasMaxSize,
+/// {
+///    fn mutate_merge(&mut self, state: &mut S, other: &Self) -> Result<MutationResult, Error> {
+///        match (self, other) {
+///            (PacketType::A(data), PacketType::A(other_data)) => data.mutate_merge(state, other_data),
+///            (PacketType::B(data), PacketType::B(other_data)) => data.mutate_merge(state, other_data),
+///            _ => Ok(MutationResult::Skipped),
+///        }
+///    }
+/// }
+/// ```
+/// And now we are able to use the [`PacketMergeMutator`].
+pub trait HasMergeMutation<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    /// Append the contents of `other` to `self`.
+    ///
+    /// The arguments to this function are similar to [`Mutator::mutate()`](libafl::mutators::Mutator::mutate).
+    fn mutate_merge(&mut self, state: &mut S, other: &Self) -> Result<MutationResult, Error>;
+}
+
+impl<S> HasMergeMutation<S> for BytesInput
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_merge(&mut self, _state: &mut S, other: &Self) -> Result<MutationResult, Error> {
+        if other.len() == 0 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        self.as_mut().extend_from_slice(other.as_ref());
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<S, T> HasMergeMutation<S> for Option<T>
+where
+    T: HasMergeMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_merge(&mut self, state: &mut S, other: &Self) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (Some(packet), Some(other_packet)) => packet.mutate_merge(state, other_packet),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+/// A mutator that merges two adjacent packets into one.
+///
+/// This simulates multiple messages that arrive in a single read at the target.
+/// `P` denotes the type of an individual packet that MUST implement [`HasMergeMutation`].
+/// PacketMergeMutator respects a lower bound on the number of packets
+/// passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we always have at least 4 packets
+/// let mutator = PacketMergeMutator::new(4);
+/// ```
+pub struct PacketMergeMutator<P, S>
+where
+    P: HasMergeMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    phantom: PhantomData<(P, S)>,
+    min_packets: usize,
+}
+
+impl<P, S> PacketMergeMutator<P, S>
+where
+    P: HasMergeMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    /// Create a new PacketMergeMutator with a lower bound for the number of packets
+    pub fn new(min_packets: usize) -> Self {
+        Self {
+            phantom: PhantomData,
+            min_packets: std::cmp::max(1, min_packets),
+        }
+    }
+}
+
+impl<I, P, S> Mutator<I, S> for PacketMergeMutator<P, S>
+where
+    P: HasMergeMutation<S>,
+    S: HasRand + HasMaxSize,
+    I: Input + HasLen + HasPackets<P>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() <= self.min_packets {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let packet = state.rand_mut().below(NonZero::new(input.len() - 1).unwrap());
+        let other = input.packets_mut().remove(packet + 1);
+
+        let ret = input.packets_mut()[packet].mutate_merge(state, &other)?;
+
+        if ret == MutationResult::Skipped {
+            input.packets_mut().insert(packet + 1, other);
+        }
+
+        Ok(ret)
+    }
+}
+
+impl<P, S> Named for PacketMergeMutator<P, S>
+where
+    P: HasMergeMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketMergeMutator")
+    }
+}
@@ -1,13 +1,25 @@
+mod corpus;
 mod crossover;
 mod delete;
 mod duplicate;
 mod havoc;
+mod merge;
+mod range;
 mod reorder;
+mod select;
 mod splice;
+mod split;
+mod token;
 
+pub use corpus::{PacketCorpusInsertMutator, PacketCorpusReplaceMutator};
 pub use crossover::{HasCrossoverInsertMutation, HasCrossoverReplaceMutation, PacketCrossoverInsertMutator, PacketCrossoverReplaceMutator};
 pub use delete::PacketDeleteMutator;
 pub use duplicate::PacketDuplicateMutator;
-pub use havoc::{supported_havoc_mutations, HasHavocMutation, PacketHavocMutator, SupportedHavocMutationsType};
+pub use havoc::{supported_havoc_mutations, HasHavocMutation, PacketHavocMutator, PacketHavocMutatorBuilder, StackDepth, SupportedHavocMutationsType};
+pub use merge::{HasMergeMutation, PacketMergeMutator};
+pub use range::{PacketRangeDeleteMutator, PacketRangeDuplicateMutator, PacketRangeMoveMutator, PacketReverseMutator, PacketRotateMutator};
 pub use reorder::PacketReorderMutator;
+pub use select::{FocusedPacketSelector, PacketSelector, RandomPacketSelector};
 pub use splice::{HasSpliceMutation, PacketSpliceMutator};
+pub use split::{HasSplitMutation, PacketSplitMutator};
+pub use token::{PacketTokenInsertMutator, PacketTokenReplaceMutator};
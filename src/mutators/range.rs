@@ -0,0 +1,331 @@
+use crate::input::HasPackets;
+use libafl_bolts::{rands::Rand, HasLen, Named};
+use libafl::{
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+use std::{borrow::Cow, marker::PhantomData, num::NonZero, ops::Range};
+
+/// How often the [`PacketRangeDuplicateMutator`] repeats a range at most
+const MAX_REPEATS: usize = 8;
+
+/// Pick a random, non-empty range of at most `max_len` packets in an input with `len` packets
+fn random_range<R: Rand>(rand: &mut R, len: usize, max_len: usize) -> Range<usize> {
+    let max_len = std::cmp::min(len, max_len);
+    let range_len = 1 + rand.below(NonZero::new(max_len).unwrap());
+    let start = rand.below(NonZero::new(len - range_len + 1).unwrap());
+    start..start + range_len
+}
+
+/// A mutator that deletes a random range of consecutive packets.
+///
+/// It respects a lower bound on the number of packets
+/// passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we always have at least 4 packets in an input
+/// let mutator = PacketRangeDeleteMutator::new(4);
+/// ```
+pub struct PacketRangeDeleteMutator<P> {
+    phantom: PhantomData<P>,
+    min_packets: usize,
+}
+
+impl<P> PacketRangeDeleteMutator<P> {
+    /// Create a new PacketRangeDeleteMutator with a lower bound on the number of packets
+    pub fn new(min_packets: usize) -> Self {
+        Self {
+            phantom: PhantomData,
+            min_packets: std::cmp::max(1, min_packets),
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketRangeDeleteMutator<P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() <= self.min_packets {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let range = random_range(state.rand_mut(), input.len(), input.len() - self.min_packets);
+        input.packets_mut().drain(range);
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketRangeDeleteMutator<P> {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketRangeDeleteMutator")
+    }
+}
+
+/// A mutator that repeats a random range of consecutive packets up to 8 times.
+///
+/// The copies are inserted directly after the range, so that e.g. a login sequence
+/// gets executed multiple times in a row.
+/// It respects an upper bound on the number of packets passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we never exceed 16 packets in an input
+/// let mutator = PacketRangeDuplicateMutator::new(16);
+/// ```
+pub struct PacketRangeDuplicateMutator<P>
+where
+    P: Clone,
+{
+    max_packets: usize,
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketRangeDuplicateMutator<P>
+where
+    P: Clone,
+{
+    /// Create a new PacketRangeDuplicateMutator with an upper bound on the number of packets
+    pub fn new(max_packets: usize) -> Self {
+        Self {
+            max_packets,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketRangeDuplicateMutator<P>
+where
+    P: Clone,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() == 0 || input.len() >= self.max_packets {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let range = random_range(state.rand_mut(), input.len(), self.max_packets - input.len());
+        let max_repeats = std::cmp::min(MAX_REPEATS, (self.max_packets - input.len()) / range.len());
+        let repeats = 1 + state.rand_mut().below(NonZero::new(max_repeats).unwrap());
+
+        let copy = input.packets()[range.clone()].to_vec();
+        let copies = std::iter::repeat_n(copy, repeats).flatten();
+        input.packets_mut().splice(range.end..range.end, copies);
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketRangeDuplicateMutator<P>
+where
+    P: Clone,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketRangeDuplicateMutator")
+    }
+}
+
+/// A mutator that moves a random range of consecutive packets to a different position.
+pub struct PacketRangeMoveMutator<P> {
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketRangeMoveMutator<P> {
+    /// Create a new PacketRangeMoveMutator
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketRangeMoveMutator<P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let range = random_range(state.rand_mut(), input.len(), input.len() - 1);
+        let to = state.rand_mut().below(NonZero::new(input.len() - range.len() + 1).unwrap());
+
+        if range.start == to {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let packets = input.packets_mut();
+
+        // Moving a range is a rotation of everything between its old and new position
+        if to < range.start {
+            packets[to..range.end].rotate_right(range.len());
+        } else {
+            packets[range.start..to + range.len()].rotate_left(range.len());
+        }
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketRangeMoveMutator<P> {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketRangeMoveMutator")
+    }
+}
+
+/// A mutator that rotates a random range of consecutive packets by a random amount.
+pub struct PacketRotateMutator<P> {
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketRotateMutator<P> {
+    /// Create a new PacketRotateMutator
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketRotateMutator<P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let range = random_range(state.rand_mut(), input.len(), input.len());
+
+        if range.len() <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let mid = 1 + state.rand_mut().below(NonZero::new(range.len() - 1).unwrap());
+        input.packets_mut()[range].rotate_left(mid);
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketRotateMutator<P> {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketRotateMutator")
+    }
+}
+
+/// A mutator that reverses the order of a random range of consecutive packets.
+pub struct PacketReverseMutator<P> {
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketReverseMutator<P> {
+    /// Create a new PacketReverseMutator
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketReverseMutator<P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let range = random_range(state.rand_mut(), input.len(), input.len());
+
+        if range.len() <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        input.packets_mut()[range].reverse();
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<P> Named for PacketReverseMutator<P> {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketReverseMutator")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PacketBytesInput;
+    use libafl::inputs::BytesInput;
+    use libafl_bolts::rands::StdRand;
+
+    struct TestState {
+        rand: StdRand,
+    }
+
+    impl HasRand for TestState {
+        type Rand = StdRand;
+
+        fn rand(&self) -> &StdRand {
+            &self.rand
+        }
+
+        fn rand_mut(&mut self) -> &mut StdRand {
+            &mut self.rand
+        }
+    }
+
+    fn sorted(input: &PacketBytesInput) -> Vec<BytesInput> {
+        let mut packets = input.packets().to_vec();
+        packets.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        packets
+    }
+
+    #[test]
+    fn test_range_mutators() {
+        let mut state = TestState {
+            rand: StdRand::with_seed(0),
+        };
+        let input = PacketBytesInput::from((0..6u8).map(|i| vec![i]).collect::<Vec<_>>());
+
+        let mut delete = PacketRangeDeleteMutator::<BytesInput>::new(2);
+        let mut duplicate = PacketRangeDuplicateMutator::<BytesInput>::new(10);
+        let mut move_ = PacketRangeMoveMutator::<BytesInput>::new();
+        let mut rotate = PacketRotateMutator::<BytesInput>::new();
+        let mut reverse = PacketReverseMutator::<BytesInput>::new();
+
+        for _ in 0..100 {
+            let mut mutated = input.clone();
+            delete.mutate(&mut state, &mut mutated).unwrap();
+            assert!((2..6).contains(&mutated.len()));
+
+            let mut mutated = input.clone();
+            duplicate.mutate(&mut state, &mut mutated).unwrap();
+            assert!((7..=10).contains(&mutated.len()));
+
+            // the remaining mutators only change the order of packets
+            for mutator in [&mut move_ as &mut dyn Mutator<PacketBytesInput, TestState>, &mut rotate, &mut reverse] {
+                let mut mutated = input.clone();
+
+                if mutator.mutate(&mut state, &mut mutated).unwrap() == MutationResult::Mutated {
+                    assert_ne!(mutated, input);
+                    assert_eq!(sorted(&mutated), sorted(&input));
+                }
+            }
+        }
+    }
+}
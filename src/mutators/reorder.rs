@@ -32,8 +32,8 @@ where
             return Ok(MutationResult::Skipped);
         }
 
-        let from = state.rand_mut().below(NonZero::new(input.len()).unwrap()) as usize;
-        let to = state.rand_mut().below(NonZero::new(input.len()).unwrap()) as usize;
+        let from = state.rand_mut().below(NonZero::new(input.len()).unwrap());
+        let to = state.rand_mut().below(NonZero::new(input.len()).unwrap());
 
         if from == to {
             return Ok(MutationResult::Skipped);
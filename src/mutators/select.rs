@@ -0,0 +1,118 @@
+use crate::feedback::StateNoveltyMetadata;
+use libafl_bolts::{rands::Rand, HasLen};
+use libafl::{
+    common::HasMetadata,
+    state::{HasCurrentTestcase, HasRand},
+};
+use std::num::NonZero;
+
+/// Decides which packet of an input a mutator works on.
+///
+/// Used by the [`PacketHavocMutator`](crate::PacketHavocMutator).
+pub trait PacketSelector<I, S> {
+    /// Returns the index of the packet to mutate. `input` contains at least one packet.
+    fn select_packet(&mut self, state: &mut S, input: &I) -> usize;
+}
+
+/// A [`PacketSelector`] that selects every packet with the same probability.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomPacketSelector;
+
+impl RandomPacketSelector {
+    /// Create a new RandomPacketSelector
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> PacketSelector<I, S> for RandomPacketSelector
+where
+    I: HasLen,
+    S: HasRand,
+{
+    fn select_packet(&mut self, state: &mut S, input: &I) -> usize {
+        state.rand_mut().below(NonZero::new(input.len()).unwrap())
+    }
+}
+
+/// A [`PacketSelector`] that focuses on the packets that led to new states.
+///
+/// When the [`StateFeedback`](crate::StateFeedback) adds an input to the corpus it remembers
+/// the first packet that brought the target into a new state or took a new transition
+/// in [`StateNoveltyMetadata`]. This selector only selects that packet or the packets after it,
+/// so the prefix that reaches the new state stays intact and fuzzing continues
+/// from the new state. This is the "deep state" strategy of butterfly.
+///
+/// Inputs without that information are treated like the [`RandomPacketSelector`] does.
+///
+/// # Example
+/// ```
+/// let mutator = PacketHavocMutator::with_selector(supported_havoc_mutations(), FocusedPacketSelector::new());
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FocusedPacketSelector;
+
+impl FocusedPacketSelector {
+    /// Create a new FocusedPacketSelector
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<I, S> PacketSelector<I, S> for FocusedPacketSelector
+where
+    I: HasLen,
+    S: HasRand + HasCurrentTestcase<I>,
+{
+    fn select_packet(&mut self, state: &mut S, input: &I) -> usize {
+        let len = input.len();
+        let focus = state.current_testcase().ok().and_then(|testcase| testcase.metadata_map().get::<StateNoveltyMetadata>().and_then(StateNoveltyMetadata::packet));
+
+        match focus {
+            Some(focus) => {
+                let focus = focus.min(len - 1);
+                focus + state.rand_mut().below(NonZero::new(len - focus).unwrap())
+            },
+            None => state.rand_mut().below(NonZero::new(len).unwrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PacketBytesInput, StateNovelty};
+    use libafl::{
+        corpus::{Corpus, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        state::{HasCorpus, StdState},
+    };
+    use libafl_bolts::rands::StdRand;
+
+    #[test]
+    fn test_focused_selection() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<PacketBytesInput>::new(), InMemoryCorpus::new(), &mut (), &mut ()).unwrap();
+        let input = PacketBytesInput::from(vec![b"A".to_vec(), b"B".to_vec(), b"C".to_vec(), b"D".to_vec()]);
+        let mut selector = FocusedPacketSelector::new();
+
+        let mut testcase = Testcase::new(input.clone());
+        testcase.add_metadata(StateNoveltyMetadata::new(StateNovelty::NEW_EDGE, Some(2)));
+        let id = state.corpus_mut().add(testcase).unwrap();
+        state.set_corpus_id(id).unwrap();
+
+        let mut selected = [0; 4];
+        for _ in 0..100 {
+            selected[selector.select_packet(&mut state, &input)] += 1;
+        }
+        assert_eq!(selected[0] + selected[1], 0);
+        assert!(selected[2] > 0 && selected[3] > 0);
+
+        // packets beyond the end of the input
+        let short = PacketBytesInput::from(vec![b"A".to_vec()]);
+        assert_eq!(selector.select_packet(&mut state, &short), 0);
+
+        // no metadata
+        let id = state.corpus_mut().add(Testcase::new(input.clone())).unwrap();
+        state.set_corpus_id(id).unwrap();
+        assert!((0..100).any(|_| selector.select_packet(&mut state, &input) == 0));
+    }
+}
@@ -16,6 +16,9 @@ use std::{borrow::Cow, marker::PhantomData, num::NonZero};
 ///
 /// Already implemented for:
 /// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - [`Option<T>`](core::option::Option) if `T` implements this trait. `None` is never mutated.
+///
+/// With the `derive` feature, `#[derive(PacketMutations)]` generates the impl shown below.
 ///
 /// # Example
 /// Suppose we have the following packet type
@@ -72,8 +75,8 @@ where
             return Ok(MutationResult::Skipped);
         }
 
-        let to = state.rand_mut().below(NonZero::new(self_len).unwrap()) as usize;
-        let from = state.rand_mut().below(NonZero::new(other_len).unwrap()) as usize;
+        let to = state.rand_mut().below(NonZero::new(self_len).unwrap());
+        let from = state.rand_mut().below(NonZero::new(other_len).unwrap());
         let len = other_len - from;
 
         // Make sure we have enough space for all the bytes from `other`
@@ -87,6 +90,19 @@ where
     }
 }
 
+impl<S, T> HasSpliceMutation<S> for Option<T>
+where
+    T: HasSpliceMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_splice(&mut self, state: &mut S, other: &Self) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (Some(packet), Some(other_packet)) => packet.mutate_splice(state, other_packet),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
 /// A mutator that splices two random packets together.
 ///
 /// `P` denotes the type of an individual packet that MUST implement [`HasSpliceMutation`].
@@ -132,7 +148,7 @@ where
             return Ok(MutationResult::Skipped);
         }
 
-        let packet = state.rand_mut().below(NonZero::new(input.len() - 1).unwrap()) as usize;
+        let packet = state.rand_mut().below(NonZero::new(input.len() - 1).unwrap());
         let other = input.packets_mut().remove(packet + 1);
 
         let ret = input.packets_mut()[packet].mutate_splice(state, &other)?;
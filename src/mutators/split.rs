@@ -0,0 +1,240 @@
+use crate::{input::HasPackets, tokens::is_delimiter};
+use libafl_bolts::{rands::Rand, HasLen, Named};
+use libafl::{
+    inputs::{BytesInput, Input},
+    mutators::{MutationResult, Mutator},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use std::{borrow::Cow, marker::PhantomData, num::NonZero};
+
+/// Signifies that a packet type supports the [`PacketSplitMutator`] mutator.
+///
+/// If you want to use the [`PacketSplitMutator`] your Input type must have a vector
+/// of packets that implement this trait.
+/// IMPORTANT: This must be implemented on the packet type, NOT the Input type.
+///
+/// Already implemented for:
+/// - [`BytesInput`](libafl::inputs::BytesInput)
+/// - [`Option<T>`](core::option::Option) if `T` implements this trait. `None` is never mutated.
+///
+/// With the `derive` feature, `#[derive(PacketMutations)]` generates the impl shown below.
+///
+/// # Example
+/// Suppose we have the following packet type
+/// ```
+/// enum PacketType {
+///    A(BytesInput),
+///    B(BytesInput),
+/// }
+/// ```
+/// Then we can implement this trait as follows
+/// ```
+/// impl<S> HasSplitMutation<S> for PacketType
+/// where
+///    S: HasRand + HasMaxSize,
+/// {
+///    fn mutate_split(&mut self, state: &mut S, other: &mut Self) -> Result<MutationResult, Error> {
+///        match (self, other) {
+///            (PacketType::A(data), PacketType::A(other_data)) => data.mutate_split(state, other_data),
+///            (PacketType::B(data), PacketType::B(other_data)) => data.mutate_split(state, other_data),
+///            _ => Ok(MutationResult::Skipped),
+///        }
+///    }
+/// }
+/// ```
+/// And now we are able to use the [`PacketSplitMutator`].
+pub trait HasSplitMutation<S>
+where
+    S: HasRand + HasMaxSize,
+{
+    /// Split `self` into two parts at a random offset.
+    ///
+    /// `other` is a copy of `self` when this gets called. Afterwards `self` must
+    /// contain the first part and `other` the second part.
+    ///
+    /// The arguments to this function are similar to [`Mutator::mutate()`](libafl::mutators::Mutator::mutate).
+    fn mutate_split(&mut self, state: &mut S, other: &mut Self) -> Result<MutationResult, Error>;
+}
+
+impl<S> HasSplitMutation<S> for BytesInput
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_split(&mut self, state: &mut S, other: &mut Self) -> Result<MutationResult, Error> {
+        let len = self.len();
+
+        if len <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        // Prefer splitting text protocols after a delimiter like "\r\n" or " "
+        let delimiters: Vec<usize> = (1..len).filter(|offset| is_delimiter(self.as_ref()[offset - 1])).collect();
+
+        let offset = if !delimiters.is_empty() && state.rand_mut().coinflip(0.5) {
+            delimiters[state.rand_mut().below(NonZero::new(delimiters.len()).unwrap())]
+        } else {
+            1 + state.rand_mut().below(NonZero::new(len - 1).unwrap())
+        };
+
+        other.as_mut().clear();
+        other.as_mut().extend_from_slice(&self.as_ref()[offset..]);
+        self.as_mut().truncate(offset);
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<S, T> HasSplitMutation<S> for Option<T>
+where
+    T: HasSplitMutation<S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate_split(&mut self, state: &mut S, other: &mut Self) -> Result<MutationResult, Error> {
+        match (self, other) {
+            (Some(packet), Some(other_packet)) => packet.mutate_split(state, other_packet),
+            _ => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+/// A mutator that splits a random packet into two consecutive packets.
+///
+/// This simulates a message that arrives in two reads at the target.
+/// `P` denotes the type of an individual packet that MUST implement [`HasSplitMutation`].
+/// PacketSplitMutator respects an upper bound on the number of packets
+/// passed as an argument to the constructor.
+///
+/// # Example
+/// ```
+/// // Make sure that we never exceed 16 packets in an input
+/// let mutator = PacketSplitMutator::new(16);
+/// ```
+pub struct PacketSplitMutator<P, S>
+where
+    P: HasSplitMutation<S> + Clone,
+    S: HasRand + HasMaxSize,
+{
+    phantom: PhantomData<(P, S)>,
+    max_packets: usize,
+}
+
+impl<P, S> PacketSplitMutator<P, S>
+where
+    P: HasSplitMutation<S> + Clone,
+    S: HasRand + HasMaxSize,
+{
+    /// Create a new PacketSplitMutator with an upper bound for the number of packets
+    pub fn new(max_packets: usize) -> Self {
+        Self {
+            phantom: PhantomData,
+            max_packets,
+        }
+    }
+}
+
+impl<I, P, S> Mutator<I, S> for PacketSplitMutator<P, S>
+where
+    P: HasSplitMutation<S> + Clone,
+    S: HasRand + HasMaxSize,
+    I: Input + HasLen + HasPackets<P>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() == 0 || input.len() >= self.max_packets {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let packet = state.rand_mut().below(NonZero::new(input.len()).unwrap());
+        let mut other = input.packets()[packet].clone();
+
+        let ret = input.packets_mut()[packet].mutate_split(state, &mut other)?;
+
+        if ret == MutationResult::Mutated {
+            input.packets_mut().insert(packet + 1, other);
+        }
+
+        Ok(ret)
+    }
+}
+
+impl<P, S> Named for PacketSplitMutator<P, S>
+where
+    P: HasSplitMutation<S> + Clone,
+    S: HasRand + HasMaxSize,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketSplitMutator")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl_bolts::rands::StdRand;
+    use libafl::{
+        inputs::BytesInput,
+        mutators::MutationResult,
+        state::{HasMaxSize, HasRand},
+    };
+
+    struct TestState {
+        rand: StdRand,
+        max_size: usize,
+    }
+    impl TestState {
+        fn new() -> Self {
+            Self {
+                rand: StdRand::with_seed(0),
+                max_size: 0,
+            }
+        }
+    }
+    impl HasRand for TestState {
+        type Rand = StdRand;
+
+        fn rand(&self) -> &StdRand {
+            &self.rand
+        }
+
+        fn rand_mut(&mut self) -> &mut StdRand {
+            &mut self.rand
+        }
+    }
+    impl HasMaxSize for TestState {
+        fn max_size(&self) -> usize {
+            self.max_size
+        }
+
+        fn set_max_size(&mut self, max_size: usize) {
+            self.max_size = max_size;
+        }
+    }
+
+    #[test]
+    fn test_split_len1() {
+        let mut state = TestState::new();
+        let mut a = BytesInput::new(b"A".to_vec());
+        let mut b = a.clone();
+
+        assert_eq!(a.mutate_split(&mut state, &mut b).unwrap(), MutationResult::Skipped);
+    }
+
+    #[test]
+    fn test_split() {
+        let mut state = TestState::new();
+        let mut after_delimiter = false;
+
+        for _ in 0..100 {
+            let mut a = BytesInput::new(b"USER anonymous\r\n".to_vec());
+            let mut b = a.clone();
+
+            assert_eq!(a.mutate_split(&mut state, &mut b).unwrap(), MutationResult::Mutated);
+            assert!(a.len() > 0 && b.len() > 0);
+            assert_eq!([a.as_ref() as &[u8], b.as_ref()].concat(), b"USER anonymous\r\n");
+
+            after_delimiter |= a.as_ref() == b"USER ";
+        }
+
+        assert!(after_delimiter);
+    }
+}
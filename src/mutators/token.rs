@@ -0,0 +1,203 @@
+use crate::{input::HasPackets, mutators::havoc::HasHavocMutation};
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+    HasLen, Named,
+};
+use libafl::{
+    common::HasMetadata,
+    inputs::Input,
+    mutators::{MutationId, MutationResult, Mutator, TokenInsert, TokenReplace, Tokens},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+use std::{borrow::Cow, marker::PhantomData, num::NonZero};
+
+/// Returns true if the state holds at least one token
+fn has_tokens<S>(state: &S) -> bool
+where
+    S: HasMetadata,
+{
+    state.metadata_map().get::<Tokens>().is_some_and(|tokens| !tokens.is_empty())
+}
+
+/// A mutator that inserts a token from the dictionary into a single, random packet.
+///
+/// The tokens are taken from the [`Tokens`](libafl::mutators::Tokens) metadata of the state.
+/// Use [`load_dictionary()`](crate::load_dictionary) and [`load_pcap_tokens()`](crate::load_pcap_tokens)
+/// to fill it. If the state has no tokens the mutation is skipped.
+///
+/// The insertion itself is done by LibAFLs [`TokenInsert`](libafl::mutators::TokenInsert)
+/// via [`HasHavocMutation`], so every packet type that supports the
+/// [`PacketHavocMutator`](crate::PacketHavocMutator) also supports this mutator.
+///
+/// # Example
+/// ```
+/// load_dictionary(&mut state, "ftp.dict")?;
+/// let mutator = PacketTokenInsertMutator::new();
+/// ```
+pub struct PacketTokenInsertMutator<P> {
+    mutations: tuple_list_type!(TokenInsert),
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketTokenInsertMutator<P> {
+    /// Create a new PacketTokenInsertMutator
+    pub fn new() -> Self {
+        Self {
+            mutations: tuple_list!(TokenInsert::new()),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketTokenInsertMutator<P>
+where
+    P: HasHavocMutation<tuple_list_type!(TokenInsert), S>,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasMetadata + HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() == 0 || !has_tokens(state) {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let packet = state.rand_mut().below(NonZero::new(input.len()).unwrap());
+        input.packets_mut()[packet].mutate_havoc(state, &mut self.mutations, MutationId::from(0usize))
+    }
+}
+
+impl<P> Named for PacketTokenInsertMutator<P> {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketTokenInsertMutator")
+    }
+}
+
+/// A mutator that overwrites bytes of a single, random packet with a token from the dictionary.
+///
+/// Works like the [`PacketTokenInsertMutator`] but uses LibAFLs
+/// [`TokenReplace`](libafl::mutators::TokenReplace), so the length of the packet stays the same.
+///
+/// # Example
+/// ```
+/// load_pcap_tokens(&mut state, "./pcaps")?;
+/// let mutator = PacketTokenReplaceMutator::new();
+/// ```
+pub struct PacketTokenReplaceMutator<P> {
+    mutations: tuple_list_type!(TokenReplace),
+    phantom: PhantomData<P>,
+}
+
+impl<P> PacketTokenReplaceMutator<P> {
+    /// Create a new PacketTokenReplaceMutator
+    pub fn new() -> Self {
+        Self {
+            mutations: tuple_list!(TokenReplace::new()),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, P> Mutator<I, S> for PacketTokenReplaceMutator<P>
+where
+    P: HasHavocMutation<tuple_list_type!(TokenReplace), S>,
+    I: Input + HasLen + HasPackets<P>,
+    S: HasMetadata + HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.len() == 0 || !has_tokens(state) {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let packet = state.rand_mut().below(NonZero::new(input.len()).unwrap());
+        input.packets_mut()[packet].mutate_havoc(state, &mut self.mutations, MutationId::from(0usize))
+    }
+}
+
+impl<P> Named for PacketTokenReplaceMutator<P> {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PacketTokenReplaceMutator")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PacketBytesInput;
+    use libafl::{common::HasMetadata, inputs::BytesInput};
+    use libafl_bolts::{rands::StdRand, serdeany::SerdeAnyMap};
+
+    struct TestState {
+        rand: StdRand,
+        max_size: usize,
+        metadata: SerdeAnyMap,
+    }
+    impl TestState {
+        fn new() -> Self {
+            Self {
+                rand: StdRand::with_seed(0),
+                max_size: 1024,
+                metadata: SerdeAnyMap::new(),
+            }
+        }
+    }
+    impl HasRand for TestState {
+        type Rand = StdRand;
+
+        fn rand(&self) -> &StdRand {
+            &self.rand
+        }
+
+        fn rand_mut(&mut self) -> &mut StdRand {
+            &mut self.rand
+        }
+    }
+    impl HasMaxSize for TestState {
+        fn max_size(&self) -> usize {
+            self.max_size
+        }
+
+        fn set_max_size(&mut self, max_size: usize) {
+            self.max_size = max_size;
+        }
+    }
+    impl HasMetadata for TestState {
+        fn metadata_map(&self) -> &SerdeAnyMap {
+            &self.metadata
+        }
+
+        fn metadata_map_mut(&mut self) -> &mut SerdeAnyMap {
+            &mut self.metadata
+        }
+    }
+
+    #[test]
+    fn test_token_insert() {
+        let mut state = TestState::new();
+        let mut input = PacketBytesInput::from(vec![b"AAAA".to_vec()]);
+        let mut mutator = PacketTokenInsertMutator::<BytesInput>::new();
+
+        assert_eq!(mutator.mutate(&mut state, &mut input).unwrap(), MutationResult::Skipped);
+
+        state.add_metadata(Tokens::from([b"USER".to_vec()]));
+        assert_eq!(mutator.mutate(&mut state, &mut input).unwrap(), MutationResult::Mutated);
+
+        let packet = input.packets()[0].as_ref();
+        assert_eq!(packet.len(), 8);
+        assert!(packet.windows(4).any(|window| window == b"USER"));
+    }
+
+    #[test]
+    fn test_token_replace() {
+        let mut state = TestState::new();
+        state.add_metadata(Tokens::from([b"B".to_vec()]));
+        let mut input = PacketBytesInput::from(vec![b"AAAA".to_vec()]);
+        let mut mutator = PacketTokenReplaceMutator::<BytesInput>::new();
+
+        assert_eq!(mutator.mutate(&mut state, &mut input).unwrap(), MutationResult::Mutated);
+
+        let packet = input.packets()[0].as_ref();
+        assert_eq!(packet.len(), 4);
+        assert_eq!(packet.iter().filter(|byte| **byte == b'B').count(), 1);
+    }
+}
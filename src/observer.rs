@@ -1,13 +1,15 @@
 use ahash::RandomState;
+#[cfg(not(feature = "safe_only"))]
 use libafl_bolts::tuples::MatchName;
 use libafl_bolts::Named;
-use libafl::{executors::ExitKind, observers::Observer, Error};
+use libafl::{common::HasNamedMetadata, executors::ExitKind, observers::Observer, Error};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cmp::Eq;
 use std::collections::{HashMap, HashSet};
-use std::fmt::{Debug, Write};
+use std::fmt::{Debug, Display, Formatter, Write};
 use std::hash::Hash;
+use std::ops::{BitAnd, BitOr, BitOrAssign};
 
 #[inline]
 fn pack_transition(from: u32, to: u32) -> u64 {
@@ -19,59 +21,419 @@ fn unpack_transition(transition: u64) -> (u32, u32) {
     ((transition >> 32) as u32, transition as u32)
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(bound = "PS: serde::Serialize + for<'a> serde::Deserialize<'a>")]
-struct StateGraph<PS>
-where
-    PS: Clone + Debug + Eq + Hash,
-{
-    nodes: HashMap<PS, u32, RandomState>,
+/// The kinds of novelty that a [`StateObserver`] can see in a run.
+///
+/// This is a set of flags that can be combined with `|`.
+/// [`StateObserver::novelty()`] returns the flags of the last run and
+/// [`StateFeedback::with_novelty()`](crate::StateFeedback::with_novelty) uses them to decide
+/// which kinds of novelty make an input interesting.
+///
+/// # Example
+/// ```
+/// // Also reward new self-loops and hit count buckets
+/// let feedback = StateFeedback::with_novelty(&observer, StateNovelty::ALL);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StateNovelty(u8);
+
+impl StateNovelty {
+    /// Nothing new was seen
+    pub const NONE: Self = Self(0);
+    /// A state was visited for the first time
+    pub const NEW_NODE: Self = Self(1);
+    /// A transition between two different states was taken for the first time
+    pub const NEW_EDGE: Self = Self(2);
+    /// A state followed itself for the first time
+    pub const NEW_SELF_LOOP: Self = Self(4);
+    /// A state or transition was visited a number of times that falls into a new bucket.
+    /// Only seen by observers created with [`StateObserver::with_hitcounts()`].
+    pub const NEW_HITCOUNT: Self = Self(8);
+    /// All of the above
+    pub const ALL: Self = Self(15);
+
+    /// Returns true if all flags of `other` are set in `self`
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if any flag of `other` is set in `self`
+    pub fn intersects(&self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns true if no flag is set
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for StateNovelty {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for StateNovelty {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for StateNovelty {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl Display for StateNovelty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let names = [(Self::NEW_NODE, "new node"), (Self::NEW_EDGE, "new edge"), (Self::NEW_SELF_LOOP, "new self-loop"), (Self::NEW_HITCOUNT, "new hitcount")];
+        let mut first = true;
+
+        for (flag, name) in names {
+            if self.contains(flag) {
+                write!(f, "{}{}", if first { "" } else { " | " }, name)?;
+                first = false;
+            }
+        }
+
+        if first {
+            write!(f, "none")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Edges whose total hit count is at most this value are considered cold
+const COLD_EDGE_HITS: u64 = 3;
+
+/// Map a hit count to its AFL-style bucket.
+/// Every bucket is a single bit so that seen buckets can be stored as a bitmask.
+#[inline]
+fn hitcount_bucket(count: u32) -> u8 {
+    match count {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 4,
+        4..=7 => 8,
+        8..=15 => 16,
+        16..=31 => 32,
+        32..=127 => 64,
+        _ => 128,
+    }
+}
+
+/// Hit count of a node or edge
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Hits {
+    /// Hits across all runs
+    total: u64,
+    /// Hits in the current run
+    current: u32,
+    /// Bitmask of all buckets that were seen in a single run
+    buckets: u8,
+}
+impl Hits {
+    fn hit(&mut self) {
+        self.total += 1;
+        self.current = self.current.saturating_add(1);
+    }
+
+    /// Classify the hits of the current run, returns true if the bucket is new
+    fn classify(&mut self) -> bool {
+        let bucket = hitcount_bucket(std::mem::take(&mut self.current));
+        let new = self.buckets & bucket != bucket;
+        self.buckets |= bucket;
+        new
+    }
+}
+
+/// Hit counts of all nodes and edges, only present in hitcount mode
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct HitCounts {
+    nodes: Vec<Hits>,
+    edges: HashMap<u64, Hits, RandomState>,
+    /// Nodes and edges that were hit in the current run
+    touched_nodes: Vec<u32>,
+    touched_edges: Vec<u64>,
+}
+impl HitCounts {
+    fn hit_node(&mut self, id: u32) {
+        if self.nodes.len() <= id as usize {
+            self.nodes.resize(id as usize + 1, Hits::default());
+        }
+
+        let hits = &mut self.nodes[id as usize];
+
+        if hits.current == 0 {
+            self.touched_nodes.push(id);
+        }
+
+        hits.hit();
+    }
+
+    fn hit_edge(&mut self, transition: u64) {
+        let hits = self.edges.entry(transition).or_default();
+
+        if hits.current == 0 {
+            self.touched_edges.push(transition);
+        }
+
+        hits.hit();
+    }
+
+    /// Classify the hit counts of the current run, returns true if any node or edge got into a new bucket
+    fn classify(&mut self) -> bool {
+        let mut new = false;
+
+        for id in self.touched_nodes.drain(..) {
+            new |= self.nodes[id as usize].classify();
+        }
+
+        for transition in self.touched_edges.drain(..) {
+            new |= self.edges.get_mut(&transition).unwrap().classify();
+        }
+
+        new
+    }
+}
+
+/// Iterate over the transitions of a path as (packet index, from, to)
+pub(crate) fn transitions<'a>(path: &'a [u32], packets: &'a [usize]) -> impl Iterator<Item = (usize, u32, u32)> + 'a {
+    path.windows(2).zip(packets.get(1..).unwrap_or_default()).map(|(pair, packet)| (*packet, pair[0], pair[1]))
+}
+
+/// The state-graph that a [`StateObserver`] builds.
+///
+/// It is stored as named metadata in the state under the name of the observer,
+/// so it gets saved and restored together with the rest of the state and
+/// survives restarts of the fuzzer. Every state gets a unique id when it is seen
+/// for the first time. The graph stores edges between these ids and a serialized
+/// copy of every state.
+///
+/// # Example
+/// ```
+/// let graph = state.named_metadata::<StateGraphMetadata>("state observer")?;
+/// println!("{}", graph.to_dot());
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StateGraphMetadata {
+    states: Vec<Vec<u8>>,
     edges: HashSet<u64, RandomState>,
-    last_node: Option<u32>,
-    new_transitions: bool,
+    self_loops: HashSet<u32, RandomState>,
+    triggers: HashMap<u64, usize, RandomState>,
+    trigger_kinds: HashMap<u64, u32, RandomState>,
+    hitcounts: Option<HitCounts>,
+    /// Ids of the serialized states, rebuilt on demand
+    #[serde(skip)]
+    ids: HashMap<Vec<u8>, u32, RandomState>,
 }
-impl<PS> StateGraph<PS>
-where
-    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
-{
-    fn new() -> Self {
-        Self {
-            nodes: HashMap::<PS, u32, RandomState>::default(),
-            edges: HashSet::<u64, RandomState>::default(),
-            last_node: None,
-            new_transitions: false,
+
+impl StateGraphMetadata {
+    /// Returns the number of vertices
+    pub fn nodes(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns the number of edges between two different states
+    pub fn edges(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Returns the number of states that followed themselves
+    pub fn self_loops(&self) -> usize {
+        self.self_loops.len()
+    }
+
+    /// Returns the state with the id `id`
+    pub fn state<PS>(&self, id: u32) -> Option<PS>
+    where
+        PS: for<'a> Deserialize<'a>,
+    {
+        postcard::from_bytes(self.states.get(id as usize)?).ok()
+    }
+
+    /// Returns whether the transition from state `from` to state `to` has been seen
+    pub fn has_edge(&self, from: u32, to: u32) -> bool {
+        if from == to {
+            self.self_loops.contains(&from)
+        } else {
+            self.edges.contains(&pack_transition(from, to))
         }
     }
 
-    fn reset(&mut self) {
-        self.last_node = None;
-        self.new_transitions = false;
+    /// Returns the index of the packet that took the transition from state `from` to state `to`
+    /// when it was discovered.
+    pub fn trigger(&self, from: u32, to: u32) -> Option<usize> {
+        self.triggers.get(&pack_transition(from, to)).copied()
     }
 
-    fn add_node(&mut self, state: &PS) -> u32 {
-        match self.nodes.get(state) {
-            Some(id) => *id,
+    /// Returns the [kind](crate::HasPacketKind) of the packet that took the transition from state `from`
+    /// to state `to` when it was discovered.
+    /// Returns None if the executor did not record the kinds, see [`StateObserver::record_packet_kinds()`].
+    pub fn trigger_kind(&self, from: u32, to: u32) -> Option<u32> {
+        self.trigger_kinds.get(&pack_transition(from, to)).copied()
+    }
+
+    /// Returns how often the state `id` was visited across all runs.
+    /// Returns None if the state is unknown or hit counts are disabled.
+    pub fn node_hitcount(&self, id: u32) -> Option<u64> {
+        self.hitcounts.as_ref()?.nodes.get(id as usize).map(|hits| hits.total)
+    }
+
+    /// Returns how often the transition from state `from` to state `to` was taken across all runs.
+    /// Returns None if the transition is unknown or hit counts are disabled.
+    pub fn edge_hitcount(&self, from: u32, to: u32) -> Option<u64> {
+        self.hitcounts.as_ref()?.edges.get(&pack_transition(from, to)).map(|hits| hits.total)
+    }
+
+    /// Returns the number of transitions that were taken at most 3 times across all runs.
+    /// Returns None if hit counts are disabled.
+    pub fn cold_edges(&self) -> Option<usize> {
+        Some(self.hitcounts.as_ref()?.edges.values().filter(|hits| hits.total <= COLD_EDGE_HITS).count())
+    }
+
+    /// Returns a DOT representation of the statemachine.
+    /// Every edge is labeled with the index and, if known, the kind of the packet that took the transition when it was discovered.
+    pub fn to_dot(&self) -> String {
+        let mut s = String::with_capacity(1024);
+        self.write_dot(&mut s);
+        s
+    }
+
+    /// Add all states and transitions of `other` to this graph.
+    ///
+    /// The ids of the states differ between graphs, so the states are matched by their serialized form.
+    /// Transitions that are new keep the packet that discovered them in `other`. Hit counts are not merged.
+    pub fn merge(&mut self, other: &StateGraphMetadata) {
+        let ids: Vec<u32> = other.states.iter().map(|bytes| self.state_id(bytes.clone()).0).collect();
+        let local = |id: u32| ids.get(id as usize).copied();
+
+        for transition in other.edges.iter().copied().chain(other.self_loops.iter().map(|id| pack_transition(*id, *id))) {
+            let (from, to) = unpack_transition(transition);
+
+            let (from, to) = match (local(from), local(to)) {
+                (Some(from), Some(to)) => (from, to),
+                _ => continue,
+            };
+
+            let new = if from == to {
+                self.self_loops.insert(from)
+            } else {
+                self.edges.insert(pack_transition(from, to))
+            };
+
+            if new {
+                if let Some(packet) = other.triggers.get(&transition) {
+                    self.triggers.insert(pack_transition(from, to), *packet);
+                }
+
+                if let Some(kind) = other.trigger_kinds.get(&transition) {
+                    self.trigger_kinds.insert(pack_transition(from, to), *kind);
+                }
+            }
+        }
+    }
+
+    /// Serialize the graph without hit counts into a string that can be sent to the monitor as a user stat
+    pub(crate) fn encode(&self) -> Result<String, Error> {
+        let graph = Self {
+            hitcounts: None,
+            ..self.clone()
+        };
+        let bytes = postcard::to_allocvec(&graph)?;
+        let mut s = String::with_capacity(2 * bytes.len());
+
+        for byte in bytes {
+            let _ = write!(s, "{:02x}", byte);
+        }
+
+        Ok(s)
+    }
+
+    /// Deserialize a graph from the output of [`encode()`](StateGraphMetadata::encode).
+    /// Returns None if the string is malformed.
+    pub(crate) fn decode(s: &str) -> Option<Self> {
+        let bytes = (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect::<Option<Vec<u8>>>()?;
+        postcard::from_bytes(&bytes).ok()
+    }
+
+    /// Returns the id of a serialized state, adding it to the graph if it is unknown.
+    /// The bool is true if the state was new.
+    pub(crate) fn state_id(&mut self, bytes: Vec<u8>) -> (u32, bool) {
+        for id in self.ids.len()..self.states.len() {
+            self.ids.insert(self.states[id].clone(), id as u32);
+        }
+
+        match self.ids.get(&bytes) {
+            Some(id) => (*id, false),
             None => {
-                let next_id = self.nodes.len() as u32;
-                assert!(self.nodes.insert(state.clone(), next_id).is_none());
-                next_id
+                let id = self.states.len() as u32;
+                self.ids.insert(bytes.clone(), id);
+                self.states.push(bytes);
+                (id, true)
             },
         }
     }
 
-    fn add_edge(&mut self, id: u32) {
-        self.new_transitions |= match self.last_node.take() {
-            Some(old_id) => {
-                if old_id != id {
-                    self.edges.insert(pack_transition(old_id, id))
-                } else {
-                    false
+    /// Add the transitions of a run to the graph and return what was new.
+    /// `known` is the number of states the graph had before the run and `kinds` the kinds of the packets of the run, if known.
+    /// Also returns the first packet that led to a new state or transition.
+    pub(crate) fn add_path(&mut self, path: &[u32], packets: &[usize], kinds: &[u32], known: usize) -> (StateNovelty, Option<usize>) {
+        let mut novelty = StateNovelty::NONE;
+        let mut first_new = path.iter().position(|id| *id as usize >= known).map(|idx| packets[idx]);
+
+        if let Some(hitcounts) = &mut self.hitcounts {
+            for id in path {
+                hitcounts.hit_node(*id);
+            }
+        }
+
+        for (packet, from, to) in transitions(path, packets) {
+            let transition = pack_transition(from, to);
+
+            if let Some(hitcounts) = &mut self.hitcounts {
+                hitcounts.hit_edge(transition);
+            }
+
+            let new = if from == to {
+                let new = self.self_loops.insert(from);
+                if new {
+                    novelty |= StateNovelty::NEW_SELF_LOOP;
                 }
-            },
-            None => false,
-        };
+                new
+            } else {
+                let new = self.edges.insert(transition);
+                if new {
+                    novelty |= StateNovelty::NEW_EDGE;
+                }
+                new
+            };
+
+            if new {
+                self.triggers.insert(transition, packet);
+
+                if let Some(kind) = kinds.get(packet) {
+                    self.trigger_kinds.insert(transition, *kind);
+                }
+
+                first_new = Some(first_new.map_or(packet, |first| first.min(packet)));
+            }
+        }
 
-        self.last_node = Some(id);
+        if let Some(hitcounts) = &mut self.hitcounts {
+            if hitcounts.classify() {
+                novelty |= StateNovelty::NEW_HITCOUNT;
+            }
+        }
+
+        (novelty, first_new)
     }
 
     fn write_dot<S>(&self, stream: &mut S)
@@ -80,9 +442,30 @@ where
     {
         let _ = write!(stream, "digraph IMPLEMENTED_STATE_MACHINE {{");
 
-        for value in &self.edges {
+        let self_loops: Vec<u64> = self.self_loops.iter().map(|id| pack_transition(*id, *id)).collect();
+
+        for value in self.edges.iter().chain(&self_loops) {
             let (from, to) = unpack_transition(*value);
-            let _ = write!(stream, "\"{}\"->\"{}\";", from, to);
+
+            let trigger = self.triggers.get(value).map(|packet| match self.trigger_kinds.get(value) {
+                Some(kind) => format!("{} (kind {})", packet, kind),
+                None => packet.to_string(),
+            });
+
+            match (trigger, self.hitcounts.as_ref().and_then(|hitcounts| hitcounts.edges.get(value))) {
+                (Some(packet), Some(hits)) => {
+                    let _ = write!(stream, "\"{}\"->\"{}\"[label=\"packet {}\\n{} hits\",penwidth={}];", from, to, packet, hits.total, 1 + hits.total.ilog2());
+                },
+                (None, Some(hits)) => {
+                    let _ = write!(stream, "\"{}\"->\"{}\"[label=\"{} hits\",penwidth={}];", from, to, hits.total, 1 + hits.total.ilog2());
+                },
+                (Some(packet), None) => {
+                    let _ = write!(stream, "\"{}\"->\"{}\"[label=\"packet {}\"];", from, to, packet);
+                },
+                (None, None) => {
+                    let _ = write!(stream, "\"{}\"->\"{}\";", from, to);
+                },
+            }
         }
 
         let _ = write!(stream, "}}");
@@ -103,14 +486,58 @@ where
 ///
 /// The executor is responsible for calling [`StateObserver::record()`](crate::StateObserver::record)
 /// with states inferred from the fuzz target.
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(bound = "PS: serde::Serialize + for<'a> serde::Deserialize<'a>")]
+///
+/// The state-graph itself is not part of the observer. It lives in the state as
+/// [`StateGraphMetadata`] under the name of the observer and is updated after every run.
+///
+/// # Hit counts
+/// An observer created with [`StateObserver::with_hitcounts()`] additionally counts how
+/// often every state and transition was visited. The counts of a run are sorted into
+/// AFL-style buckets (1, 2, 3, 4-7, 8-15, 16-31, 32-127, 128+). A
+/// [`StateFeedback`](crate::StateFeedback) with [`StateNovelty::NEW_HITCOUNT`] also rewards inputs that bring a state or transition
+/// into a bucket it has never been in before. The DOT output then also labels every edge
+/// with its total hit count.
+///
+/// # Multiple instances
+/// When the observer is sent to other fuzzer instances together with a new testcase,
+/// it carries the states of its run. The [`StateFeedback`](crate::StateFeedback) of the
+/// receiving instance merges them into its own state-graph, so a transition that
+/// one instance discovered is not rewarded again on the others.
+/// This happens automatically with the [`Launcher`](libafl::events::Launcher), but it has limits:
+/// - Observers are only sent along with new corpus entries. States of runs that were not
+///   interesting to the sender never reach the other instances.
+/// - Observers are only sent if the [`EventConfig`](libafl::events::EventConfig) of the
+///   clients match and are not `AlwaysUnique`. Otherwise the receiver executes the
+///   input again, which updates its graph as well but costs an execution.
+/// - The observers are merged by the [`StateFeedback`](crate::StateFeedback). If it is
+///   combined with `feedback_or_fast!` after a feedback that already found the input
+///   interesting, it is not evaluated and the states are not merged.
+///
+/// So the state-graphs of the instances converge but are not guaranteed to be identical.
+/// The [`StateFeedback`](crate::StateFeedback) additionally sends every change of its graph
+/// to the monitor, where [`HasStateStats`](crate::HasStateStats) merges them into the global state-graph.
+#[derive(Debug)]
 pub struct StateObserver<PS>
 where
     PS: Clone + Debug + Eq + Hash,
 {
     name: Cow<'static, str>,
-    graph: StateGraph<PS>,
+    hitcounts: bool,
+    /// Ids of all states in the state-graph
+    nodes: HashMap<PS, u32, RandomState>,
+    /// States of the current run that are not in the state-graph yet
+    new_states: Vec<PS>,
+    path: Vec<u32>,
+    /// States of the run of a remote observer, empty for local observers
+    trace: Vec<PS>,
+    /// Packet index at which each state was recorded
+    packets: Vec<usize>,
+    /// Kind of every packet of the current run, empty if the executor doesn't know them
+    kinds: Vec<u32>,
+    novelty: StateNovelty,
+    first_new_packet: Option<usize>,
+    /// False if the observer was received from another fuzzer instance
+    local: bool,
 }
 
 impl<PS> StateObserver<PS>
@@ -121,33 +548,332 @@ where
     pub fn new(name: &'static str) -> Self {
         Self {
             name: Cow::Borrowed(name),
-            graph: StateGraph::<PS>::new(),
+            hitcounts: false,
+            nodes: HashMap::<PS, u32, RandomState>::default(),
+            new_states: Vec::new(),
+            path: Vec::new(),
+            trace: Vec::new(),
+            packets: Vec::new(),
+            kinds: Vec::new(),
+            novelty: StateNovelty::NONE,
+            first_new_packet: None,
+            local: true,
+        }
+    }
+
+    /// Create a new StateObserver with a given name that also counts
+    /// how often states and transitions were visited.
+    pub fn with_hitcounts(name: &'static str) -> Self {
+        Self {
+            hitcounts: true,
+            ..Self::new(name)
         }
     }
 
     /// Tell the observer that the target has entered state `state`.
+    ///
+    /// Every call counts as one packet, so the state is attributed to the packet after
+    /// the one of the previous call. Use [`record_at()`](StateObserver::record_at) if that is not the case.
     pub fn record(&mut self, state: &PS) {
-        let node = self.graph.add_node(state);
-        self.graph.add_edge(node);
+        let packet = self.packets.last().map_or(0, |packet| packet + 1);
+        self.record_at(packet, state);
     }
 
-    /// Returns whether any new edges were created in the state-graph during the last run.
-    /// Used by [`StateFeedback`](crate::StateFeedback).
+    /// Tell the observer that the target has entered state `state` after
+    /// receiving the packet with the index `packet`.
+    ///
+    /// # Example
+    /// ```
+    /// for (i, packet) in input.packets().iter().enumerate() {
+    ///     let response = send(packet);
+    ///     state_observer.record_at(i, &response.status_code);
+    /// }
+    /// ```
+    pub fn record_at(&mut self, packet: usize, state: &PS) {
+        let id = match self.nodes.get(state) {
+            Some(id) => *id,
+            None => {
+                let next_id = self.nodes.len() as u32;
+                assert!(self.nodes.insert(state.clone(), next_id).is_none());
+                self.new_states.push(state.clone());
+                next_id
+            },
+        };
+
+        self.packets.push(packet);
+        self.path.push(id);
+    }
+
+    /// Tell the observer the [kind](crate::HasPacketKind) of every packet of the current run, in order.
+    ///
+    /// The state-graph remembers the kind of the packet that discovered a transition
+    /// and shows it in [`StateGraphMetadata::to_dot()`].
+    /// The [`NetworkExecutor`](crate::NetworkExecutor) does this automatically, custom executors
+    /// call it once per run.
+    ///
+    /// # Example
+    /// ```
+    /// state_observer.record_packet_kinds(input.packets().iter().map(HasPacketKind::packet_kind));
+    /// ```
+    pub fn record_packet_kinds<K>(&mut self, kinds: K)
+    where
+        K: IntoIterator<Item = u32>,
+    {
+        self.kinds.clear();
+        self.kinds.extend(kinds);
+    }
+
+    /// Returns the id of `state` in the state-graph
+    pub fn state_id(&self, state: &PS) -> Option<u32> {
+        self.nodes.get(state).copied()
+    }
+
+    /// Returns whether any new vertices were created in the state-graph during the last run.
+    pub fn had_new_nodes(&self) -> bool {
+        self.novelty.contains(StateNovelty::NEW_NODE)
+    }
+
+    /// Returns whether any new edges between two different states were created
+    /// in the state-graph during the last run.
     pub fn had_new_transitions(&self) -> bool {
-        self.graph.new_transitions
+        self.novelty.contains(StateNovelty::NEW_EDGE)
     }
 
-    /// Returns the number of vertices and edges in the state-graph.
+    /// Returns whether a state followed itself for the first time during the last run.
+    pub fn had_new_self_loops(&self) -> bool {
+        self.novelty.contains(StateNovelty::NEW_SELF_LOOP)
+    }
+
+    /// Returns whether a state or transition was visited a number of times
+    /// in the last run that falls into a new hit count bucket.
+    /// Always false if the observer was not created with [`StateObserver::with_hitcounts()`].
+    pub fn had_new_hitcounts(&self) -> bool {
+        self.novelty.contains(StateNovelty::NEW_HITCOUNT)
+    }
+
+    /// Returns all kinds of novelty that were seen during the last run.
     /// Used by [`StateFeedback`](crate::StateFeedback).
-    pub fn info(&self) -> (usize, usize) {
-        (self.graph.nodes.len(), self.graph.edges.len())
+    pub fn novelty(&self) -> StateNovelty {
+        self.novelty
+    }
+
+    /// Returns the index of the first packet in the last run that led to a new state or transition.
+    pub fn first_new_packet(&self) -> Option<usize> {
+        self.first_new_packet
+    }
+
+    /// Returns the ids of all states that were recorded during the last run in the order
+    /// they were recorded.
+    /// Used by [`StatePathFeedback`](crate::StatePathFeedback).
+    pub fn path(&self) -> &[u32] {
+        &self.path
+    }
+
+    /// Returns the packet index at which each state in [`path()`](StateObserver::path) was recorded.
+    pub fn packets(&self) -> &[usize] {
+        &self.packets
+    }
+
+    /// Returns all transitions of the last run as (packet index, from, to) where
+    /// the packet is the one that moved the target from state `from` to state `to`.
+    pub fn transitions(&self) -> impl Iterator<Item = (usize, u32, u32)> + '_ {
+        transitions(&self.path, &self.packets)
+    }
+
+    /// Returns the number of vertices and edges in the state-graph.
+    #[deprecated(note = "The state-graph is stored in the state, use StateGraphMetadata::nodes() and StateGraphMetadata::edges()")]
+    pub fn info<S>(&self, state: &S) -> (usize, usize)
+    where
+        S: HasNamedMetadata,
+    {
+        state.named_metadata::<StateGraphMetadata>(&self.name).map_or((0, 0), |graph| (graph.nodes(), graph.edges()))
     }
 
     /// Returns a DOT representation of the statemachine.
-    pub fn get_statemachine(&self) -> String {
-        let mut s = String::with_capacity(1024);
-        self.graph.write_dot(&mut s);
-        s
+    #[deprecated(note = "The state-graph is stored in the state, use StateGraphMetadata::to_dot()")]
+    pub fn get_statemachine<S>(&self, state: &S) -> String
+    where
+        S: HasNamedMetadata,
+    {
+        state.named_metadata::<StateGraphMetadata>(&self.name).map_or_else(|_| StateGraphMetadata::default().to_dot(), StateGraphMetadata::to_dot)
+    }
+
+    /// Returns the states of the last run in the order they were recorded
+    fn trace(&self) -> Vec<PS> {
+        if self.is_remote() {
+            return self.trace.clone();
+        }
+
+        let mut states = vec![None; self.nodes.len()];
+
+        for (state, id) in &self.nodes {
+            states[*id as usize] = Some(state);
+        }
+
+        self.path.iter().filter_map(|id| states[*id as usize].cloned()).collect()
+    }
+
+    /// Returns whether the observer was received from another fuzzer instance.
+    /// Its states have not been added to the local state-graph yet.
+    pub(crate) fn is_remote(&self) -> bool {
+        !self.local
+    }
+
+    /// Map the states of a remote observer to ids in the local state-graph.
+    /// States that are unknown get added to the graph.
+    /// Returns the path of the run and whether any states were new.
+    pub(crate) fn import_path(&self, graph: &mut StateGraphMetadata) -> Result<(Vec<u32>, bool), Error> {
+        let mut ids = HashMap::<&PS, u32, RandomState>::default();
+        let mut path = Vec::with_capacity(self.trace.len());
+        let mut new_nodes = false;
+
+        for state in &self.trace {
+            let id = match ids.get(state) {
+                Some(id) => *id,
+                None => {
+                    // postcard is deterministic so equal states have equal bytes
+                    let (id, new) = graph.state_id(postcard::to_allocvec(state)?);
+                    new_nodes |= new;
+                    ids.insert(state, id);
+                    id
+                },
+            };
+
+            path.push(id);
+        }
+
+        Ok((path, new_nodes))
+    }
+
+    /// Returns the path of the last run in ids of the local state-graph, which is stored
+    /// under the name of the observer in `state`.
+    /// Works for local and remote observers alike.
+    pub(crate) fn local_path<S>(&self, state: &mut S) -> Result<Vec<u32>, Error>
+    where
+        S: HasNamedMetadata,
+    {
+        if self.is_remote() {
+            let graph = state.named_metadata_or_insert_with(&self.name, StateGraphMetadata::default);
+            Ok(self.import_path(graph)?.0)
+        } else {
+            Ok(self.path.clone())
+        }
+    }
+
+    /// Add the states and transitions of a remote observer to the local state-graph
+    /// and return what was new to it together with the first packet that led to something new.
+    pub(crate) fn merge(&self, graph: &mut StateGraphMetadata) -> Result<(StateNovelty, Option<usize>), Error> {
+        let known = graph.states.len();
+        let (path, new_nodes) = self.import_path(graph)?;
+        let (mut novelty, first_new_packet) = graph.add_path(&path, &self.packets, &self.kinds, known);
+
+        if new_nodes {
+            novelty |= StateNovelty::NEW_NODE;
+        }
+
+        Ok((novelty, first_new_packet))
+    }
+
+    /// Make the ids of the observer match the ids of the state-graph
+    fn sync(&mut self, graph: &mut StateGraphMetadata) -> Result<(), Error> {
+        // states of a run that never finished
+        for state in self.new_states.drain(..) {
+            self.nodes.remove(&state);
+        }
+
+        if self.hitcounts && graph.hitcounts.is_none() {
+            graph.hitcounts = Some(HitCounts::default());
+        }
+
+        if self.nodes.len() > graph.states.len() {
+            self.nodes.clear();
+        }
+
+        for id in self.nodes.len()..graph.states.len() {
+            let state: PS = postcard::from_bytes(&graph.states[id])?;
+            self.nodes.insert(state, id as u32);
+        }
+
+        Ok(())
+    }
+
+    /// Add the states and transitions of the last run to the state-graph
+    fn update(&mut self, graph: &mut StateGraphMetadata) -> Result<(), Error> {
+        let known = graph.states.len();
+
+        if !self.new_states.is_empty() {
+            self.novelty |= StateNovelty::NEW_NODE;
+
+            for state in self.new_states.drain(..) {
+                graph.states.push(postcard::to_allocvec(&state)?);
+            }
+        }
+
+        let (novelty, first_new_packet) = graph.add_path(&self.path, &self.packets, &self.kinds, known);
+        self.novelty |= novelty;
+        self.first_new_packet = first_new_packet;
+        Ok(())
+    }
+}
+
+/// What a [`StateObserver`] sends to other fuzzer instances.
+/// The states of the run are only looked up when the observer actually gets serialized.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "PS: serde::Serialize + for<'a> serde::Deserialize<'a>")]
+struct RemoteStateObserver<PS> {
+    name: Cow<'static, str>,
+    hitcounts: bool,
+    trace: Vec<PS>,
+    packets: Vec<usize>,
+    kinds: Vec<u32>,
+    novelty: StateNovelty,
+    first_new_packet: Option<usize>,
+}
+
+impl<PS> Serialize for StateObserver<PS>
+where
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        RemoteStateObserver {
+            name: self.name.clone(),
+            hitcounts: self.hitcounts,
+            trace: self.trace(),
+            packets: self.packets.clone(),
+            kinds: self.kinds.clone(),
+            novelty: self.novelty,
+            first_new_packet: self.first_new_packet,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, PS> Deserialize<'de> for StateObserver<PS>
+where
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let remote = RemoteStateObserver::<PS>::deserialize(deserializer)?;
+
+        Ok(Self {
+            name: remote.name,
+            hitcounts: remote.hitcounts,
+            nodes: HashMap::<PS, u32, RandomState>::default(),
+            new_states: Vec::new(),
+            path: Vec::new(),
+            trace: remote.trace,
+            packets: remote.packets,
+            kinds: remote.kinds,
+            novelty: remote.novelty,
+            first_new_packet: remote.first_new_packet,
+            local: false,
+        })
     }
 }
 
@@ -160,9 +886,10 @@ where
     }
 }
 
-impl<PS> MatchName for StateObserver<PS> 
+#[cfg(not(feature = "safe_only"))]
+impl<PS> MatchName for StateObserver<PS>
 where
-    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>
+    PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
 {
     fn match_name<T>(&self, name: &str) -> Option<&T> {
         if self.name == name {
@@ -184,73 +911,263 @@ where
 impl<PS, I, S> Observer<I, S> for StateObserver<PS>
 where
     PS: Clone + Debug + Hash + Eq + Serialize + for<'a> Deserialize<'a>,
+    S: HasNamedMetadata,
 {
-    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
-        self.graph.reset();
-        Ok(())
+    fn pre_exec(&mut self, state: &mut S, _input: &I) -> Result<(), Error> {
+        self.path.clear();
+        self.packets.clear();
+        self.kinds.clear();
+        self.novelty = StateNovelty::NONE;
+        self.first_new_packet = None;
+
+        let graph = state.named_metadata_or_insert_with(&self.name, StateGraphMetadata::default);
+        self.sync(graph)
     }
 
-    fn post_exec(&mut self, _state: &mut S, _input: &I, _exit_kind: &ExitKind) -> Result<(), Error> {
-        Ok(())
+    fn post_exec(&mut self, state: &mut S, _input: &I, _exit_kind: &ExitKind) -> Result<(), Error> {
+        let graph = state.named_metadata_or_insert_with(&self.name, StateGraphMetadata::default);
+        self.update(graph)
     }
 }
 
-/*
 #[cfg(test)]
-mod benchmarks {
-    extern crate test;
+mod tests {
     use super::*;
-    use test::Bencher;
-
-    type State = [u8; 32];
-
-    fn state(n: usize) -> State {
-        let mut state = State::default();
-        state[0..8].copy_from_slice(&n.to_le_bytes());
-        state
-    }
-
-    #[bench]
-    fn bench_duplicates(b: &mut Bencher) {
-        let mut graph = StateGraph::<State>::new();
-        b.iter(|| {
-            let node = graph.add_node(&State::default());
-            graph.add_edge(node);
-        });
-    }
-
-    #[bench]
-    fn bench_insertions(b: &mut Bencher) {
-        let mut graph = StateGraph::<State>::new();
-        let mut i: usize = 0;
-        b.iter(|| {
-            let node = graph.add_node(&state(i));
-            graph.add_edge(node);
-            i += 1;
-        });
-    }
-
-    #[bench]
-    #[ignore]
-    fn memory_footprint(_: &mut Bencher) {
-        let mut graph = StateGraph::<State>::new();
-        let limit: usize = 24576;
-
-        for i in 0..limit {
-            let i_node = graph.add_node(&state(i));
-
-            for j in 0..limit {
-                let j_node = graph.add_node(&state(j));
-                graph.add_edge(i_node);
-                graph.add_edge(j_node);
-                graph.reset();
-            }
+    use libafl_bolts::serdeany::NamedSerdeAnyMap;
+
+    #[derive(Default, Serialize, Deserialize)]
+    struct TestState {
+        metadata: NamedSerdeAnyMap,
+    }
+    impl HasNamedMetadata for TestState {
+        fn named_metadata_map(&self) -> &NamedSerdeAnyMap {
+            &self.metadata
+        }
+
+        fn named_metadata_map_mut(&mut self) -> &mut NamedSerdeAnyMap {
+            &mut self.metadata
+        }
+    }
+    impl TestState {
+        fn graph(&self) -> &StateGraphMetadata {
+            self.named_metadata::<StateGraphMetadata>("state").unwrap()
+        }
+    }
+
+    fn run(observer: &mut StateObserver<u32>, state: &mut TestState, states: &[u32]) {
+        Observer::<(), TestState>::pre_exec(observer, state, &()).unwrap();
+
+        for s in states {
+            observer.record(s);
+        }
+
+        Observer::<(), TestState>::post_exec(observer, state, &(), &ExitKind::Ok).unwrap();
+    }
+
+    #[test]
+    fn test_novelty() {
+        let mut observer = StateObserver::<u32>::new("state");
+        let mut state = TestState::default();
+
+        run(&mut observer, &mut state, &[0]);
+        assert_eq!(observer.novelty(), StateNovelty::NEW_NODE);
+
+        run(&mut observer, &mut state, &[0, 0]);
+        assert_eq!(observer.novelty(), StateNovelty::NEW_SELF_LOOP);
+
+        run(&mut observer, &mut state, &[0, 1]);
+        assert_eq!(observer.novelty(), StateNovelty::NEW_NODE | StateNovelty::NEW_EDGE);
+        assert_eq!(observer.novelty().to_string(), "new node | new edge");
+
+        run(&mut observer, &mut state, &[0, 0, 1]);
+        assert!(observer.novelty().is_empty());
+        assert_eq!(observer.path(), &[0, 0, 1]);
+        assert_eq!((state.graph().nodes(), state.graph().edges()), (2, 1));
+        assert!(state.graph().to_dot().contains("\"0\"->\"0\"[label=\"packet 1\"];"));
+
+        #[allow(deprecated)]
+        {
+            assert_eq!(observer.info(&state), (2, 1));
+            assert_eq!(observer.get_statemachine(&state), state.graph().to_dot());
         }
+    }
+
+    #[test]
+    fn test_trigger_kinds() {
+        let mut observer = StateObserver::<u32>::new("state");
+        let mut state = TestState::default();
+
+        Observer::<(), TestState>::pre_exec(&mut observer, &mut state, &()).unwrap();
+        observer.record_packet_kinds([3, 5]);
+        observer.record(&0);
+        observer.record(&1);
+        Observer::<(), TestState>::post_exec(&mut observer, &mut state, &(), &ExitKind::Ok).unwrap();
+
+        assert_eq!(state.graph().trigger(0, 1), Some(1));
+        assert_eq!(state.graph().trigger_kind(0, 1), Some(5));
+        assert!(state.graph().to_dot().contains("\"0\"->\"1\"[label=\"packet 1 (kind 5)\"];"));
+
+        // the kinds are forgotten after the run
+        run(&mut observer, &mut state, &[1, 0]);
+        assert_eq!(state.graph().trigger_kind(1, 0), None);
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut observer = StateObserver::<u32>::new("state");
+        let mut state = TestState::default();
+        run(&mut observer, &mut state, &[5, 7, 5]);
+
+        // a restarted fuzzer gets the serialized state and a fresh observer
+        let mut state: TestState = postcard::from_bytes(&postcard::to_allocvec(&state).unwrap()).unwrap();
+        let mut observer = StateObserver::<u32>::new("state");
+
+        run(&mut observer, &mut state, &[7, 5, 7]);
+        assert!(observer.novelty().is_empty());
+        assert_eq!(observer.path(), &[1, 0, 1]);
+        assert_eq!(state.graph().state::<u32>(1), Some(7));
+
+        // a run that never finished doesn't leave anything behind
+        Observer::<(), TestState>::pre_exec(&mut observer, &mut state, &()).unwrap();
+        observer.record(&9);
+        run(&mut observer, &mut state, &[11, 9]);
+        assert_eq!(observer.path(), &[2, 3]);
+        assert_eq!(state.graph().state::<u32>(2), Some(11));
+    }
+
+    #[test]
+    fn test_packets() {
+        let mut observer = StateObserver::<u32>::new("state");
+        let mut state = TestState::default();
+
+        Observer::<(), TestState>::pre_exec(&mut observer, &mut state, &()).unwrap();
+        observer.record(&220);
+        observer.record_at(3, &331);
+        observer.record(&230);
+        observer.record_at(4, &230);
+        Observer::<(), TestState>::post_exec(&mut observer, &mut state, &(), &ExitKind::Ok).unwrap();
+
+        assert_eq!(observer.packets(), &[0, 3, 4, 4]);
+        assert_eq!(transitions(&[], &[]).count(), 0);
+        assert_eq!(observer.transitions().collect::<Vec<_>>(), [(3, 0, 1), (4, 1, 2), (4, 2, 2)]);
+        assert_eq!(state.graph().trigger(0, 1), Some(3));
+        assert_eq!(state.graph().trigger(2, 2), Some(4));
+        assert_eq!(observer.first_new_packet(), Some(0));
+
+        // the first packet that took a transition is kept
+        run(&mut observer, &mut state, &[230, 220, 331]);
+        assert_eq!(state.graph().trigger(0, 1), Some(3));
+        assert_eq!(state.graph().trigger(2, 0), Some(1));
+        assert_eq!(observer.first_new_packet(), Some(1));
+
+        run(&mut observer, &mut state, &[220, 331, 230, 230, 500]);
+        assert_eq!(observer.first_new_packet(), Some(4));
+        run(&mut observer, &mut state, &[220, 331]);
+        assert_eq!(observer.first_new_packet(), None);
+    }
 
-        println!("nodes = {}", graph.nodes.len());
-        println!("edges = {}", graph.edges.len());
+    #[test]
+    fn test_merge() {
+        let mut observer = StateObserver::<u32>::new("state");
+        let mut state = TestState::default();
+        run(&mut observer, &mut state, &[3, 4, 3]);
 
-        loop {}
+        // the same observer on another fuzzer instance that already knows 4 -> 3
+        let mut other_observer = StateObserver::<u32>::new("state");
+        let mut other_state = TestState::default();
+        run(&mut other_observer, &mut other_state, &[4, 3]);
+
+        let remote: StateObserver<u32> = postcard::from_bytes(&postcard::to_allocvec(&observer).unwrap()).unwrap();
+        assert!(remote.is_remote());
+        assert!(!observer.is_remote());
+        assert!(observer.trace.is_empty());
+        assert_eq!(remote.trace, [3, 4, 3]);
+
+        let graph = other_state.named_metadata_map_mut().get_mut::<StateGraphMetadata>("state").unwrap();
+        assert_eq!(remote.merge(graph).unwrap(), (StateNovelty::NEW_EDGE, Some(1)));
+        assert!(graph.has_edge(1, 0));
+        assert!(remote.merge(graph).unwrap().0.is_empty());
+
+        // the local observer picks up the merged transitions
+        run(&mut other_observer, &mut other_state, &[3, 4, 3]);
+        assert!(other_observer.novelty().is_empty());
+        assert_eq!(other_observer.path(), &[1, 0, 1]);
+    }
+
+    #[test]
+    fn test_hitcount_buckets() {
+        assert_eq!(hitcount_bucket(1), 1);
+        assert_eq!(hitcount_bucket(3), 4);
+        assert_eq!(hitcount_bucket(7), 8);
+        assert_eq!(hitcount_bucket(127), 64);
+        assert_eq!(hitcount_bucket(u32::MAX), 128);
+    }
+
+    #[test]
+    fn test_hitcounts() {
+        let mut observer = StateObserver::<u32>::with_hitcounts("state");
+        let mut state = TestState::default();
+
+        run(&mut observer, &mut state, &[0, 1, 0]);
+        assert!(observer.had_new_transitions());
+        assert!(observer.had_new_hitcounts());
+
+        // same counts again
+        run(&mut observer, &mut state, &[0, 1, 0]);
+        assert!(!observer.had_new_transitions());
+        assert!(!observer.had_new_hitcounts());
+
+        // 0 -> 1 is taken twice now
+        run(&mut observer, &mut state, &[0, 1, 0, 1]);
+        assert!(!observer.had_new_transitions());
+        assert!(observer.had_new_hitcounts());
+
+        run(&mut observer, &mut state, &[0, 1, 0, 1, 0, 1]);
+        assert!(observer.had_new_hitcounts());
+        run(&mut observer, &mut state, &[0, 1, 0, 1, 0, 1]);
+        assert!(!observer.had_new_hitcounts());
+
+        let graph = state.graph();
+        assert_eq!(graph.node_hitcount(0), Some(12));
+        assert_eq!(graph.edge_hitcount(0, 1), Some(10));
+        assert_eq!(graph.edge_hitcount(1, 0), Some(7));
+        assert_eq!(graph.edge_hitcount(1, 2), None);
+        assert_eq!(graph.cold_edges(), Some(0));
+        assert!(graph.to_dot().contains("\"0\"->\"1\"[label=\"packet 1\\n10 hits\",penwidth=4];"));
+
+        let mut observer = StateObserver::<u32>::new("state");
+        let mut state = TestState::default();
+        run(&mut observer, &mut state, &[0, 1, 0]);
+        run(&mut observer, &mut state, &[0, 1, 0, 1]);
+        assert!(!observer.had_new_hitcounts());
+        assert_eq!(state.graph().edge_hitcount(0, 1), None);
+        assert_eq!(state.graph().cold_edges(), None);
+    }
+
+    #[test]
+    fn test_merge_graphs() {
+        let mut observer = StateObserver::<u32>::new("state");
+        let mut a = TestState::default();
+        let mut b = TestState::default();
+
+        run(&mut observer, &mut a, &[1, 2, 2]);
+        run(&mut observer, &mut b, &[3, 2, 1]);
+
+        let mut global = StateGraphMetadata::decode(&a.graph().encode().unwrap()).unwrap();
+        assert_eq!((global.nodes(), global.edges(), global.self_loops()), (2, 1, 1));
+
+        global.merge(&StateGraphMetadata::decode(&b.graph().encode().unwrap()).unwrap());
+        assert_eq!((global.nodes(), global.edges(), global.self_loops()), (3, 3, 1));
+        assert_eq!(global.state::<u32>(2), Some(3));
+        assert!(global.has_edge(2, 1) && global.has_edge(1, 0));
+        assert_eq!(global.trigger(2, 1), Some(1));
+
+        // merging the same graph again changes nothing
+        global.merge(b.graph());
+        assert_eq!((global.nodes(), global.edges(), global.self_loops()), (3, 3, 1));
+
+        assert!(StateGraphMetadata::decode("abc").is_none());
+        assert!(StateGraphMetadata::decode("zz").is_none());
     }
 }
-*/
\ No newline at end of file
+
@@ -0,0 +1,78 @@
+use crate::{
+    feedback::{StateNoveltyMetadata, StatePathMetadata, StateTraceMetadata},
+    observer::StateGraphMetadata,
+    scheduler::{PotencyMetadata, StateSchedulerMetadata},
+};
+
+/// Implement [`SerdeAny`](libafl_bolts::serdeany::SerdeAny) for a metadata type.
+///
+/// This is the safe part of libafls `impl_serdeany!`. The registration of the type for
+/// deserialization needs a static constructor, which counts as unsafe code, so it is done
+/// separately below and only without the feature `safe_only`.
+/// The unit tests always register the types because they cannot invoke [`register_metadata!`].
+macro_rules! impl_serdeany_safe {
+    ($($name:ty),+ $(,)?) => {
+        $(
+            impl libafl_bolts::serdeany::SerdeAny for $name {
+                fn as_any(&self) -> &dyn std::any::Any {
+                    self
+                }
+
+                fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+                    self
+                }
+
+                fn as_any_boxed(self: Box<Self>) -> Box<dyn std::any::Any> {
+                    self
+                }
+
+                fn type_name(&self) -> &'static str {
+                    std::any::type_name::<Self>()
+                }
+            }
+        )+
+    };
+}
+
+impl_serdeany_safe!(StateNoveltyMetadata, StateTraceMetadata, StatePathMetadata, StateGraphMetadata, PotencyMetadata, StateSchedulerMetadata);
+
+#[cfg(any(not(feature = "safe_only"), test))]
+libafl_bolts::create_register!(StateNoveltyMetadata);
+#[cfg(any(not(feature = "safe_only"), test))]
+libafl_bolts::create_register!(StateTraceMetadata);
+#[cfg(any(not(feature = "safe_only"), test))]
+libafl_bolts::create_register!(StatePathMetadata);
+#[cfg(any(not(feature = "safe_only"), test))]
+libafl_bolts::create_register!(StateGraphMetadata);
+#[cfg(any(not(feature = "safe_only"), test))]
+libafl_bolts::create_register!(PotencyMetadata);
+#[cfg(any(not(feature = "safe_only"), test))]
+libafl_bolts::create_register!(StateSchedulerMetadata);
+
+/// Register the metadata types of butterfly for deserialization.
+///
+/// Without the feature `safe_only` this happens automatically at startup and the macro is not needed.
+/// With `safe_only` butterfly itself contains no unsafe code, so the registration
+/// has to be done by the fuzzer. Invoke this macro at the start of `main()`,
+/// before any threads are spawned and before a state is deserialized.
+/// It expands to an `unsafe` block in the crate of the fuzzer.
+///
+/// # Example
+/// ```
+/// // first thing in main()
+/// butterfly::register_metadata!();
+/// ```
+#[macro_export]
+macro_rules! register_metadata {
+    () => {
+        unsafe {
+            use $crate::__private::libafl_bolts::serdeany::RegistryBuilder;
+            RegistryBuilder::register::<$crate::StateNoveltyMetadata>();
+            RegistryBuilder::register::<$crate::StateTraceMetadata>();
+            RegistryBuilder::register::<$crate::StatePathMetadata>();
+            RegistryBuilder::register::<$crate::StateGraphMetadata>();
+            RegistryBuilder::register::<$crate::PotencyMetadata>();
+            RegistryBuilder::register::<$crate::StateSchedulerMetadata>();
+        }
+    };
+}
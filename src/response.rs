@@ -0,0 +1,199 @@
+use libafl_bolts::{
+    shmem::{ShMem, ShMemProvider},
+    Named,
+};
+use libafl::{observers::Observer, Error};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Name of the environment variable that holds the id of the shared memory of a [`ResponseObserver`].
+/// The size of the buffer is stored in the variable with the suffix `_SIZE`.
+pub const RESPONSE_BUFFER_ENV: &str = "BUTTERFLY_RESPONSE_SHM";
+
+/// Size of the record counter at the start of the buffer
+const HEADER_LEN: usize = 4;
+/// Size of the packet index and length in front of every response
+const RECORD_HEADER_LEN: usize = 8;
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<usize> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+/// Iterate over all well-formed responses in `buf`. Parsing stops at the first truncated record.
+fn parse_responses(buf: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let count = read_u32(buf, 0).unwrap_or(0);
+    let mut offset = HEADER_LEN;
+
+    (0..count).map_while(move |_| {
+        let packet = read_u32(buf, offset)?;
+        let len = read_u32(buf, offset + 4)?;
+        let start = offset + RECORD_HEADER_LEN;
+        let response = buf.get(start..start.checked_add(len)?)?;
+        offset = start + len;
+        Some((packet, response))
+    })
+}
+
+/// An observer that owns a shared memory buffer in which a target writes the responses to the packets it received.
+///
+/// The target is usually run with a preload library that replaces the socket API.
+/// butterfly does not ship one, so a desocketing library has to be extended to write the buffer.
+/// That library gets the buffer from the environment variables returned by
+/// [`ResponseObserver::envs()`] and appends a record for every response that the target sends.
+/// Feedbacks and executors can then look at the responses of the last run with
+/// [`ResponseObserver::responses()`] and [`ResponseObserver::responses_to()`].
+///
+/// # Layout
+/// All integers are 32-bit little-endian. `count` is the number of records that follow.
+/// Every record contains the index of the packet that the response belongs to, the length of the response
+/// and the bytes of the response.
+/// ```text
+/// | count | packet | len | response | packet | len | response | ...
+/// ```
+/// The observer resets `count` to zero in `pre_exec()`.
+///
+/// The packet index refers to the position of the packet in the testcase that the target received.
+/// For a [`PacketBytesInput`](crate::PacketBytesInput) that is the length-prefixed wire encoding,
+/// the preload library splits it at the 32-bit little-endian length fields and counts the packets from 0:
+/// ```text
+/// | len(packet 0): u32 | packet 0 | len(packet 1): u32 | packet 1 | ...
+/// ```
+///
+/// The shared memory is not sent to other fuzzer instances,
+/// so an observer that was received from another instance has no responses.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct ResponseObserver<SHM>
+where
+    SHM: ShMem,
+{
+    name: Cow<'static, str>,
+    #[serde(skip)]
+    shmem: Option<SHM>,
+}
+
+impl<SHM> ResponseObserver<SHM>
+where
+    SHM: ShMem,
+{
+    /// Create a new ResponseObserver that allocates a buffer of `size` bytes with the given shared memory provider
+    pub fn new<SP>(name: &'static str, provider: &mut SP, size: usize) -> Result<Self, Error>
+    where
+        SP: ShMemProvider<ShMem = SHM>,
+    {
+        if size < HEADER_LEN {
+            return Err(Error::illegal_argument(format!("Response buffer must have at least {} bytes", HEADER_LEN)));
+        }
+
+        let mut ret = Self {
+            name: Cow::Borrowed(name),
+            shmem: Some(provider.new_shmem(size)?),
+        };
+        ret.reset();
+        Ok(ret)
+    }
+
+    /// The environment variables that tell the target where to find the buffer.
+    ///
+    /// # Example
+    /// ```
+    /// let response_observer = ResponseObserver::new("responses", &mut shmem_provider, 65536)?;
+    /// let executor = ForkserverExecutor::builder()
+    ///     .envs(response_observer.envs())
+    ///     ...
+    /// ```
+    pub fn envs(&self) -> Vec<(String, String)> {
+        match &self.shmem {
+            Some(shmem) => vec![(RESPONSE_BUFFER_ENV.to_string(), shmem.id().to_string()), (format!("{}_SIZE", RESPONSE_BUFFER_ENV), shmem.len().to_string())],
+            None => Vec::new(),
+        }
+    }
+
+    /// Remove all responses
+    fn reset(&mut self) {
+        if let Some(shmem) = &mut self.shmem {
+            shmem[..HEADER_LEN].fill(0);
+        }
+    }
+
+    /// Iterate over the responses of the last run together with the index of the packet they belong to.
+    ///
+    /// Records that exceed the buffer are ignored, so a misbehaving target cannot cause an out-of-bounds read.
+    pub fn responses(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        parse_responses(self.shmem.as_deref().unwrap_or_default())
+    }
+
+    /// Iterate over all responses to the packet with the index `packet`
+    pub fn responses_to(&self, packet: usize) -> impl Iterator<Item = &[u8]> {
+        self.responses().filter(move |(index, _)| *index == packet).map(|(_, response)| response)
+    }
+}
+
+impl<SHM> Named for ResponseObserver<SHM>
+where
+    SHM: ShMem,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<SHM, I, S> Observer<I, S> for ResponseObserver<SHM>
+where
+    SHM: ShMem,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.reset();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl_bolts::shmem::StdShMemProvider;
+
+    fn record(buf: &mut Vec<u8>, packet: u32, response: &[u8]) {
+        buf.extend_from_slice(&packet.to_le_bytes());
+        buf.extend_from_slice(&(response.len() as u32).to_le_bytes());
+        buf.extend_from_slice(response);
+    }
+
+    #[test]
+    fn test_parse_responses() {
+        let mut buf = 3u32.to_le_bytes().to_vec();
+        record(&mut buf, 0, b"220 Welcome\r\n");
+        record(&mut buf, 2, b"331 Password required\r\n");
+        record(&mut buf, 3, b"230 Logged in\r\n");
+
+        let responses: Vec<(usize, &[u8])> = parse_responses(&buf).collect();
+        assert_eq!(responses, [(0, &b"220 Welcome\r\n"[..]), (2, b"331 Password required\r\n"), (3, b"230 Logged in\r\n")]);
+
+        // truncated records and bogus counts
+        assert_eq!(parse_responses(&buf[..buf.len() - 1]).count(), 2);
+        buf[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse_responses(&buf).count(), 3);
+        assert_eq!(parse_responses(&[1, 0]).count(), 0);
+    }
+
+    #[test]
+    fn test_response_observer() {
+        let mut provider = StdShMemProvider::new().unwrap();
+        let mut observer = ResponseObserver::new("responses", &mut provider, 256).unwrap();
+        assert!(ResponseObserver::new("responses", &mut provider, 2).is_err());
+        assert_eq!(observer.envs()[0].0, RESPONSE_BUFFER_ENV);
+
+        let mut buf = 3u32.to_le_bytes().to_vec();
+        record(&mut buf, 0, b"220 Welcome\r\n");
+        record(&mut buf, 1, b"331 Password required\r\n");
+        record(&mut buf, 1, b"230 Logged in\r\n");
+        observer.shmem.as_mut().unwrap()[..buf.len()].copy_from_slice(&buf);
+
+        assert_eq!(observer.responses_to(1).collect::<Vec<_>>(), [&b"331 Password required\r\n"[..], b"230 Logged in\r\n"]);
+        assert_eq!(observer.responses_to(2).count(), 0);
+
+        Observer::<(), ()>::pre_exec(&mut observer, &mut (), &()).unwrap();
+        assert_eq!(observer.responses().count(), 0);
+    }
+}
@@ -1,11 +1,40 @@
-use libafl_bolts::{rands::Rand, Named};
+use crate::{feedback::StateTraceMetadata, input::HasPackets, observer::StateObserver};
+use libafl_bolts::{generic_hash_std, rands::Rand, HasLen, Named};
 use libafl::{
-    inputs::Input,
+    common::{HasMetadata, HasNamedMetadata},
+    corpus::{Corpus, CorpusId, Testcase},
+    inputs::{BytesInput, Input},
     mutators::{ComposedByMutations, MutationId, MutationResult, Mutator, MutatorsTuple, ScheduledMutator},
-    state::HasRand,
+    random_corpus_id,
+    schedulers::{RemovableScheduler, Scheduler},
+    state::{HasCorpus, HasImported, HasRand},
     Error,
 };
-use std::{borrow::Cow, marker::PhantomData, num::NonZero};
+use libafl_bolts::tuples::{Handle, Handled, MatchName, MatchNameRef};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt::Debug,
+    hash::{DefaultHasher, Hash, Hasher},
+    marker::PhantomData,
+    num::NonZero,
+};
+
+/// Number of trials after which the statistics of a mutator or packet get halved
+/// so that old successes don't dominate the later stages of a campaign
+const POTENCY_DECAY_WINDOW: u64 = 4096;
+
+/// Lower bound for the probability that a mutation of an unproductive packet is kept
+const MIN_PACKET_ACCEPTANCE: f64 = 0.1;
+
+/// Number of mutations after which the [`PotencyMutationScheduler`] stops rejecting
+/// mutations of unproductive packets
+const MAX_PACKET_ATTEMPTS: usize = 8;
+
+/// Number of times the [`PotencyMutationScheduler`] tries to mutate an input,
+/// including mutators that skipped, before it gives up on the input
+const MAX_MUTATION_ATTEMPTS: usize = 64;
 
 /// A mutation scheduler for butterflys mutators.
 ///
@@ -91,7 +120,7 @@ where
 
     fn schedule(&self, state: &mut S, _input: &I) -> MutationId {
         MutationId::from(
-            state.rand_mut().below(NonZero::new(self.mutations.len()).unwrap()) as usize
+            state.rand_mut().below(NonZero::new(self.mutations.len()).unwrap())
         )
     }
 
@@ -106,3 +135,838 @@ where
         Ok(result)
     }
 }
+
+/// Statistics about how often a mutator or packet was chosen
+/// and how often that resulted in a new corpus entry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Potency {
+    trials: u64,
+    hits: u64,
+}
+
+impl Potency {
+    /// Number of executions with this choice
+    pub fn trials(&self) -> u64 {
+        self.trials
+    }
+
+    /// Number of executions with this choice that produced a new corpus entry
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Estimated probability that the next trial is a hit.
+    /// Choices without any trials get a score of `0.5`.
+    pub fn score(&self) -> f64 {
+        (self.hits as f64 + 1.0) / (self.trials as f64 + 2.0)
+    }
+
+    fn record(&mut self, hit: bool) {
+        self.trials += 1;
+
+        if hit {
+            self.hits += 1;
+        }
+
+        if self.trials >= POTENCY_DECAY_WINDOW {
+            self.trials /= 2;
+            self.hits /= 2;
+        }
+    }
+}
+
+/// Assigns a packet to a kind, like the variant of a packet enum or the command of a text protocol.
+///
+/// The [`PotencyMutationScheduler`] learns separately for every kind of packet how often mutating it
+/// produces new corpus entries. The kinds are stored in the state, so they must be the same
+/// across restarts and across all fuzzer instances of a campaign.
+///
+/// Already implemented for:
+/// - [`BytesInput`](libafl::inputs::BytesInput) which always has kind 0
+/// - [`Option<T>`](core::option::Option) where `None` has kind 0 and `Some` the kind of its content plus 1
+///
+/// With the `derive` feature, `#[derive(PacketMutations)]` implements it with the index of the enum variant.
+///
+/// # Example
+/// ```
+/// impl HasPacketKind for FTPCommand {
+///     fn packet_kind(&self) -> u32 {
+///         match self {
+///             FTPCommand::USER(_) => 0,
+///             FTPCommand::PASS(_) => 1,
+///             FTPCommand::QUIT => 2,
+///         }
+///     }
+/// }
+/// ```
+pub trait HasPacketKind {
+    /// The kind of this packet
+    fn packet_kind(&self) -> u32;
+}
+
+impl HasPacketKind for BytesInput {
+    fn packet_kind(&self) -> u32 {
+        0
+    }
+}
+
+impl<T> HasPacketKind for Option<T>
+where
+    T: HasPacketKind,
+{
+    fn packet_kind(&self) -> u32 {
+        match self {
+            Some(packet) => packet.packet_kind().wrapping_add(1),
+            None => 0,
+        }
+    }
+}
+
+fn packet_hash<P>(packet: &P) -> u64
+where
+    P: Hash + HasPacketKind,
+{
+    let mut hasher = DefaultHasher::new();
+    packet.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The weights that the [`PotencyMutationScheduler`] learned.
+///
+/// They live in the metadata of the state so they are saved and restored together with it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PotencyMetadata {
+    mutations: Vec<Potency>,
+    packet_indices: Vec<Potency>,
+    packet_kinds: BTreeMap<u32, Potency>,
+}
+
+impl PotencyMetadata {
+    /// Potency of the mutator at position `idx` in the list of mutators
+    pub fn mutation(&self, idx: usize) -> Potency {
+        self.mutations.get(idx).copied().unwrap_or_default()
+    }
+
+    /// Potency of mutating the packet at position `idx`
+    pub fn packet_index(&self, idx: usize) -> Potency {
+        self.packet_indices.get(idx).copied().unwrap_or_default()
+    }
+
+    /// Potency of mutating packets of the given [kind](HasPacketKind)
+    pub fn packet_kind(&self, kind: u32) -> Potency {
+        self.packet_kinds.get(&kind).copied().unwrap_or_default()
+    }
+
+    fn packet_score(&self, idx: usize, kind: u32) -> f64 {
+        self.packet_index(idx).score() * self.packet_kind(kind).score()
+    }
+
+    fn record(&mut self, mutation: usize, packet: Option<(usize, u32)>, hit: bool) {
+        if self.mutations.len() <= mutation {
+            self.mutations.resize(mutation + 1, Potency::default());
+        }
+
+        self.mutations[mutation].record(hit);
+
+        if let Some((idx, kind)) = packet {
+            if self.packet_indices.len() <= idx {
+                self.packet_indices.resize(idx + 1, Potency::default());
+            }
+
+            self.packet_indices[idx].record(hit);
+            self.packet_kinds.entry(kind).or_default().record(hit);
+        }
+    }
+}
+
+/// An adaptive version of the [`PacketMutationScheduler`].
+///
+/// Like the [`PacketMutationScheduler`] it executes exactly one mutator per run
+/// but it learns which mutators, packet positions and [packet kinds](HasPacketKind)
+/// produce new corpus entries, i.e. new coverage or new states reported by the
+/// [`StateFeedback`](crate::StateFeedback), and prefers them in future runs.
+///
+/// Mutators are chosen with a probability proportional to their success rate.
+/// To find out which packet a mutator changed, the scheduler compares the packets
+/// before and after the mutation. Mutations of packets with a low success rate
+/// are discarded with a certain probability and the mutator is run again, so
+/// that the packet choice gets biased without changing the mutators themselves.
+///
+/// The statistics are stored as [`PotencyMetadata`] in the state and halved
+/// every 4096 trials so that the scheduler keeps adapting.
+///
+/// # Example
+/// ```
+/// let mutator = PotencyMutationScheduler::new(tuple_list!(
+///     PacketHavocMutator::new(supported_havoc_mutations()),
+///     PacketReorderMutator::new(),
+///     PacketDeleteMutator::new(4),
+///     PacketDuplicateMutator::new(16)
+/// ));
+/// ```
+pub struct PotencyMutationScheduler<I, MT, S, P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+    P: Hash + HasPacketKind,
+{
+    mutations: MT,
+    last: Option<(usize, Option<(usize, u32)>)>,
+    phantom: PhantomData<(I, S, P)>,
+}
+
+impl<I, MT, S, P> PotencyMutationScheduler<I, MT, S, P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+    P: Hash + HasPacketKind,
+{
+    /// Create a new PotencyMutationScheduler with a list of mutators.
+    /// These mutators _should_ be from butterfly.   
+    /// It is not guaranteed that external mutators will work too.
+    pub fn new(mutations: MT) -> Self {
+        Self {
+            mutations,
+            last: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Pick a mutator with a probability proportional to its score
+    fn choose_mutation(&self, state: &mut S) -> usize {
+        let len = self.mutations.len();
+        let scores: Vec<f64> = match state.metadata_map().get::<PotencyMetadata>() {
+            Some(metadata) => (0..len).map(|idx| metadata.mutation(idx).score()).collect(),
+            None => vec![1.0; len],
+        };
+
+        let mut target = state.rand_mut().next_float() * scores.iter().sum::<f64>();
+
+        for (idx, score) in scores.iter().enumerate() {
+            if target < *score {
+                return idx;
+            }
+
+            target -= score;
+        }
+
+        len - 1
+    }
+
+    /// Find the first packet that differs between `before` and `after`
+    fn changed_packet(before: &[u64], after: &I) -> Option<(usize, u32)> {
+        let after = after.packets();
+
+        for (idx, packet) in after.iter().enumerate() {
+            if before.get(idx) != Some(&packet_hash(packet)) {
+                return Some((idx, packet.packet_kind()));
+            }
+        }
+
+        if before.len() > after.len() {
+            // a packet at the end was removed. Attribute it to the new last packet
+            return after.last().map(|packet| (after.len() - 1, packet.packet_kind()));
+        }
+
+        None
+    }
+
+    /// Probability that a mutation of `packet` gets accepted
+    fn acceptance(state: &S, input: &I, packet: (usize, u32)) -> f64 {
+        let Some(metadata) = state.metadata_map().get::<PotencyMetadata>() else {
+            return 1.0;
+        };
+
+        let best = input.packets().iter().enumerate().map(|(idx, packet)| metadata.packet_score(idx, packet.packet_kind())).fold(0.0, f64::max);
+
+        if best <= 0.0 {
+            return 1.0;
+        }
+
+        (metadata.packet_score(packet.0, packet.1) / best).max(MIN_PACKET_ACCEPTANCE)
+    }
+}
+
+impl<I, MT, S, P> ComposedByMutations for PotencyMutationScheduler<I, MT, S, P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+    P: Hash + HasPacketKind,
+{
+    type Mutations = MT;
+
+    fn mutations(&self) -> &Self::Mutations {
+        &self.mutations
+    }
+
+    fn mutations_mut(&mut self) -> &mut MT {
+        &mut self.mutations
+    }
+}
+
+impl<I, MT, S, P> Named for PotencyMutationScheduler<I, MT, S, P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+    P: Hash + HasPacketKind,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("PotencyMutationScheduler")
+    }
+}
+
+impl<I, MT, S, P> Mutator<I, S> for PotencyMutationScheduler<I, MT, S, P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+    P: Hash + HasPacketKind,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        self.scheduled_mutate(state, input)
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        if let Some((mutation, packet)) = self.last.take() {
+            state.metadata_or_insert_with(PotencyMetadata::default).record(mutation, packet, new_corpus_id.is_some());
+            self.mutations.get_and_post_exec(mutation, state, new_corpus_id)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I, MT, S, P> ScheduledMutator<I, S> for PotencyMutationScheduler<I, MT, S, P>
+where
+    I: Input + HasLen + HasPackets<P>,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+    P: Hash + HasPacketKind,
+{
+    fn iterations(&self, _state: &mut S, _input: &I) -> u64 {
+        1
+    }
+
+    fn schedule(&self, state: &mut S, _input: &I) -> MutationId {
+        MutationId::from(self.choose_mutation(state))
+    }
+
+    fn scheduled_mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let before: Vec<u64> = input.packets().iter().map(packet_hash).collect();
+        // Only needed to undo mutations that get rejected
+        let mut backup: Option<I> = None;
+        let mut mutations = 0;
+
+        for _ in 0..MAX_MUTATION_ATTEMPTS {
+            let mutation = self.choose_mutation(state);
+            let may_reject = mutations + 1 < MAX_PACKET_ATTEMPTS && state.metadata_map().contains::<PotencyMetadata>();
+
+            if may_reject && backup.is_none() {
+                backup = Some(input.clone());
+            }
+
+            if self.mutations.get_and_mutate(MutationId::from(mutation), state, input)? == MutationResult::Skipped {
+                continue;
+            }
+
+            mutations += 1;
+
+            let packet = Self::changed_packet(&before, input);
+            let accept = match packet {
+                Some(packet) if may_reject => {
+                    let acceptance = Self::acceptance(state, input, packet);
+                    state.rand_mut().coinflip(acceptance)
+                },
+                _ => true,
+            };
+
+            if accept {
+                self.last = Some((mutation, packet));
+                return Ok(MutationResult::Mutated);
+            }
+
+            input.clone_from(backup.as_ref().unwrap());
+        }
+
+        Ok(MutationResult::Skipped)
+    }
+}
+
+/// What the [`StateAwareScheduler`] knows about a single vertex of the state-graph.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ScheduledState {
+    seeds: Vec<CorpusId>,
+    selected: u64,
+    found: u64,
+    discovered: u64,
+}
+
+impl ScheduledState {
+    /// Corpus entries that reach this state
+    pub fn seeds(&self) -> &[CorpusId] {
+        &self.seeds
+    }
+
+    /// How often this state was chosen as the target state
+    pub fn selected(&self) -> u64 {
+        self.selected
+    }
+
+    /// How many new corpus entries were found while this state was the target state.
+    /// Entries imported from other fuzzer instances don't count.
+    pub fn found(&self) -> u64 {
+        self.found
+    }
+
+    /// The weight of this state when the next target state is chosen.
+    /// `known` is the number of states the scheduler knows.
+    ///
+    /// States that few corpus entries reach are favored, as are states that were discovered
+    /// recently and states that produced new corpus entries when they were selected.
+    fn score(&self, known: usize) -> f64 {
+        let rarity = 1.0 / self.seeds.len() as f64;
+        let recency = 1.0 + self.discovered as f64 / known as f64;
+        let productivity = (self.found + 1) as f64 / (self.selected + 2) as f64;
+        rarity * recency * productivity
+    }
+}
+
+/// Metadata of the [`StateAwareScheduler`] that maps the states of the state-graph
+/// to the corpus entries that reach them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StateSchedulerMetadata {
+    states: BTreeMap<u32, ScheduledState>,
+    target: Option<u32>,
+    untraced: Vec<CorpusId>,
+    added: u64,
+    imported: usize,
+}
+
+impl StateSchedulerMetadata {
+    /// Returns what the scheduler knows about the state with the id `id`
+    pub fn state(&self, id: u32) -> Option<&ScheduledState> {
+        self.states.get(&id)
+    }
+
+    /// Returns the number of states that are reached by at least one corpus entry
+    pub fn states(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns the state that the current corpus entry was chosen for
+    pub fn target(&self) -> Option<u32> {
+        self.target
+    }
+
+    /// Returns the corpus entries that don't reach any state
+    pub fn untraced(&self) -> &[CorpusId] {
+        &self.untraced
+    }
+}
+
+/// Probability that the [`StateAwareScheduler`] picks a corpus entry that doesn't reach any state
+const UNTRACED_PROBABILITY: f64 = 0.05;
+
+/// Pick one of `scores` with a probability proportional to its score
+fn choose_target<R>(rand: &mut R, scores: &[(u32, f64)]) -> u32
+where
+    R: Rand,
+{
+    let mut target = rand.next_float() * scores.iter().map(|(_, score)| score).sum::<f64>();
+
+    for (id, score) in scores {
+        if target < *score {
+            return *id;
+        }
+
+        target -= score;
+    }
+
+    scores[scores.len() - 1].0
+}
+
+/// A corpus scheduler that decides which state of the target to fuzz next, like AFLNet does.
+///
+/// The scheduler remembers which states of the [`StateObserver`]s state-graph each corpus entry reaches.
+/// It takes them from the [`StateTraceMetadata`] of the testcase or, if there is none, from the
+/// observer in the run that added the testcase.
+/// To choose the next corpus entry it first picks a target state and then a random corpus entry
+/// that reaches that state. The target state is chosen with a probability that is higher for
+///   - rare states that few corpus entries reach
+///   - states that were discovered recently
+///   - states that were selected less often or that led to new corpus entries when they were selected
+///
+/// As long as no state is known it chooses corpus entries at random.
+/// Corpus entries that don't reach any state, e.g. because they were added without an evaluation,
+/// are still chosen with a small probability.
+/// Entries imported from other fuzzer instances don't count as found for the target state.
+/// The mapping is stored as [`StateSchedulerMetadata`] in the state.
+///
+/// # Example
+/// ```
+/// let state_observer = StateObserver::<u32>::new("state");
+/// let scheduler = StateAwareScheduler::new(&state_observer);
+/// let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+/// ```
+#[derive(Debug)]
+pub struct StateAwareScheduler<PS>
+where
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    observer_handle: Handle<StateObserver<PS>>,
+    last_run: Option<(u64, Vec<u32>)>,
+    phantom: PhantomData<PS>,
+}
+
+impl<PS> StateAwareScheduler<PS>
+where
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    /// Create a new StateAwareScheduler that uses the state-graph of `observer`
+    pub fn new(observer: &StateObserver<PS>) -> Self {
+        Self {
+            observer_handle: observer.handle(),
+            last_run: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S, PS> RemovableScheduler<I, S> for StateAwareScheduler<PS>
+where
+    S: HasMetadata,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn on_remove(&mut self, state: &mut S, id: CorpusId, _testcase: &Option<Testcase<I>>) -> Result<(), Error> {
+        if let Some(metadata) = state.metadata_map_mut().get_mut::<StateSchedulerMetadata>() {
+            for target in metadata.states.values_mut() {
+                target.seeds.retain(|seed| *seed != id);
+            }
+
+            metadata.untraced.retain(|seed| *seed != id);
+        }
+
+        Ok(())
+    }
+}
+
+impl<I, S, PS> Scheduler<I, S> for StateAwareScheduler<PS>
+where
+    I: Input,
+    S: HasCorpus<I> + HasRand + HasMetadata + HasNamedMetadata + HasImported,
+    PS: Clone + Debug + Eq + Hash + Serialize + for<'a> Deserialize<'a>,
+{
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        let current_id = *state.corpus().current();
+        state.corpus().get(id)?.borrow_mut().set_parent_id_optional(current_id);
+
+        // Testcases from other fuzzer instances are added without an evaluation
+        // but the StateFeedback gives them a trace
+        let last_run = self.last_run.take();
+        let trace = state.corpus().get(id)?.borrow().metadata_map().get::<StateTraceMetadata>().map(|trace| trace.states().to_vec());
+        let mut states = match (trace, last_run) {
+            (Some(states), _) => states,
+            (None, Some((hash, states))) if hash == generic_hash_std(&state.corpus().cloned_input_for_id(id)?) => states,
+            _ => Vec::new(),
+        };
+        states.sort_unstable();
+        states.dedup();
+
+        let metadata = state.metadata_or_insert_with(StateSchedulerMetadata::default);
+        // Whether this was found locally is only known once libafl counts the imports, see next()
+        metadata.added += 1;
+
+        if states.is_empty() {
+            metadata.untraced.push(id);
+        }
+
+        for state in states {
+            let discovered = metadata.states.len() as u64;
+            metadata
+                .states
+                .entry(state)
+                .or_insert_with(|| ScheduledState {
+                    discovered,
+                    ..ScheduledState::default()
+                })
+                .seeds
+                .push(id);
+        }
+
+        Ok(())
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        let state_observer = observers.get(&self.observer_handle).ok_or_else(|| Error::key_not_found(format!("StateObserver {} not found", self.observer_handle.name())))?;
+
+        self.last_run = Some((generic_hash_std(input), state_observer.local_path(state)?));
+        Ok(())
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        if state.corpus().count() == 0 {
+            return Err(Error::empty("No entries in corpus".to_string()));
+        }
+
+        let imported = *state.imported();
+        let metadata = state.metadata_or_insert_with(StateSchedulerMetadata::default);
+
+        // Credit the previous target state with the entries that were added since, minus the imported ones
+        let found = metadata.added.saturating_sub(imported.saturating_sub(metadata.imported) as u64);
+        if let Some(target) = metadata.target.and_then(|target| metadata.states.get_mut(&target)) {
+            target.found += found;
+        }
+        metadata.added = 0;
+        metadata.imported = imported;
+
+        let known = metadata.states.len();
+        let scores: Vec<(u32, f64)> = metadata.states.iter().filter(|(_, target)| !target.seeds.is_empty()).map(|(id, target)| (*id, target.score(known))).collect();
+
+        let untraced = metadata.untraced.len();
+
+        let id = if scores.is_empty() {
+            state.metadata_mut::<StateSchedulerMetadata>()?.target = None;
+            random_corpus_id!(state.corpus(), state.rand_mut())
+        } else if untraced > 0 && state.rand_mut().coinflip(UNTRACED_PROBABILITY) {
+            let seed = state.rand_mut().below(NonZero::new(untraced).unwrap());
+            let metadata = state.metadata_mut::<StateSchedulerMetadata>()?;
+            metadata.target = None;
+            metadata.untraced[seed]
+        } else {
+            let target = choose_target(state.rand_mut(), &scores);
+
+            let metadata = state.metadata_mut::<StateSchedulerMetadata>()?;
+            metadata.target = Some(target);
+            let target_state = metadata.states.get_mut(&target).unwrap();
+            target_state.selected += 1;
+            let seeds = target_state.seeds.len();
+
+            let seed = state.rand_mut().below(NonZero::new(seeds).unwrap());
+            state.metadata::<StateSchedulerMetadata>()?.states[&target].seeds[seed]
+        };
+
+        self.set_current_scheduled(state, Some(id))?;
+        Ok(id)
+    }
+
+    fn set_current_scheduled(&mut self, state: &mut S, next_id: Option<CorpusId>) -> Result<(), Error> {
+        *state.corpus_mut().current_mut() = next_id;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PacketBytesInput, PacketDeleteMutator, PacketDuplicateMutator};
+    use libafl::{
+        corpus::InMemoryCorpus,
+        executors::ExitKind,
+        inputs::BytesInput,
+        observers::Observer,
+        state::{HasMaxSize, HasRand, StdState},
+    };
+    use libafl_bolts::{rands::StdRand, serdeany::SerdeAnyMap, tuples::tuple_list};
+
+    struct TestState {
+        rand: StdRand,
+        max_size: usize,
+        metadata: SerdeAnyMap,
+    }
+    impl TestState {
+        fn new() -> Self {
+            Self {
+                rand: StdRand::with_seed(0),
+                max_size: 0,
+                metadata: SerdeAnyMap::new(),
+            }
+        }
+    }
+    impl HasRand for TestState {
+        type Rand = StdRand;
+
+        fn rand(&self) -> &StdRand {
+            &self.rand
+        }
+
+        fn rand_mut(&mut self) -> &mut StdRand {
+            &mut self.rand
+        }
+    }
+    impl HasMaxSize for TestState {
+        fn max_size(&self) -> usize {
+            self.max_size
+        }
+
+        fn set_max_size(&mut self, max_size: usize) {
+            self.max_size = max_size;
+        }
+    }
+    impl HasMetadata for TestState {
+        fn metadata_map(&self) -> &SerdeAnyMap {
+            &self.metadata
+        }
+
+        fn metadata_map_mut(&mut self) -> &mut SerdeAnyMap {
+            &mut self.metadata
+        }
+    }
+
+    #[test]
+    fn test_potency_decay() {
+        let mut potency = Potency::default();
+        assert_eq!(potency.score(), 0.5);
+
+        for i in 0..POTENCY_DECAY_WINDOW {
+            potency.record(i % 4 == 0);
+        }
+
+        assert_eq!(potency.trials(), POTENCY_DECAY_WINDOW / 2);
+        assert_eq!(potency.hits(), POTENCY_DECAY_WINDOW / 8);
+    }
+
+    #[test]
+    fn test_learn_mutations() {
+        let mut state = TestState::new();
+        let mut scheduler = PotencyMutationScheduler::<PacketBytesInput, _, _, BytesInput>::new(tuple_list!(PacketDeleteMutator::new(1), PacketDuplicateMutator::new(64)));
+        let input = PacketBytesInput::from(vec![b"A".to_vec(), b"B".to_vec(), b"C".to_vec()]);
+
+        // only duplications produce new corpus entries
+        for _ in 0..256 {
+            let mut mutated = input.clone();
+            assert_eq!(scheduler.mutate(&mut state, &mut mutated).unwrap(), MutationResult::Mutated);
+            let hit = mutated.len() > input.len();
+            scheduler.post_exec(&mut state, hit.then_some(CorpusId(0))).unwrap();
+        }
+
+        let metadata = state.metadata_map().get::<PotencyMetadata>().unwrap();
+        assert!(metadata.mutation(1).score() > 0.9);
+        assert!(metadata.mutation(0).score() < 0.1);
+        assert!(metadata.mutation(1).trials() > metadata.mutation(0).trials());
+        assert_eq!(metadata.packet_kind(0).trials(), 256);
+
+        // without a new corpus entry nothing gets recorded twice
+        scheduler.post_exec(&mut state, None).unwrap();
+        assert_eq!(state.metadata_map().get::<PotencyMetadata>().unwrap().packet_kind(0).trials(), 256);
+    }
+
+    #[test]
+    fn test_all_mutators_skip() {
+        let mut state = TestState::new();
+        let mut scheduler = PotencyMutationScheduler::<PacketBytesInput, _, _, BytesInput>::new(tuple_list!(PacketDeleteMutator::new(2), PacketDuplicateMutator::new(2)));
+        let mut input = PacketBytesInput::from(vec![b"A".to_vec(), b"B".to_vec()]);
+
+        // the input is at the lower and upper bound of the mutators
+        assert_eq!(scheduler.mutate(&mut state, &mut input).unwrap(), MutationResult::Skipped);
+        assert_eq!(input.len(), 2);
+    }
+
+    #[test]
+    fn test_learn_packets() {
+        let mut state = TestState::new();
+        let mut scheduler = PotencyMutationScheduler::<PacketBytesInput, _, _, BytesInput>::new(tuple_list!(PacketDeleteMutator::new(1)));
+        let input = PacketBytesInput::from(vec![b"A".to_vec(), b"B".to_vec(), b"C".to_vec(), b"D".to_vec()]);
+        let mut deleted_first = 0;
+
+        // only deleting the first packet produces new corpus entries
+        for _ in 0..512 {
+            let mut mutated = input.clone();
+            scheduler.mutate(&mut state, &mut mutated).unwrap();
+            let hit = mutated.packets()[0] != input.packets()[0];
+            scheduler.post_exec(&mut state, hit.then_some(CorpusId(0))).unwrap();
+            deleted_first += hit as usize;
+        }
+
+        let metadata = state.metadata_map().get::<PotencyMetadata>().unwrap();
+        assert!(metadata.packet_index(0).score() > metadata.packet_index(1).score());
+        assert!(deleted_first > 512 / 2);
+    }
+
+    #[test]
+    fn test_state_aware_scheduler() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<PacketBytesInput>::new(), InMemoryCorpus::new(), &mut (), &mut ()).unwrap();
+        let mut observers = tuple_list!(StateObserver::<u32>::new("state"));
+        let mut scheduler = StateAwareScheduler::new(&observers.0);
+
+        // only the second seed reaches state 2
+        for (seed, states) in [(b"A", &[0, 1][..]), (b"B", &[0, 1, 2]), (b"C", &[0])] {
+            let input = PacketBytesInput::from(vec![seed.to_vec()]);
+            observers.0.pre_exec(&mut state, &input).unwrap();
+            for s in states {
+                observers.0.record(s);
+            }
+            observers.0.post_exec(&mut state, &input, &ExitKind::Ok).unwrap();
+
+            scheduler.on_evaluation(&mut state, &input, &observers).unwrap();
+            let id = state.corpus_mut().add(Testcase::new(input)).unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+        }
+
+        // a testcase without an evaluation doesn't reach any state
+        let id = state.corpus_mut().add(Testcase::new(PacketBytesInput::from(vec![b"D".to_vec()]))).unwrap();
+        scheduler.on_add(&mut state, id).unwrap();
+
+        // unless it has a trace
+        let mut testcase = Testcase::new(PacketBytesInput::from(vec![b"E".to_vec()]));
+        testcase.add_metadata(StateTraceMetadata::new(vec![2, 0, 2], vec![0, 1, 2]).unwrap());
+        let id = state.corpus_mut().add(testcase).unwrap();
+        scheduler.on_add(&mut state, id).unwrap();
+
+        let metadata = state.metadata::<StateSchedulerMetadata>().unwrap();
+        assert_eq!(metadata.states(), 3);
+        assert_eq!(metadata.state(0).unwrap().seeds(), &[CorpusId(0), CorpusId(1), CorpusId(2), CorpusId(4)]);
+        assert_eq!(metadata.state(2).unwrap().seeds(), &[CorpusId(1), CorpusId(4)]);
+
+        let mut picked = [0; 5];
+        for _ in 0..1000 {
+            picked[scheduler.next(&mut state).unwrap().0] += 1;
+        }
+        // the entry without a trace is only picked as a fallback
+        assert!(picked[3] > 0 && picked[3] < picked[0]);
+        assert!(picked[1] > picked[0] && picked[1] > picked[2]);
+        assert!(picked[4] > picked[0] && picked[4] > picked[2]);
+
+        let metadata = state.metadata::<StateSchedulerMetadata>().unwrap();
+        assert_eq!(metadata.untraced(), &[CorpusId(3)]);
+        assert_eq!((0..3).map(|id| metadata.state(id).unwrap().selected()).sum::<u64>(), 1000 - picked[3]);
+
+        RemovableScheduler::<PacketBytesInput, _>::on_remove(&mut scheduler, &mut state, CorpusId(1), &None).unwrap();
+        assert_eq!(state.metadata::<StateSchedulerMetadata>().unwrap().state(2).unwrap().seeds(), &[CorpusId(4)]);
+        RemovableScheduler::<PacketBytesInput, _>::on_remove(&mut scheduler, &mut state, CorpusId(3), &None).unwrap();
+        assert!(state.metadata::<StateSchedulerMetadata>().unwrap().untraced().is_empty());
+    }
+
+    #[test]
+    fn test_found_ignores_imports() {
+        let mut state = StdState::new(StdRand::with_seed(0), InMemoryCorpus::<PacketBytesInput>::new(), InMemoryCorpus::new(), &mut (), &mut ()).unwrap();
+        let observer = StateObserver::<u32>::new("state");
+        let mut scheduler = StateAwareScheduler::new(&observer);
+
+        // the first entry is added before anything is scheduled, the second and fourth are imports
+        for (i, imported) in [false, true, false, true].into_iter().enumerate() {
+            let mut testcase = Testcase::new(PacketBytesInput::from(vec![b"A".to_vec()]));
+            testcase.add_metadata(StateTraceMetadata::new(vec![0], vec![0]).unwrap());
+            let id = state.corpus_mut().add(testcase).unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+
+            // libafl counts an import after adding it to the corpus
+            if imported {
+                *state.imported_mut() += 1;
+            }
+
+            if i == 0 {
+                scheduler.next(&mut state).unwrap();
+            }
+        }
+        scheduler.next(&mut state).unwrap();
+
+        assert_eq!(state.metadata::<StateSchedulerMetadata>().unwrap().state(0).unwrap().found(), 1);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{
+    capture::Capture,
+    input::{Flow, FlowFilter},
+};
+use libafl::{common::HasMetadata, mutators::Tokens, Error};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// Shortest token that gets extracted from a pcap
+const MIN_TOKEN_LEN: usize = 2;
+/// Longest token that gets extracted from a pcap
+const MAX_TOKEN_LEN: usize = 32;
+
+/// Bytes that separate tokens in text protocols
+pub(crate) fn is_delimiter(byte: u8) -> bool {
+    !byte.is_ascii_graphic() || b"=,;:&?\"'()<>[]{}".contains(&byte)
+}
+
+/// Split application data into words that are likely keywords of a text protocol
+fn extract_tokens(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    data.split(|byte| is_delimiter(*byte)).filter(|word| (MIN_TOKEN_LEN..=MAX_TOKEN_LEN).contains(&word.len()) && !word.iter().all(u8::is_ascii_digit))
+}
+
+/// Add all tokens of `tokens` to the [`Tokens`](libafl::mutators::Tokens) metadata of the state
+fn add_tokens<S>(state: &mut S, tokens: &[Vec<u8>]) -> usize
+where
+    S: HasMetadata,
+{
+    let metadata = state.metadata_or_insert_with(Tokens::new);
+    let before = metadata.len();
+    metadata.add_tokens(tokens);
+    metadata.len() - before
+}
+
+/// Helper function that loads an AFL-style dictionary file into the state.
+///
+/// The tokens are added to the [`Tokens`](libafl::mutators::Tokens) metadata of the state
+/// that is used by the [`PacketTokenInsertMutator`](crate::PacketTokenInsertMutator) and
+/// [`PacketTokenReplaceMutator`](crate::PacketTokenReplaceMutator).
+/// Existing tokens are kept so this can be called multiple times.
+///
+/// # Arguments
+/// - `state`: libafls state
+/// - `path`: path to the dictionary file. Every line has the form `name="value"` or `"value"`
+pub fn load_dictionary<S, P>(state: &mut S, path: P) -> Result<(), Error>
+where
+    S: HasMetadata,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let tokens = Tokens::from_file(path)?;
+    let added = add_tokens(state, tokens.tokens());
+    println!("[butterfly] Loaded {} tokens from {}", added, path.display());
+    Ok(())
+}
+
+/// Helper function that extracts tokens from the pcap files in a given directory.
+///
+/// It scans the directory for the same files as [`load_pcaps`](crate::load_pcaps),
+/// reassembles the first flow of each capture and splits all messages that the client
+/// sent at whitespace and common delimiters like `=` or `:`.
+/// Words between 2 and 32 bytes that are not plain numbers become tokens
+/// in the [`Tokens`](libafl::mutators::Tokens) metadata of the state.
+///
+/// This works well for text protocols like FTP, SMTP or HTTP where commands
+/// and header names end up in the dictionary.
+///
+/// # Arguments
+/// - `state`: libafls state
+/// - `in_dir`: path to directory with pcap files
+pub fn load_pcap_tokens<S, P>(state: &mut S, in_dir: P) -> Result<(), Error>
+where
+    S: HasMetadata,
+    P: Into<PathBuf>,
+{
+    for entry in std::fs::read_dir(in_dir.into())? {
+        let entry = entry?;
+        let path = entry.path();
+
+        let attributes = std::fs::metadata(&path);
+
+        if attributes.is_err() {
+            continue;
+        }
+
+        let attr = attributes?;
+
+        if attr.is_file() && attr.len() > 0 {
+            if path.extension() == Some(OsStr::new("pcapng")) || path.extension() == Some(OsStr::new("pcap")) {
+                let flow = match Flow::from_capture(&Capture::from_file(&path)?, &FlowFilter::First) {
+                    Ok(flow) => flow,
+                    Err(Error::Empty(..)) => continue,
+                    Err(e) => return Err(e),
+                };
+                let tokens: Vec<Vec<u8>> = flow.client_messages().flat_map(|message| extract_tokens(message.data())).map(<[u8]>::to_vec).collect();
+                let added = add_tokens(state, &tokens);
+                println!("[butterfly] Extracted {} tokens from {}", added, path.display());
+            }
+        } else if attr.is_dir() {
+            load_pcap_tokens(state, path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_tokens() {
+        let tokens: Vec<&[u8]> = extract_tokens(b"USER anonymous\r\nPORT 127,0,0,1,4,1\r\nGET /index.html HTTP/1.1\r\nContent-Length: 42\r\n").collect();
+        let expected: [&[u8]; 7] = [b"USER", b"anonymous", b"PORT", b"GET", b"/index.html", b"HTTP/1.1", b"Content-Length"];
+        assert_eq!(tokens, expected);
+    }
+}